@@ -20,9 +20,15 @@ cd "${PROJECT_DIR}"/open-coroutine-core
 "${CARGO}" test --target "${TARGET}" --no-default-features --features korosensei
 "${CARGO}" test --target "${TARGET}" --no-default-features --features korosensei --release
 
+"${CARGO}" test --target "${TARGET}" --no-default-features --features boost
+"${CARGO}" test --target "${TARGET}" --no-default-features --features boost --release
+
 "${CARGO}" test --target "${TARGET}" --no-default-features --features net
 "${CARGO}" test --target "${TARGET}" --no-default-features --features net --release
 
+"${CARGO}" test --target "${TARGET}" --no-default-features --features net,boost
+"${CARGO}" test --target "${TARGET}" --no-default-features --features net,boost --release
+
 if [ "${TARGET}" != "riscv64gc-unknown-linux-gnu" ]; then
     "${CARGO}" test --target "${TARGET}" --no-default-features --features preemptive-schedule
 fi
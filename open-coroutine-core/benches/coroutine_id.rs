@@ -0,0 +1,125 @@
+//! Counts the heap allocations of the syscall suspension path, which used to
+//! leak the coroutine name every time a coroutine entered the syscall table.
+//!
+//! The bookkeeping of the syscall table is measured both ways, keyed by the
+//! leaked `uuid` names as before and keyed by the integer ids as now, then the
+//! whole path is measured through the scheduler.
+//!
+//! Run without the `logs` feature, otherwise the logs dominate the allocations:
+//! `cargo bench -p open-coroutine-core --bench coroutine_id --no-default-features --features korosensei`.
+use dashmap::DashMap;
+use open_coroutine_core::common::Current;
+use open_coroutine_core::constants::{Syscall, SyscallState};
+use open_coroutine_core::coroutine::suspender::SimpleSuspender;
+use open_coroutine_core::coroutine::StateMachine;
+use open_coroutine_core::scheduler::{
+    SchedulableCoroutine, SchedulableSuspender, Scheduler, SchedulerImpl,
+};
+use open_coroutine_timer::TimerList;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static RETAINED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        _ = ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        _ = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        _ = RETAINED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        _ = RETAINED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const COROUTINES: usize = 16;
+const SYSCALLS: usize = 10_000;
+
+fn measure(label: &str, f: impl FnOnce() -> std::io::Result<()>) -> std::io::Result<()> {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+    let retained = RETAINED.load(Ordering::Relaxed);
+    let start = Instant::now();
+    f()?;
+    let cost = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let allocated = ALLOCATED.load(Ordering::Relaxed) - allocated;
+    let retained = RETAINED.load(Ordering::Relaxed).saturating_sub(retained);
+    let suspensions = COROUTINES * SYSCALLS;
+    println!(
+        "{label}, {suspensions} syscall suspensions: {allocations} allocations ({:.2}/suspension), \
+         {allocated} bytes ({:.2}/suspension), {retained} bytes retained, {:?}/suspension",
+        allocations as f64 / suspensions as f64,
+        allocated as f64 / suspensions as f64,
+        cost / u32::try_from(suspensions).expect("too many suspensions"),
+    );
+    Ok(())
+}
+
+/// Enter and leave the syscall table like the scheduler, `key` makes the key of a coroutine.
+fn syscall_table<K: Copy + Eq + std::hash::Hash>(
+    keys: &[K],
+    key: impl Fn(K) -> K,
+) -> std::io::Result<()> {
+    let table = DashMap::new();
+    let mut suspend = TimerList::default();
+    for _ in 0..SYSCALLS {
+        let timestamp = open_coroutine_timer::now();
+        for (value, k) in keys.iter().enumerate() {
+            let k = key(*k);
+            suspend.insert(timestamp, k);
+            _ = table.insert(k, value);
+        }
+        while let Some((_, mut entry)) = suspend.pop_front() {
+            while let Some(k) = entry.pop_front() {
+                _ = table.remove(&k);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> std::io::Result<()> {
+    let names: Vec<&'static str> = (0..COROUTINES)
+        .map(|_| &*format!("open-coroutine-scheduler|{}", uuid::Uuid::new_v4()).leak())
+        .collect();
+    measure("before: keyed by the leaked names", || {
+        syscall_table(&names, |name| &*Box::leak(Box::from(name)))
+    })?;
+    let ids: Vec<usize> = (0..COROUTINES).collect();
+    measure("after: keyed by the ids", || syscall_table(&ids, |id| id))?;
+
+    let mut scheduler = SchedulerImpl::default();
+    for _ in 0..COROUTINES {
+        scheduler.submit(
+            |_, ()| {
+                let coroutine = SchedulableCoroutine::current().expect("not in coroutine");
+                for _ in 0..SYSCALLS {
+                    let timestamp = open_coroutine_timer::now();
+                    coroutine
+                        .syscall((), Syscall::nanosleep, SyscallState::Suspend(timestamp))
+                        .expect("change to syscall state failed !");
+                    SchedulableSuspender::current()
+                        .expect("no suspender")
+                        .suspend();
+                    coroutine
+                        .syscall_resume()
+                        .expect("change to running state failed !");
+                }
+            },
+            None,
+        )?;
+    }
+    measure("scheduler", || scheduler.try_schedule())
+}
@@ -1,3 +1,4 @@
+use crate::constants::{Priority, PRIORITY_AGING};
 use crate::coroutine::suspender::SimpleDelaySuspender;
 use crate::scheduler::SchedulableSuspender;
 use std::fmt::Debug;
@@ -26,6 +27,10 @@ pub fn page_size() -> usize {
     ret
 }
 
+/// Tests changing or depending on the global settings should hold this lock.
+#[cfg(test)]
+pub(crate) static SETTINGS_LOCK: Mutex<()> = Mutex::new(());
+
 /// Give the object a name.
 pub trait Named {
     /// Get the name of this object.
@@ -118,6 +123,47 @@ impl Blocker for CondvarBlocker {
     }
 }
 
+/// Choose which priority to serve next, the highest non-empty priority
+/// is preferred, but the lower ones will be aged and picked after being
+/// passed over `PRIORITY_AGING` times.
+///
+/// With `SchedulePolicy::Deadline`, the ones without deadline are aged the
+/// same way, so they still run when the deadlines keep coming.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub(crate) struct Aging([AtomicUsize; 3], AtomicUsize);
+
+impl Aging {
+    /// Whether pick the one with the earliest deadline, `others_empty` means
+    /// there is nothing without deadline.
+    pub(crate) fn pick_deadline(&self, others_empty: bool) -> bool {
+        if others_empty {
+            self.1.store(0, Ordering::Release);
+            return true;
+        }
+        if self.1.fetch_add(1, Ordering::AcqRel) + 1 >= PRIORITY_AGING {
+            self.1.store(0, Ordering::Release);
+            return false;
+        }
+        true
+    }
+
+    pub(crate) fn pick(&self, is_empty: impl Fn(Priority) -> bool) -> Option<Priority> {
+        let mut picked = None;
+        for (priority, age) in Priority::ALL.into_iter().zip(&self.0) {
+            if is_empty(priority) {
+                age.store(0, Ordering::Release);
+                continue;
+            }
+            if picked.is_none() || age.fetch_add(1, Ordering::AcqRel) + 1 >= PRIORITY_AGING {
+                age.store(0, Ordering::Release);
+                picked = Some(priority);
+            }
+        }
+        picked
+    }
+}
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "net")] {
         use crate::net::event_loop::{EventLoop, EventLoopImpl};
@@ -198,4 +244,34 @@ mod tests {
             crate::error!("condvar_blocker cost {cost:?}");
         }
     }
+
+    #[test]
+    fn test_aging() {
+        let aging = Aging::default();
+        assert_eq!(None, aging.pick(|_| true));
+        let mut low = 0;
+        for _ in 0..PRIORITY_AGING * 2 {
+            if Some(Priority::Low) == aging.pick(|priority| priority == Priority::Normal) {
+                low += 1;
+            }
+        }
+        assert_eq!(2, low);
+        assert_eq!(
+            Some(Priority::Normal),
+            aging.pick(|priority| priority != Priority::Normal)
+        );
+    }
+
+    #[test]
+    fn test_deadline_aging() {
+        let aging = Aging::default();
+        assert!(aging.pick_deadline(true));
+        let mut others = 0;
+        for _ in 0..PRIORITY_AGING * 2 {
+            if !aging.pick_deadline(false) {
+                others += 1;
+            }
+        }
+        assert_eq!(2, others);
+    }
 }
@@ -1,3 +1,4 @@
+use crate::coroutine::error::CoroutineError;
 use std::fmt::{Debug, Display, Formatter};
 
 /// min stack size for backtrace
@@ -9,7 +10,7 @@ pub const MONITOR_CPU: usize = 0;
 /// Enums used to describe syscall
 #[allow(non_camel_case_types, missing_docs)]
 #[repr(C)]
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Syscall {
     sleep,
     usleep,
@@ -63,6 +64,8 @@ pub enum Syscall {
     renameat2,
     mkdirat,
     openat,
+    /// Not a real syscall, the coroutine is parked in `future::block_on`.
+    park,
 }
 
 impl Display for Syscall {
@@ -118,12 +121,8 @@ impl Display for SyscallState {
 
 /// Enums used to describe coroutine state
 #[repr(C)]
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum CoroutineState<Y, R>
-where
-    Y: Copy + Eq + PartialEq,
-    R: Copy + Eq + PartialEq,
-{
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CoroutineState<Y, R> {
     ///The coroutine is created.
     Created,
     ///The coroutine is ready to run.
@@ -136,14 +135,16 @@ where
     SystemCall(Y, Syscall, SyscallState),
     /// The coroutine completed with a return value.
     Complete(R),
-    /// The coroutine completed with a error message.
-    Error(&'static str),
+    /// The coroutine completed with an error.
+    Error(CoroutineError),
+    /// The coroutine was cancelled, and its stack has been unwound.
+    Cancelled,
 }
 
 impl<Y, R> Display for CoroutineState<Y, R>
 where
-    Y: Copy + Eq + PartialEq + Debug,
-    R: Copy + Eq + PartialEq + Debug,
+    Y: Debug,
+    R: Debug,
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         Debug::fmt(self, f)
@@ -169,3 +170,52 @@ impl Display for PoolState {
         Debug::fmt(self, f)
     }
 }
+
+/// Enums used to describe coroutine priority
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum Priority {
+    ///Latency-critical work, always runs before the others.
+    High,
+    ///The default priority.
+    #[default]
+    Normal,
+    ///Bulk work, runs when there is nothing else to do.
+    Low,
+}
+
+impl Priority {
+    /// All priorities, from the highest to the lowest.
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+/// Enums used to describe how the ready coroutines and tasks are ordered
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum SchedulePolicy {
+    ///Ordered by priority, first in first out within the same priority.
+    #[default]
+    Priority,
+    ///Earliest deadline first, the ones without deadline run after them by priority,
+    ///but they are aged by `PRIORITY_AGING` so they don't starve.
+    Deadline,
+}
+
+impl Display for SchedulePolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+/// The scheduling group of the tasks submitted without a group.
+pub const DEFAULT_GROUP: &str = "default";
+
+/// A ready lower priority coroutine or task will be picked after being
+/// passed over this many times, so the low priority work still makes progress.
+pub const PRIORITY_AGING: usize = 16;
@@ -1 +1,481 @@
-//todo
+use context::stack::Stack;
+use context::{Context, Transfer};
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::ops::Deref;
+use std::panic::AssertUnwindSafe;
+
+// 协程让出时传递给调用方的数据
+const YIELD: usize = 0;
+
+// 协程结束时传递给调用方的数据
+const DONE: usize = 1;
+
+/// The same as `corosensei::CoroutineResult`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum CoroutineResult<Yield, Return> {
+    Yield(Yield),
+    Return(Return),
+}
+
+// 协程被强制展开时抛出的panic
+struct ForcedUnwind;
+
+/// The same as `corosensei::Yielder`, used to suspend the coroutine.
+pub(crate) struct Yielder<Param, Yield> {
+    //the context which resumed the coroutine
+    caller: Cell<Option<Context>>,
+    param: Cell<Option<Param>>,
+    yielded: Cell<Option<Yield>>,
+    unwind: Cell<bool>,
+}
+
+impl<Param, Yield> Yielder<Param, Yield> {
+    /// Suspends the execution of the coroutine and switches back to the caller.
+    pub(crate) fn suspend(&self, val: Yield) -> Param {
+        self.yielded.set(Some(val));
+        let caller = self.caller.take().expect("no caller to switch back");
+        let transfer = unsafe { caller.resume(YIELD) };
+        self.caller.set(Some(transfer.context));
+        if self.unwind.get() {
+            std::panic::resume_unwind(Box::new(ForcedUnwind));
+        }
+        self.param.take().expect("no param to resume")
+    }
+}
+
+type Func<'c, Param, Yield, Return> = Box<dyn FnOnce(&Yielder<Param, Yield>, Param) -> Return + 'c>;
+
+// 协程栈上的代码通过它和调用方交换数据，分配在堆上保证地址不变
+struct Shared<'c, Param, Yield, Return> {
+    yielder: Yielder<Param, Yield>,
+    func: Cell<Option<Func<'c, Param, Yield, Return>>>,
+    result: Cell<Option<std::thread::Result<Return>>>,
+}
+
+impl<Param, Yield, Return> Shared<'_, Param, Yield, Return> {
+    /// Finish the coroutine with `result` and never come back.
+    unsafe fn finish(&self, result: std::thread::Result<Return>) -> ! {
+        self.result.set(Some(result));
+        let caller = self
+            .yielder
+            .caller
+            .take()
+            .expect("no caller to switch back");
+        _ = caller.resume(DONE);
+        unreachable!("a finished coroutine should never be resumed");
+    }
+}
+
+/// The same as `corosensei::ScopedCoroutine`, but built on top of `context`.
+pub(crate) struct ScopedCoroutine<'c, Param, Yield, Return, S> {
+    #[allow(box_pointers)]
+    shared: Box<Shared<'c, Param, Yield, Return>>,
+    context: Option<Context>,
+    started: bool,
+    done: bool,
+    stack: S,
+    // the same as `corosensei::ScopedCoroutine`
+    #[allow(clippy::type_complexity)]
+    marker: PhantomData<&'c fn(Param) -> CoroutineResult<Yield, Return>>,
+    marker2: PhantomData<*mut ()>,
+}
+
+extern "C" fn coroutine_entry<Param, Yield, Return>(transfer: Transfer) -> ! {
+    unsafe {
+        let shared = &*(transfer.data as *const Shared<'_, Param, Yield, Return>);
+        shared.yielder.caller.set(Some(transfer.context));
+        let result = {
+            let func = shared.func.take().expect("coroutine already started");
+            let param = shared.yielder.param.take().expect("no param to start");
+            // all values created by the coroutine are dropped here,
+            // and the panic can't unwind across the `extern "C"` function
+            std::panic::catch_unwind(AssertUnwindSafe(|| func(&shared.yielder, param)))
+        };
+        shared.finish(result)
+    }
+}
+
+#[allow(box_pointers)]
+impl<'c, Param, Yield, Return, S> ScopedCoroutine<'c, Param, Yield, Return, S>
+where
+    S: Deref<Target = Stack>,
+{
+    pub(crate) fn with_stack<F>(stack: S, f: F) -> Self
+    where
+        F: FnOnce(&Yielder<Param, Yield>, Param) -> Return + 'c,
+    {
+        let shared = Box::new(Shared {
+            yielder: Yielder {
+                caller: Cell::new(None),
+                param: Cell::new(None),
+                yielded: Cell::new(None),
+                unwind: Cell::new(false),
+            },
+            func: Cell::new(Some(Box::new(f))),
+            result: Cell::new(None),
+        });
+        let context = unsafe { Context::new(&stack, coroutine_entry::<Param, Yield, Return>) };
+        ScopedCoroutine {
+            shared,
+            context: Some(context),
+            started: false,
+            done: false,
+            stack,
+            marker: PhantomData,
+            marker2: PhantomData,
+        }
+    }
+
+    /// Resumes the execution of this coroutine.
+    ///
+    /// # Panics
+    /// if the coroutine already finished, or the coroutine panicked.
+    pub(crate) fn resume(&mut self, val: Param) -> CoroutineResult<Yield, Return> {
+        assert!(!self.done, "cannot resume a finished coroutine");
+        self.shared.yielder.param.set(Some(val));
+        let context = self.context.take().expect("coroutine context lost");
+        let shared = std::ptr::addr_of!(*self.shared);
+        let transfer = unsafe { context.resume(shared as usize) };
+        self.started = true;
+        if DONE == transfer.data {
+            self.done = true;
+            return match self.shared.result.take().expect("no result to return") {
+                Ok(result) => CoroutineResult::Return(result),
+                Err(e) => std::panic::resume_unwind(e),
+            };
+        }
+        self.context = Some(transfer.context);
+        CoroutineResult::Yield(
+            self.shared
+                .yielder
+                .yielded
+                .take()
+                .expect("no value to yield"),
+        )
+    }
+
+    pub(crate) fn started(&self) -> bool {
+        self.started
+    }
+
+    pub(crate) fn done(&self) -> bool {
+        self.done
+    }
+
+    /// Unwinds the coroutine stack from where it suspended, so all values on it
+    /// are dropped, then marks the coroutine as having completed.
+    ///
+    /// # Panics
+    /// if the coroutine catches the unwinding without rethrowing it.
+    pub(crate) fn force_unwind(&mut self) {
+        if self.done {
+            return;
+        }
+        self.done = true;
+        if !self.started {
+            // 协程还没开始执行，只需要释放闭包
+            drop(self.shared.func.take());
+            self.context = None;
+            return;
+        }
+        self.shared.yielder.unwind.set(true);
+        let context = self.context.take().expect("coroutine context lost");
+        let shared = std::ptr::addr_of!(*self.shared);
+        let transfer = unsafe { context.resume(shared as usize) };
+        if DONE != transfer.data {
+            self.context = Some(transfer.context);
+            self.done = false;
+            panic!("the ForcedUnwind panic was caught and not rethrown");
+        }
+        match self.shared.result.take().expect("no result to return") {
+            Ok(_) => panic!("the ForcedUnwind panic was caught and not rethrown"),
+            Err(e) => {
+                if !e.is::<ForcedUnwind>() {
+                    std::panic::resume_unwind(e);
+                }
+            }
+        }
+    }
+
+    /// Forcibly marks the coroutine as having completed, even if it is
+    /// currently suspended in the middle of a function.
+    ///
+    /// # Safety
+    /// The values on the coroutine stack will be leaked.
+    pub(crate) unsafe fn force_reset(&mut self) {
+        self.context = None;
+        self.done = true;
+    }
+
+    pub(crate) fn trap_handler(&self) -> CoroutineTrapHandler<Param, Yield, Return> {
+        CoroutineTrapHandler {
+            shared: std::ptr::addr_of!(*self.shared).cast(),
+            stack_limit: self.stack.bottom() as usize - crate::common::page_size(),
+            stack_base: self.stack.top() as usize,
+        }
+    }
+}
+
+/// The same as `corosensei::trap::CoroutineTrapHandler`.
+pub(crate) struct CoroutineTrapHandler<Param, Yield, Return> {
+    shared: *const Shared<'static, Param, Yield, Return>,
+    //include the guard page
+    stack_limit: usize,
+    stack_base: usize,
+}
+
+impl<Param, Yield, Return> CoroutineTrapHandler<Param, Yield, Return> {
+    /// Checks whether the given stack pointer is within the bounds of the coroutine stack.
+    pub(crate) fn stack_ptr_in_bounds(&self, stack_ptr: usize) -> bool {
+        stack_ptr >= self.stack_limit && stack_ptr < self.stack_base
+    }
+
+    /// Sets up the registers so that the interrupted coroutine will execute
+    /// `f` once the trap handler returns, and then finish with the result.
+    ///
+    /// # Safety
+    /// This function must be called in the signal handler of the trapped coroutine.
+    #[allow(clippy::cast_possible_truncation)]
+    pub(crate) unsafe fn setup_trap_handler<F>(&self, f: F) -> TrapHandlerRegs
+    where
+        F: FnOnce() -> Return + 'static,
+    {
+        // 出错的协程栈已经不可用，直接从栈顶开始重新使用
+        let mut top = self.stack_base - size_of::<F>();
+        top &= !(align_of::<F>() - 1);
+        let f_ptr = top as *mut F;
+        f_ptr.write(f);
+        let top = top & !15;
+        let entry = trap_entry::<F, Param, Yield, Return> as *const () as usize;
+        let shared = self.shared as usize;
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                // the fake return address
+                let sp = top - 8;
+                (sp as *mut usize).write(0);
+                TrapHandlerRegs {
+                    rip: entry as u64,
+                    rsp: sp as u64,
+                    rbp: 0,
+                    rdi: f_ptr as u64,
+                    rsi: shared as u64,
+                }
+            } else if #[cfg(target_arch = "x86")] {
+                // the fake return address
+                let sp = top - 4;
+                (sp as *mut usize).write(0);
+                TrapHandlerRegs {
+                    eip: entry as u32,
+                    esp: sp as u32,
+                    ebp: 0,
+                    ecx: f_ptr as u32,
+                    edx: shared as u32,
+                }
+            } else if #[cfg(target_arch = "aarch64")] {
+                TrapHandlerRegs {
+                    pc: entry as u64,
+                    sp: top as u64,
+                    x0: f_ptr as u64,
+                    x1: shared as u64,
+                    x29: 0,
+                    lr: 0,
+                }
+            } else if #[cfg(target_arch = "arm")] {
+                TrapHandlerRegs {
+                    pc: (entry & !1) as u32,
+                    r0: f_ptr as u32,
+                    r1: shared as u32,
+                    r7: 0,
+                    r11: 0,
+                    r13: top as u32,
+                    r14: 0,
+                    cpsr_thumb: entry & 1 != 0,
+                    cpsr_endian: cfg!(target_endian = "big"),
+                }
+            } else if #[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))] {
+                TrapHandlerRegs {
+                    pc: entry,
+                    ra: 0,
+                    sp: top,
+                    a0: f_ptr as usize,
+                    a1: shared,
+                    s0: 0,
+                }
+            } else if #[cfg(target_arch = "loongarch64")] {
+                TrapHandlerRegs {
+                    pc: entry as u64,
+                    sp: top as u64,
+                    a0: f_ptr as u64,
+                    a1: shared as u64,
+                    fp: 0,
+                    ra: 0,
+                }
+            } else {
+                compile_error!("Unsupported platform");
+            }
+        }
+    }
+}
+
+// 参数通过寄存器传递，和`TrapHandlerRegs`保持一致
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        extern "sysv64" fn trap_entry<F, Param, Yield, Return>(
+            f: *mut F,
+            shared: *const Shared<'_, Param, Yield, Return>,
+        ) -> !
+        where
+            F: FnOnce() -> Return,
+        {
+            unsafe { (*shared).finish(Ok(f.read()())) }
+        }
+    } else if #[cfg(target_arch = "x86")] {
+        extern "fastcall" fn trap_entry<F, Param, Yield, Return>(
+            f: *mut F,
+            shared: *const Shared<'_, Param, Yield, Return>,
+        ) -> !
+        where
+            F: FnOnce() -> Return,
+        {
+            unsafe { (*shared).finish(Ok(f.read()())) }
+        }
+    } else {
+        extern "C" fn trap_entry<F, Param, Yield, Return>(
+            f: *mut F,
+            shared: *const Shared<'_, Param, Yield, Return>,
+        ) -> !
+        where
+            F: FnOnce() -> Return,
+        {
+            unsafe { (*shared).finish(Ok(f.read()())) }
+        }
+    }
+}
+
+/// The registers to be restored by the trap handler,
+/// the same as `corosensei::trap::TrapHandlerRegs`.
+#[derive(Debug, Copy, Clone)]
+#[cfg(target_arch = "x86_64")]
+pub(crate) struct TrapHandlerRegs {
+    pub(crate) rip: u64,
+    pub(crate) rsp: u64,
+    pub(crate) rbp: u64,
+    pub(crate) rdi: u64,
+    pub(crate) rsi: u64,
+}
+
+/// The registers to be restored by the trap handler,
+/// the same as `corosensei::trap::TrapHandlerRegs`.
+#[derive(Debug, Copy, Clone)]
+#[cfg(target_arch = "x86")]
+pub(crate) struct TrapHandlerRegs {
+    pub(crate) eip: u32,
+    pub(crate) esp: u32,
+    pub(crate) ebp: u32,
+    pub(crate) ecx: u32,
+    pub(crate) edx: u32,
+}
+
+/// The registers to be restored by the trap handler,
+/// the same as `corosensei::trap::TrapHandlerRegs`.
+#[derive(Debug, Copy, Clone)]
+#[cfg(target_arch = "aarch64")]
+pub(crate) struct TrapHandlerRegs {
+    pub(crate) pc: u64,
+    pub(crate) sp: u64,
+    pub(crate) x0: u64,
+    pub(crate) x1: u64,
+    pub(crate) x29: u64,
+    pub(crate) lr: u64,
+}
+
+/// The registers to be restored by the trap handler,
+/// the same as `corosensei::trap::TrapHandlerRegs`.
+#[derive(Debug, Copy, Clone)]
+#[cfg(target_arch = "arm")]
+#[allow(clippy::struct_excessive_bools)]
+pub(crate) struct TrapHandlerRegs {
+    pub(crate) pc: u32,
+    pub(crate) r0: u32,
+    pub(crate) r1: u32,
+    pub(crate) r7: u32,
+    pub(crate) r11: u32,
+    pub(crate) r13: u32,
+    pub(crate) r14: u32,
+    pub(crate) cpsr_thumb: bool,
+    pub(crate) cpsr_endian: bool,
+}
+
+/// The registers to be restored by the trap handler,
+/// the same as `corosensei::trap::TrapHandlerRegs`.
+#[derive(Debug, Copy, Clone)]
+#[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))]
+pub(crate) struct TrapHandlerRegs {
+    pub(crate) pc: usize,
+    pub(crate) ra: usize,
+    pub(crate) sp: usize,
+    pub(crate) a0: usize,
+    pub(crate) a1: usize,
+    pub(crate) s0: usize,
+}
+
+/// The registers to be restored by the trap handler,
+/// the same as `corosensei::trap::TrapHandlerRegs`.
+#[derive(Debug, Copy, Clone)]
+#[cfg(target_arch = "loongarch64")]
+pub(crate) struct TrapHandlerRegs {
+    pub(crate) pc: u64,
+    pub(crate) sp: u64,
+    pub(crate) a0: u64,
+    pub(crate) a1: u64,
+    pub(crate) fp: u64,
+    pub(crate) ra: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coroutine::stack::PooledStack;
+
+    #[test]
+    fn test_switch() {
+        let stack = PooledStack::new(crate::constants::DEFAULT_STACK_SIZE).unwrap();
+        let mut coroutine = ScopedCoroutine::with_stack(stack, |yielder, input: i32| {
+            assert_eq!(1, input);
+            assert_eq!(3, yielder.suspend(2));
+            4
+        });
+        assert!(!coroutine.started());
+        assert_eq!(CoroutineResult::Yield(2), coroutine.resume(1));
+        assert!(coroutine.started());
+        assert!(!coroutine.done());
+        assert_eq!(CoroutineResult::Return(4), coroutine.resume(3));
+        assert!(coroutine.done());
+    }
+
+    #[test]
+    fn test_force_unwind() {
+        struct Guard<'a>(&'a Cell<bool>);
+
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                self.0.set(true);
+            }
+        }
+
+        let dropped = Cell::new(false);
+        let stack = PooledStack::new(crate::constants::DEFAULT_STACK_SIZE).unwrap();
+        let mut coroutine = ScopedCoroutine::with_stack(stack, |yielder, ()| {
+            let _guard = Guard(&dropped);
+            yielder.suspend(());
+            unreachable!("should never execute to here");
+        });
+        assert_eq!(CoroutineResult::Yield(()), coroutine.resume(()));
+        assert!(!dropped.get());
+        coroutine.force_unwind();
+        assert!(dropped.get());
+        assert!(coroutine.done());
+    }
+}
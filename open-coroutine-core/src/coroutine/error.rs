@@ -0,0 +1,173 @@
+use std::any::Any;
+use std::borrow::Cow;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Enums used to describe the trap which interrupts a coroutine.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TrapKind {
+    ///`SIGSEGV` on unix, `EXCEPTION_ACCESS_VIOLATION` on windows.
+    InvalidMemoryReference,
+    ///`SIGBUS` on unix.
+    BusError,
+}
+
+impl Display for TrapKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrapKind::InvalidMemoryReference => write!(f, "invalid memory reference"),
+            TrapKind::BusError => write!(f, "bus error"),
+        }
+    }
+}
+
+/// The reason why a coroutine or a task finished with error.
+#[derive(Clone)]
+pub enum CoroutineError {
+    /// Panicked with a `&'static str` or a formatted `String`.
+    Panic(Cow<'static, str>),
+    /// Panicked with other payload, such as `std::panic::panic_any(42)`.
+    Payload(Arc<Mutex<Box<dyn Any + Send>>>),
+    /// Overflowed the coroutine stack, try to increase the `stack_size`.
+    StackOverflow {
+        /// The name of the coroutine.
+        name: String,
+        /// The configured stack size of the coroutine.
+        stack_size: usize,
+    },
+    /// Interrupted by a trap other than stack overflow.
+    Trap {
+        /// The kind of the trap.
+        kind: TrapKind,
+        /// The signal number on unix, or the exception code on windows.
+        signum: i32,
+        /// The faulting address.
+        address: usize,
+    },
+}
+
+impl CoroutineError {
+    /// Get the panic payload if it's neither `&'static str` nor `String`.
+    ///
+    /// # Panics
+    /// if the payload lock is poisoned.
+    #[must_use]
+    pub fn payload(&self) -> Option<MutexGuard<'_, Box<dyn Any + Send>>> {
+        match self {
+            CoroutineError::Payload(payload) => Some(payload.lock().unwrap()),
+            _ => None,
+        }
+    }
+}
+
+impl From<Box<dyn Any + Send>> for CoroutineError {
+    fn from(payload: Box<dyn Any + Send>) -> Self {
+        match payload.downcast::<&'static str>() {
+            Ok(message) => CoroutineError::Panic(Cow::Borrowed(*message)),
+            Err(payload) => match payload.downcast::<String>() {
+                Ok(message) => CoroutineError::Panic(Cow::Owned(*message)),
+                Err(payload) => CoroutineError::Payload(Arc::new(Mutex::new(payload))),
+            },
+        }
+    }
+}
+
+impl From<&'static str> for CoroutineError {
+    fn from(message: &'static str) -> Self {
+        CoroutineError::Panic(Cow::Borrowed(message))
+    }
+}
+
+impl Display for CoroutineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoroutineError::Panic(message) => write!(f, "{message}"),
+            CoroutineError::Payload(_) => write!(f, "Box<dyn Any>"),
+            CoroutineError::StackOverflow { name, stack_size } => write!(
+                f,
+                "coroutine:{name} stack overflow, stack_size:{stack_size}"
+            ),
+            CoroutineError::Trap {
+                kind,
+                signum,
+                address,
+            } => write!(f, "{kind}, signum:{signum}, address:{address:#x}"),
+        }
+    }
+}
+
+impl Debug for CoroutineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoroutineError::Panic(message) => f.debug_tuple("Panic").field(message).finish(),
+            CoroutineError::Payload(_) => f.debug_tuple("Payload").finish_non_exhaustive(),
+            CoroutineError::StackOverflow { name, stack_size } => f
+                .debug_struct("StackOverflow")
+                .field("name", name)
+                .field("stack_size", stack_size)
+                .finish(),
+            CoroutineError::Trap {
+                kind,
+                signum,
+                address,
+            } => f
+                .debug_struct("Trap")
+                .field("kind", kind)
+                .field("signum", signum)
+                .field("address", address)
+                .finish(),
+        }
+    }
+}
+
+impl Eq for CoroutineError {}
+
+impl PartialEq for CoroutineError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (CoroutineError::Panic(a), CoroutineError::Panic(b)) => a.eq(b),
+            (CoroutineError::Payload(a), CoroutineError::Payload(b)) => Arc::ptr_eq(a, b),
+            (
+                CoroutineError::StackOverflow { name, stack_size },
+                CoroutineError::StackOverflow {
+                    name: other_name,
+                    stack_size: other_stack_size,
+                },
+            ) => name.eq(other_name) && stack_size.eq(other_stack_size),
+            (
+                CoroutineError::Trap {
+                    kind,
+                    signum,
+                    address,
+                },
+                CoroutineError::Trap {
+                    kind: other_kind,
+                    signum: other_signum,
+                    address: other_address,
+                },
+            ) => kind.eq(other_kind) && signum.eq(other_signum) && address.eq(other_address),
+            _ => false,
+        }
+    }
+}
+
+impl std::error::Error for CoroutineError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_payload() {
+        let error =
+            CoroutineError::from(std::panic::catch_unwind(|| panic!("static")).unwrap_err());
+        assert_eq!(CoroutineError::from("static"), error);
+        let error = CoroutineError::from(std::panic::catch_unwind(|| panic!("{}", 1)).unwrap_err());
+        assert_eq!("1", error.to_string());
+        let error = CoroutineError::from(
+            std::panic::catch_unwind(|| std::panic::panic_any(1)).unwrap_err(),
+        );
+        assert_eq!(Some(&1), error.payload().unwrap().downcast_ref::<i32>());
+    }
+}
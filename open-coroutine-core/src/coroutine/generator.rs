@@ -0,0 +1,208 @@
+use crate::constants::{CoroutineState, DEFAULT_STACK_SIZE};
+use crate::coroutine::error::CoroutineError;
+use crate::coroutine::suspender::Suspender;
+use crate::coroutine::{Coroutine, CoroutineImpl, SimpleCoroutine};
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::iter::FusedIterator;
+use std::panic::UnwindSafe;
+
+/// An `Iterator` over the values yielded by a closure running in a coroutine.
+///
+/// The closure is resumed lazily, once per `next`, so it can be written as
+/// straight-line code. After the iteration finished, the return value or the
+/// panic of the closure can be obtained by `result`.
+///
+/// # Examples
+/// ```
+/// use open_coroutine_core::coroutine::Generator;
+/// use open_coroutine_core::coroutine::suspender::Suspender;
+///
+/// let mut numbers = Generator::new(|yielder| {
+///     for word in "1 2 x 3".split_whitespace() {
+///         match word.parse::<i32>() {
+///             Ok(number) => _ = yielder.suspend_with(number),
+///             Err(_) => return Some(word),
+///         }
+///     }
+///     None
+/// })
+/// .expect("create generator failed");
+/// assert_eq!(vec![1, 2], numbers.by_ref().collect::<Vec<_>>());
+/// assert_eq!(Some(&Ok(Some("x"))), numbers.result());
+/// ```
+#[repr(C)]
+#[derive(Debug)]
+pub struct Generator<'g, Yield, Return>
+where
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
+{
+    coroutine: CoroutineImpl<'g, (), Yield, Return>,
+    result: Option<Result<Return, CoroutineError>>,
+}
+
+impl<'g, Yield, Return> Generator<'g, Yield, Return>
+where
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
+{
+    /// Create a new generator with the default stack size.
+    ///
+    /// # Errors
+    /// if stack allocate failed.
+    pub fn new(
+        f: impl FnOnce(&dyn Suspender<Resume = (), Yield = Yield>) -> Return + UnwindSafe + 'g,
+    ) -> std::io::Result<Self> {
+        Self::with_stack_size(f, DEFAULT_STACK_SIZE)
+    }
+
+    /// Create a new generator with the given stack size.
+    ///
+    /// # Errors
+    /// if stack allocate failed.
+    pub fn with_stack_size(
+        f: impl FnOnce(&dyn Suspender<Resume = (), Yield = Yield>) -> Return + UnwindSafe + 'g,
+        stack_size: usize,
+    ) -> std::io::Result<Self> {
+        Ok(Generator {
+            coroutine: CoroutineImpl::new(
+                format!("generator-{}", uuid::Uuid::new_v4()),
+                move |suspender, ()| f(suspender),
+                stack_size,
+            )?,
+            result: None,
+        })
+    }
+
+    /// Returns `true` if the closure returned or panicked.
+    pub fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// Returns the return value of the closure, or the error if it panicked,
+    /// `None` if the iteration has not finished yet.
+    pub fn result(&self) -> Option<&Result<Return, CoroutineError>> {
+        self.result.as_ref()
+    }
+
+    /// Takes the result out of this generator, see `result`.
+    pub fn take_result(&mut self) -> Option<Result<Return, CoroutineError>> {
+        self.result.take()
+    }
+}
+
+impl<Yield, Return> Iterator for Generator<'_, Yield, Return>
+where
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
+{
+    type Item = Yield;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished() {
+            return None;
+        }
+        let result = match self.coroutine.resume() {
+            Ok(CoroutineState::Suspend(value, _) | CoroutineState::SystemCall(value, _, _)) => {
+                return Some(value)
+            }
+            Ok(CoroutineState::Complete(value)) => Ok(value),
+            Ok(CoroutineState::Error(error)) => Err(error),
+            Ok(state) => Err(CoroutineError::Panic(Cow::Owned(format!(
+                "generator finished with unexpected state {state}"
+            )))),
+            Err(e) => Err(CoroutineError::Panic(Cow::Owned(e.to_string()))),
+        };
+        self.result = Some(result);
+        None
+    }
+}
+
+impl<Yield, Return> FusedIterator for Generator<'_, Yield, Return>
+where
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
+{
+}
+
+impl<Yield, Return> Drop for Generator<'_, Yield, Return>
+where
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
+{
+    fn drop(&mut self) {
+        // 未迭代完的生成器，展开协程栈以释放栈上的值
+        if !self.is_finished() {
+            _ = self.coroutine.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_generator() -> std::io::Result<()> {
+        let mut fibonacci = Generator::new(|yielder| {
+            let (mut a, mut b) = (0, 1);
+            while a < 50 {
+                yielder.suspend_with(a);
+                (a, b) = (b, a + b);
+            }
+            "done"
+        })?;
+        assert!(!fibonacci.is_finished());
+        assert_eq!(
+            vec![0, 1, 1, 2, 3, 5, 8, 13, 21, 34],
+            fibonacci.by_ref().collect::<Vec<_>>()
+        );
+        assert!(fibonacci.is_finished());
+        assert_eq!(None, fibonacci.next());
+        assert_eq!(Some(Ok("done")), fibonacci.take_result());
+        Ok(())
+    }
+
+    #[test]
+    fn test_panic() -> std::io::Result<()> {
+        let mut generator = Generator::new(|yielder| {
+            yielder.suspend_with(1);
+            panic!("test panic, just ignore it");
+        })?;
+        assert_eq!(Some(1), generator.next());
+        assert_eq!(None, generator.next());
+        assert_eq!(
+            Some(&Err(CoroutineError::from("test panic, just ignore it"))),
+            generator.result()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop() -> std::io::Result<()> {
+        struct Guard(Arc<AtomicBool>);
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::Release);
+            }
+        }
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = dropped.clone();
+        let mut generator = Generator::new(move |yielder| {
+            let _guard = Guard(flag);
+            loop {
+                yielder.suspend_with(());
+            }
+        })?;
+        assert_eq!(Some(()), generator.next());
+        assert!(!dropped.load(Ordering::Acquire));
+        drop(generator);
+        assert!(dropped.load(Ordering::Acquire));
+        Ok(())
+    }
+}
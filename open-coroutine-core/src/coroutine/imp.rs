@@ -1,16 +1,22 @@
-use crate::constants::{CoroutineState, Syscall, SyscallState};
+use crate::constants::{CoroutineState, Priority, Syscall, SyscallState};
+use crate::coroutine::error::{CoroutineError, TrapKind};
 use crate::coroutine::local::CoroutineLocal;
+use crate::coroutine::stack::{PooledStack, StackInfo};
+use crate::coroutine::state::StateCell;
 use crate::coroutine::suspender::{DelaySuspender, Suspender, SuspenderImpl};
-use crate::coroutine::{Coroutine, Current, Named, StateMachine, COROUTINE};
-use corosensei::stack::DefaultStack;
-use corosensei::trap::TrapHandlerRegs;
-use corosensei::{CoroutineResult, ScopedCoroutine};
+use crate::coroutine::{
+    Coroutine, CoroutineResult, Current, Named, ScopedCoroutine, StateMachine, TrapHandlerRegs,
+    COROUTINE,
+};
 use std::cell::Cell;
 use std::cmp::Ordering;
 use std::ffi::c_void;
 use std::fmt::{Debug, Formatter};
 use std::io::{Error, ErrorKind};
 use std::panic::{AssertUnwindSafe, UnwindSafe};
+use std::sync::atomic::AtomicUsize;
+
+static COROUTINE_ID: AtomicUsize = AtomicUsize::new(1);
 
 cfg_if::cfg_if! {
     if #[cfg(unix)] {
@@ -25,20 +31,26 @@ cfg_if::cfg_if! {
 pub struct CoroutineImpl<'c, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Eq + PartialEq + UnwindSafe,
-    Return: Copy + Eq + PartialEq + UnwindSafe,
+    Yield: UnwindSafe,
+    Return: UnwindSafe,
 {
+    id: usize,
     name: String,
-    inner: ScopedCoroutine<'c, Param, Yield, Result<Return, &'static str>, DefaultStack>,
-    state: Cell<CoroutineState<Yield, Return>>,
+    priority: Cell<Priority>,
+    deadline: Cell<Option<u64>>,
+    stack_size: usize,
+    stack: StackInfo,
+    stack_usage: Option<usize>,
+    inner: ScopedCoroutine<'c, Param, Yield, Result<Return, CoroutineError>, PooledStack>,
+    state: StateCell<Yield, Return>,
     local: CoroutineLocal<'c>,
 }
 
 impl<Param, Yield, Return> Drop for CoroutineImpl<'_, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Eq + PartialEq + UnwindSafe,
-    Return: Copy + Eq + PartialEq + UnwindSafe,
+    Yield: UnwindSafe,
+    Return: UnwindSafe,
 {
     fn drop(&mut self) {
         //for test_yield case
@@ -51,12 +63,17 @@ where
 impl<Param, Yield, Return> Debug for CoroutineImpl<'_, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Eq + PartialEq + Debug + UnwindSafe,
-    Return: Copy + Eq + PartialEq + Debug + UnwindSafe,
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Coroutine")
+            .field("id", &self.id)
             .field("name", &self.name)
+            .field("priority", &self.priority)
+            .field("deadline", &self.deadline)
+            .field("stack_size", &self.stack_size)
+            .field("stack_usage", &self.stack_usage)
             .field("status", &self.state)
             .field("local", &self.local)
             .finish()
@@ -66,8 +83,8 @@ where
 impl<'c, Param, Yield, Return> Current<'c> for CoroutineImpl<'c, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Eq + PartialEq + UnwindSafe,
-    Return: Copy + Eq + PartialEq + UnwindSafe,
+    Yield: UnwindSafe,
+    Return: UnwindSafe,
 {
     #[allow(clippy::ptr_as_ptr)]
     fn init_current(current: &CoroutineImpl<'c, Param, Yield, Return>) {
@@ -75,6 +92,7 @@ where
             s.borrow_mut()
                 .push_front(current as *const _ as *const c_void);
         });
+        CoroutineLocal::init_current(&current.local);
     }
 
     fn current() -> Option<&'c Self> {
@@ -86,6 +104,7 @@ where
     }
 
     fn clean_current() {
+        CoroutineLocal::clean_current();
         COROUTINE.with(|s| _ = s.borrow_mut().pop_front());
     }
 }
@@ -93,38 +112,38 @@ where
 impl<Param, Yield, Return> Eq for CoroutineImpl<'_, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Debug + Eq + PartialEq + UnwindSafe,
-    Return: Copy + Debug + Eq + PartialEq + UnwindSafe,
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
 {
 }
 
 impl<Param, Yield, Return> PartialEq<Self> for CoroutineImpl<'_, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Debug + Eq + PartialEq + UnwindSafe,
-    Return: Copy + Debug + Eq + PartialEq + UnwindSafe,
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
 {
     fn eq(&self, other: &Self) -> bool {
-        self.name.eq(&other.name)
+        self.id.eq(&other.id)
     }
 }
 
 impl<Param, Yield, Return> Ord for CoroutineImpl<'_, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Debug + Eq + PartialEq + UnwindSafe,
-    Return: Copy + Debug + Eq + PartialEq + UnwindSafe,
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
 {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.name.cmp(&other.name)
+        self.id.cmp(&other.id)
     }
 }
 
 impl<Param, Yield, Return> PartialOrd<Self> for CoroutineImpl<'_, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Debug + Eq + PartialEq + UnwindSafe,
-    Return: Copy + Debug + Eq + PartialEq + UnwindSafe,
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
 {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -134,8 +153,8 @@ where
 impl<Param, Yield, Return> Named for CoroutineImpl<'_, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Debug + Eq + PartialEq + UnwindSafe,
-    Return: Copy + Debug + Eq + PartialEq + UnwindSafe,
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
 {
     fn get_name(&self) -> &str {
         &self.name
@@ -145,8 +164,8 @@ where
 impl<'c, Param, Yield, Return> Coroutine<'c> for CoroutineImpl<'c, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Eq + PartialEq + Debug + UnwindSafe,
-    Return: Copy + Eq + PartialEq + Debug + UnwindSafe,
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
 {
     type Resume = Param;
     type Yield = Yield;
@@ -162,53 +181,76 @@ where
         F: 'c,
         Self: Sized,
     {
-        let stack = DefaultStack::new(stack_size.max(crate::common::page_size()))?;
-        #[cfg(feature = "logs")]
-        let co_name = name.clone().leak();
+        let stack = PooledStack::new(stack_size.max(crate::common::page_size()))?;
+        let stack_info = stack.info();
         let inner = ScopedCoroutine::with_stack(stack, move |y, p| {
             let suspender = SuspenderImpl(y);
             SuspenderImpl::<Param, Yield>::init_current(&suspender);
             #[allow(box_pointers)]
             let r = std::panic::catch_unwind(AssertUnwindSafe(|| f(&suspender, p))).map_err(|e| {
-                let message = *e
-                    .downcast_ref::<&'static str>()
-                    .unwrap_or(&"coroutine failed without message");
-                crate::error!("coroutine:{} finish with error:{}", co_name, message);
-                message
+                // 协程被取消，继续展开协程栈
+                if Self::current().is_some_and(|c| c.state.shape() == CoroutineState::Cancelled) {
+                    std::panic::resume_unwind(e);
+                }
+                CoroutineError::from(e)
             });
             SuspenderImpl::<Param, Yield>::clean_current();
             r
         });
         Ok(CoroutineImpl {
+            id: COROUTINE_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
             name,
+            priority: Cell::new(Priority::default()),
+            deadline: Cell::new(None),
+            stack_size,
+            stack: stack_info,
+            stack_usage: None,
             inner,
-            state: Cell::new(CoroutineState::Created),
+            state: StateCell::new(CoroutineState::Created),
             local: CoroutineLocal::default(),
         })
     }
 
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn priority(&self) -> Priority {
+        self.priority.get()
+    }
+
+    fn set_priority(&self, priority: Priority) -> Priority {
+        self.priority.replace(priority)
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        self.deadline.get()
+    }
+
+    fn set_deadline(&self, deadline: Option<u64>) -> Option<u64> {
+        self.deadline.replace(deadline)
+    }
+
     fn resume_with(
         &mut self,
         arg: Self::Resume,
     ) -> std::io::Result<CoroutineState<Self::Yield, Self::Return>> {
-        if let CoroutineState::Complete(r) = self.state() {
-            return Ok(CoroutineState::Complete(r));
-        }
         Self::setup_trap_handler();
         self.running()?;
         Self::init_current(self);
-        let r = match self.inner.resume(arg) {
+        let trap_handler = TRAP_HANDLER.with(|h| h.replace(Some(Self::trap_handler)));
+        let result = self.inner.resume(arg);
+        TRAP_HANDLER.with(|h| h.set(trap_handler));
+        match result {
             CoroutineResult::Yield(y) => {
-                let current = self.state();
+                let current = self.state.shape();
                 match current {
                     CoroutineState::Running => {
                         let timestamp = SuspenderImpl::<Yield, Param>::timestamp();
-                        let new_state = CoroutineState::Suspend(y, timestamp);
                         self.suspend(y, timestamp)?;
-                        new_state
                     }
-                    CoroutineState::SystemCall(y, syscall, state) => {
-                        CoroutineState::SystemCall(y, syscall, state)
+                    CoroutineState::SystemCall((), syscall, state) => {
+                        self.syscall(y, syscall, state)?;
                     }
                     _ => {
                         return Err(Error::new(
@@ -219,18 +261,32 @@ where
                 }
             }
             CoroutineResult::Return(result) => {
-                if let Ok(returns) = result {
-                    self.complete(returns)?;
-                    CoroutineState::Complete(returns)
-                } else {
-                    let message = result.unwrap_err();
-                    self.error(message)?;
-                    CoroutineState::Error(message)
+                self.stack_usage = unsafe { self.stack.measure() };
+                self.local.clean();
+                match result {
+                    Ok(returns) => self.complete(returns)?,
+                    Err(message) => {
+                        crate::error!("coroutine:{} finish with error:{}", self.name, message);
+                        self.error(message)?;
+                    }
                 }
             }
-        };
+        }
+        Self::clean_current();
+        // the yielded/returned value is moved out to the caller
+        Ok(self.state.take())
+    }
+
+    fn cancel(&mut self) -> std::io::Result<()> {
+        self.cancelled()?;
+        Self::setup_trap_handler();
+        Self::init_current(self);
+        let trap_handler = TRAP_HANDLER.with(|h| h.replace(Some(Self::trap_handler)));
+        self.inner.force_unwind();
+        TRAP_HANDLER.with(|h| h.set(trap_handler));
+        self.local.clean();
         Self::clean_current();
-        Ok(r)
+        Ok(())
     }
 
     fn local(&self) -> &CoroutineLocal<'c> {
@@ -241,21 +297,25 @@ where
 impl<'c, Param, Yield, Return> StateMachine<'c> for CoroutineImpl<'c, Param, Yield, Return>
 where
     Param: UnwindSafe,
-    Yield: Copy + Debug + Eq + PartialEq + UnwindSafe,
-    Return: Copy + Debug + Eq + PartialEq + UnwindSafe,
+    Yield: Debug + UnwindSafe,
+    Return: Debug + UnwindSafe,
 {
-    fn state(&self) -> CoroutineState<Self::Yield, Self::Return> {
+    fn state(&self) -> CoroutineState<Self::Yield, Self::Return>
+    where
+        Self::Yield: Copy,
+        Self::Return: Copy,
+    {
         self.state.get()
     }
 
     fn ready(&self) -> std::io::Result<()> {
-        let current = self.state();
+        let current = self.state.shape();
         match current {
             CoroutineState::Created => {
                 self.state.set(CoroutineState::Ready);
                 return Ok(());
             }
-            CoroutineState::Suspend(_, timestamp) => {
+            CoroutineState::Suspend((), timestamp) => {
                 if timestamp <= open_coroutine_timer::now() {
                     self.state.set(CoroutineState::Ready);
                     return Ok(());
@@ -274,19 +334,19 @@ where
     }
 
     fn running(&self) -> std::io::Result<()> {
-        let current = self.state();
+        let current = self.state.shape();
         match current {
             CoroutineState::Created | CoroutineState::Ready => {
                 self.state.set(CoroutineState::Running);
                 return Ok(());
             }
-            CoroutineState::Suspend(_, timestamp) => {
+            CoroutineState::Suspend((), timestamp) => {
                 if timestamp <= open_coroutine_timer::now() {
                     self.state.set(CoroutineState::Running);
                     return Ok(());
                 }
             }
-            CoroutineState::SystemCall(_, _, _) => return Ok(()),
+            CoroutineState::SystemCall((), _, _) => return Ok(()),
             _ => {}
         }
         Err(Error::new(
@@ -300,7 +360,7 @@ where
     }
 
     fn suspend(&self, val: Self::Yield, timestamp: u64) -> std::io::Result<()> {
-        let current = self.state();
+        let current = self.state.shape();
         if CoroutineState::Running == current {
             self.state.set(CoroutineState::Suspend(val, timestamp));
             return Ok(());
@@ -321,19 +381,19 @@ where
         syscall: Syscall,
         syscall_state: SyscallState,
     ) -> std::io::Result<()> {
-        let current = self.state();
+        let current = self.state.shape();
         match current {
             CoroutineState::Running => {
                 let state = CoroutineState::SystemCall(val, syscall, syscall_state);
-                self.state.set(state);
                 crate::info!("{} {}->{}", self.get_name(), current, state);
+                self.state.set(state);
                 return Ok(());
             }
-            CoroutineState::SystemCall(_, original_syscall, _) => {
+            CoroutineState::SystemCall((), original_syscall, _) => {
                 if original_syscall == syscall {
                     let state = CoroutineState::SystemCall(val, syscall, syscall_state);
-                    self.state.set(state);
                     crate::info!("{} {}->{}", self.get_name(), current, state);
+                    self.state.set(state);
                     return Ok(());
                 }
             }
@@ -350,13 +410,13 @@ where
     }
 
     fn syscall_resume(&self) -> std::io::Result<()> {
-        let current = self.state();
-        if let CoroutineState::SystemCall(_, _, SyscallState::Finished | SyscallState::Timeout) =
+        let current = self.state.shape();
+        if let CoroutineState::SystemCall((), _, SyscallState::Finished | SyscallState::Timeout) =
             current
         {
             let state = CoroutineState::Running;
-            self.state.set(state);
             crate::info!("{} {}->{}", self.get_name(), current, state);
+            self.state.set(state);
             return Ok(());
         }
         Err(Error::new(
@@ -370,7 +430,7 @@ where
     }
 
     fn complete(&self, val: Self::Return) -> std::io::Result<()> {
-        let current = self.state();
+        let current = self.state.shape();
         if CoroutineState::Running == current {
             self.state.set(CoroutineState::Complete(val));
             return Ok(());
@@ -385,8 +445,8 @@ where
         ))
     }
 
-    fn error(&self, val: &'static str) -> std::io::Result<()> {
-        let current = self.state();
+    fn error(&self, val: CoroutineError) -> std::io::Result<()> {
+        let current = self.state.shape();
         if CoroutineState::Running == current {
             self.state.set(CoroutineState::Error(val));
             return Ok(());
@@ -400,20 +460,48 @@ where
             ),
         ))
     }
+
+    fn cancelled(&self) -> std::io::Result<()> {
+        let current = self.state.shape();
+        match current {
+            CoroutineState::Created
+            | CoroutineState::Ready
+            | CoroutineState::Suspend((), _)
+            | CoroutineState::SystemCall((), _, _) => {
+                let state = CoroutineState::Cancelled;
+                crate::info!("{} {}->{}", self.get_name(), current, state);
+                self.state.set(state);
+                return Ok(());
+            }
+            _ => {}
+        }
+        Err(Error::other(format!(
+            "{} unexpected {current}->{}",
+            self.name,
+            CoroutineState::<Yield, Return>::Cancelled
+        )))
+    }
 }
 
 impl<'c, Param, Yield, Return> CoroutineImpl<'c, Param, Yield, Return>
 where
     Param: UnwindSafe + 'c,
-    Yield: Copy + Eq + PartialEq + UnwindSafe + 'c,
-    Return: Copy + Eq + PartialEq + UnwindSafe + 'c,
+    Yield: UnwindSafe + 'c,
+    Return: UnwindSafe + 'c,
 {
+    /// Returns the peak stack usage in bytes, it's only available after
+    /// this coroutine finished with the stack measurement enabled.
+    #[must_use]
+    pub fn stack_usage(&self) -> Option<usize> {
+        self.stack_usage
+    }
+
     cfg_if::cfg_if! {
         if #[cfg(unix)] {
             #[allow(clippy::cast_possible_truncation, clippy::too_many_lines)]
             extern "C" fn trap_handler(
-                _signum: libc::c_int,
-                _siginfo: *mut libc::siginfo_t,
+                signum: libc::c_int,
+                siginfo: *mut libc::siginfo_t,
                 context: *mut c_void,
             ) {
                 unsafe {
@@ -456,10 +544,38 @@ where
                             compile_error!("Unsupported platform");
                         }
                     }
+                    cfg_if::cfg_if! {
+                        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                            let address = (*siginfo).si_addr() as usize;
+                        } else {
+                            let address = (*siginfo).si_addr as usize;
+                        }
+                    }
                     if let Some(co) = Self::current() {
                         let handler = co.inner.trap_handler();
                         assert!(handler.stack_ptr_in_bounds(sp));
-                        let regs = handler.setup_trap_handler(|| Err("invalid memory reference"));
+                        //不能在信号处理函数中分配内存，错误在协程栈上构造
+                        let stack_overflow = co.stack.in_guard_page(address);
+                        let name: *const str = co.name.as_str();
+                        let stack_size = co.stack_size;
+                        let regs = handler.setup_trap_handler(move || {
+                            Err(if stack_overflow {
+                                CoroutineError::StackOverflow {
+                                    name: String::from(&*name),
+                                    stack_size,
+                                }
+                            } else {
+                                CoroutineError::Trap {
+                                    kind: if libc::SIGBUS == signum {
+                                        TrapKind::BusError
+                                    } else {
+                                        TrapKind::InvalidMemoryReference
+                                    },
+                                    signum,
+                                    address,
+                                }
+                            })
+                        });
                         cfg_if::cfg_if! {
                             if #[cfg(all(
                                     any(target_os = "linux", target_os = "android"),
@@ -567,10 +683,15 @@ where
             }
         } else if #[cfg(windows)] {
             unsafe extern "system" fn trap_handler(exception_info: *mut EXCEPTION_POINTERS) -> i32 {
-                match (*(*exception_info).ExceptionRecord).ExceptionCode {
-                    EXCEPTION_ACCESS_VIOLATION | EXCEPTION_STACK_OVERFLOW => {}
+                let record = &*(*exception_info).ExceptionRecord;
+                let signum = record.ExceptionCode;
+                let stack_overflow = match signum {
+                    EXCEPTION_ACCESS_VIOLATION => false,
+                    EXCEPTION_STACK_OVERFLOW => true,
                     _ => return 0, // EXCEPTION_CONTINUE_SEARCH
-                }
+                };
+                // the second element of ExceptionInformation is the faulting address
+                let address = record.ExceptionInformation[1];
 
                 if let Some(co) = Self::current() {
                     cfg_if::cfg_if! {
@@ -588,7 +709,22 @@ where
                         // EXCEPTION_CONTINUE_SEARCH
                         return 0;
                     }
-                    let regs = handler.setup_trap_handler(|| Err("invalid memory reference"));
+                    let name: *const str = co.name.as_str();
+                    let stack_size = co.stack_size;
+                    let regs = handler.setup_trap_handler(move || {
+                        Err(if stack_overflow {
+                            CoroutineError::StackOverflow {
+                                name: String::from(&*name),
+                                stack_size,
+                            }
+                        } else {
+                            CoroutineError::Trap {
+                                kind: TrapKind::InvalidMemoryReference,
+                                signum,
+                                address,
+                            }
+                        })
+                    });
 
                     cfg_if::cfg_if! {
                         if #[cfg(target_arch = "x86_64")] {
@@ -621,6 +757,8 @@ where
     fn setup_trap_handler() {
         use std::sync::atomic::{AtomicBool, Ordering};
         static TRAP_HANDLER_INITED: AtomicBool = AtomicBool::new(false);
+        #[cfg(unix)]
+        SIGNAL_STACK.with(|_| {});
         if TRAP_HANDLER_INITED
             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
             .is_ok()
@@ -632,7 +770,7 @@ where
                 set.add(Signal::SIGBUS);
                 set.add(Signal::SIGSEGV);
                 let sa = SigAction::new(
-                    SigHandler::SigAction(Self::trap_handler),
+                    SigHandler::SigAction(trap_dispatcher),
                     SaFlags::SA_ONSTACK,
                     set,
                 );
@@ -643,7 +781,7 @@ where
             }
             #[cfg(windows)]
             unsafe {
-                if AddVectoredExceptionHandler(1, Some(Self::trap_handler)).is_null() {
+                if AddVectoredExceptionHandler(1, Some(trap_dispatcher)).is_null() {
                     panic!(
                         "failed to add exception handler: {}",
                         Error::last_os_error()
@@ -653,3 +791,88 @@ where
         }
     }
 }
+
+cfg_if::cfg_if! {
+    if #[cfg(unix)] {
+        type TrapHandler = extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void);
+
+        thread_local! {
+            static SIGNAL_STACK: SignalStack = SignalStack::new();
+        }
+
+        /// The installed signal handler, dispatch to the trap handler of the running coroutine.
+        extern "C" fn trap_dispatcher(
+            signum: libc::c_int,
+            siginfo: *mut libc::siginfo_t,
+            context: *mut c_void,
+        ) {
+            match TRAP_HANDLER.with(Cell::get) {
+                Some(handler) => handler(signum, siginfo, context),
+                // not in coroutine, fallback to the default action
+                None => unsafe {
+                    _ = libc::signal(signum, libc::SIG_DFL);
+                },
+            }
+        }
+    } else if #[cfg(windows)] {
+        type TrapHandler = unsafe extern "system" fn(*mut EXCEPTION_POINTERS) -> i32;
+
+        /// The installed exception handler, dispatch to the trap handler of the running coroutine.
+        unsafe extern "system" fn trap_dispatcher(exception_info: *mut EXCEPTION_POINTERS) -> i32 {
+            match TRAP_HANDLER.with(Cell::get) {
+                Some(handler) => handler(exception_info),
+                None => 0, // EXCEPTION_CONTINUE_SEARCH
+            }
+        }
+    }
+}
+
+thread_local! {
+    // the trap handler is generic over the coroutine type, so it can only be
+    // installed through `trap_dispatcher`
+    static TRAP_HANDLER: Cell<Option<TrapHandler>> = const { Cell::new(None) };
+}
+
+/// The alternate signal stack, without it the trap handler can not run when the stack overflows.
+#[cfg(unix)]
+struct SignalStack(Option<Vec<u8>>);
+
+#[cfg(unix)]
+impl SignalStack {
+    fn new() -> Self {
+        unsafe {
+            let mut old: libc::stack_t = std::mem::zeroed();
+            _ = libc::sigaltstack(std::ptr::null(), std::ptr::addr_of_mut!(old));
+            if old.ss_flags & libc::SS_DISABLE == 0 {
+                //already installed, such as the threads created by std
+                return SignalStack(None);
+            }
+            let mut stack = vec![0u8; libc::SIGSTKSZ.max(crate::constants::DEFAULT_STACK_SIZE)];
+            let new = libc::stack_t {
+                ss_sp: stack.as_mut_ptr().cast(),
+                ss_flags: 0,
+                ss_size: stack.len(),
+            };
+            if libc::sigaltstack(std::ptr::addr_of!(new), std::ptr::null_mut()) != 0 {
+                return SignalStack(None);
+            }
+            SignalStack(Some(stack))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SignalStack {
+    fn drop(&mut self) {
+        if self.0.is_some() {
+            unsafe {
+                let disable = libc::stack_t {
+                    ss_sp: std::ptr::null_mut(),
+                    ss_flags: libc::SS_DISABLE,
+                    ss_size: libc::SIGSTKSZ,
+                };
+                _ = libc::sigaltstack(std::ptr::addr_of!(disable), std::ptr::null_mut());
+            }
+        }
+    }
+}
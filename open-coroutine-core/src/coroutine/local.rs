@@ -1,45 +1,323 @@
+use crate::common::Current;
 use dashmap::DashMap;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::c_void;
+use std::fmt::{Debug, Formatter};
 
 /// A struct for coroutines handles local args.
 #[repr(C)]
-#[derive(Debug, Default)]
-pub struct CoroutineLocal<'c>(DashMap<&'c str, usize>);
+#[derive(Default)]
+pub struct CoroutineLocal<'c>(
+    DashMap<&'c str, Box<dyn Any>>,
+    // the values of `coroutine_local!`, keyed by the address of `LocalKey`
+    RefCell<HashMap<usize, LocalValue>>,
+);
+
+// copy the value for the child coroutine
+type Inherit = fn(&dyn Any) -> Box<dyn Any + Send>;
+
+struct LocalValue {
+    value: Box<dyn Any>,
+    inherit: Option<Inherit>,
+}
+
+/// The snapshot of the inheritable coroutine local values,
+/// see `inheritable_coroutine_local!`.
+#[derive(Default)]
+pub struct Inherited(Vec<(usize, Box<dyn Any + Send>, Inherit)>);
+
+impl Debug for Inherited {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Inherited")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
 
 #[allow(missing_docs, box_pointers)]
 impl<'c> CoroutineLocal<'c> {
-    pub fn put<V>(&self, key: &'c str, val: V) -> Option<V> {
-        let v = Box::leak(Box::new(val));
+    /// Put a value, the old value will be returned if it has the same type,
+    /// otherwise the old value will be dropped.
+    pub fn put<V: 'static>(&self, key: &'c str, val: V) -> Option<V> {
         self.0
-            .insert(key, v as *mut V as usize)
-            .map(|ptr| unsafe { *Box::from_raw((ptr as *mut c_void).cast::<V>()) })
+            .insert(key, Box::new(val))
+            .and_then(|old| old.downcast::<V>().ok())
+            .map(|old| *old)
     }
 
+    /// Get the value, returns `None` if the value has another type.
     #[must_use]
-    pub fn get<V>(&self, key: &'c str) -> Option<&V> {
+    pub fn get<V: 'static>(&self, key: &'c str) -> Option<&V> {
         self.0
             .get(key)
-            .map(|ptr| unsafe { &*(*ptr as *mut c_void).cast::<V>() })
+            .and_then(|r| r.downcast_ref::<V>().map(|v| std::ptr::addr_of!(*v)))
+            .map(|ptr| unsafe { &*ptr })
     }
 
+    /// Get the value, returns `None` if the value has another type.
+    #[allow(clippy::mut_from_ref)]
     #[must_use]
-    pub fn get_mut<V>(&self, key: &'c str) -> Option<&mut V> {
+    pub fn get_mut<V: 'static>(&self, key: &'c str) -> Option<&mut V> {
         self.0
-            .get(key)
-            .map(|ptr| unsafe { &mut *(*ptr as *mut c_void).cast::<V>() })
+            .get_mut(key)
+            .and_then(|mut r| r.downcast_mut::<V>().map(|v| std::ptr::addr_of_mut!(*v)))
+            .map(|ptr| unsafe { &mut *ptr })
     }
 
+    /// Remove the value, returns `None` if the value has another type.
     #[must_use]
-    pub fn remove<V>(&self, key: &'c str) -> Option<V> {
+    pub fn remove<V: 'static>(&self, key: &'c str) -> Option<V> {
         self.0
             .remove(key)
-            .map(|ptr| unsafe { *Box::from_raw((ptr.1 as *mut c_void).cast::<V>()) })
+            .and_then(|(_, old)| old.downcast::<V>().ok())
+            .map(|old| *old)
+    }
+
+    /// Access the value of `key`, the value will be initialized at the first access.
+    ///
+    /// # Panics
+    /// if the stored value has another type.
+    pub fn with<T: 'static, R>(&self, key: &'static LocalKey<T>, f: impl FnOnce(&T) -> R) -> R {
+        let id = std::ptr::addr_of!(*key) as usize;
+        let ptr = self.1.borrow().get(&id).map(Self::downcast::<T>);
+        let ptr = ptr.unwrap_or_else(|| {
+            // 初始化时可能访问其他的协程本地变量，不能持有借用
+            let val = LocalValue {
+                value: Box::new((key.init)()),
+                inherit: key.inherit,
+            };
+            Self::downcast::<T>(self.1.borrow_mut().entry(id).or_insert(val))
+        });
+        // 值分配在堆上，协程结束前不会被释放
+        f(unsafe { &*ptr })
+    }
+
+    fn downcast<T: 'static>(val: &LocalValue) -> *const T {
+        val.value
+            .downcast_ref::<T>()
+            .expect("the coroutine local value has another type")
+    }
+
+    /// Take a snapshot of the inheritable values, which can be sent to
+    /// other threads and installed in the child coroutines.
+    #[must_use]
+    pub fn inherited(&self) -> Inherited {
+        Inherited(
+            self.1
+                .borrow()
+                .iter()
+                .filter_map(|(id, val)| val.inherit.map(|f| (*id, f(&*val.value), f)))
+                .collect(),
+        )
+    }
+
+    /// Install the inheritable values, the existing values will be replaced.
+    pub fn inherit(&self, inherited: Inherited) {
+        let mut values = self.1.borrow_mut();
+        for (id, value, inherit) in inherited.0 {
+            _ = values.insert(
+                id,
+                LocalValue {
+                    value,
+                    inherit: Some(inherit),
+                },
+            );
+        }
+    }
+
+    /// Run `f` with the `inherited` values, the values of `coroutine_local!`
+    /// set by `f` will be dropped after `f` returned, and the original
+    /// values will be restored.
+    pub(crate) fn scope<R>(&self, inherited: Inherited, f: impl FnOnce() -> R) -> R {
+        let original = std::mem::take(&mut *self.1.borrow_mut());
+        self.inherit(inherited);
+        let r = f();
+        self.clean();
+        *self.1.borrow_mut() = original;
+        r
+    }
+
+    /// Drop all values of `coroutine_local!`, called when the coroutine finished.
+    pub(crate) fn clean(&self) {
+        // 析构时可能访问其他的协程本地变量，先取出再释放
+        let values = std::mem::take(&mut *self.1.borrow_mut());
+        drop(values);
+    }
+}
+
+impl Debug for CoroutineLocal<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoroutineLocal")
+            .field("keys", &self.0.iter().map(|r| *r.key()).collect::<Vec<_>>())
+            .field("typed", &self.1.borrow().len())
+            .finish()
+    }
+}
+
+thread_local! {
+    static COROUTINE_LOCAL: RefCell<VecDeque<*const c_void>> = const { RefCell::new(VecDeque::new()) };
+}
+
+impl<'c> Current<'c> for CoroutineLocal<'c> {
+    fn init_current(current: &CoroutineLocal<'c>) {
+        COROUTINE_LOCAL.with(|s| {
+            s.borrow_mut()
+                .push_front(std::ptr::addr_of!(*current).cast::<c_void>());
+        });
+    }
+
+    fn current() -> Option<&'c Self> {
+        COROUTINE_LOCAL.with(|s| {
+            s.borrow()
+                .front()
+                .map(|ptr| unsafe { &*(*ptr).cast::<CoroutineLocal<'c>>() })
+        })
     }
+
+    fn clean_current() {
+        COROUTINE_LOCAL.with(|s| _ = s.borrow_mut().pop_front());
+    }
+}
+
+/// A key for a coroutine local value, created by `coroutine_local!`.
+///
+/// Each coroutine has its own copy of the value, which is lazily initialized
+/// at the first access, and dropped when the coroutine finished.
+pub struct LocalKey<T: 'static> {
+    init: fn() -> T,
+    inherit: Option<Inherit>,
+}
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    #[must_use]
+    pub const fn new(init: fn() -> T) -> Self {
+        LocalKey {
+            init,
+            inherit: None,
+        }
+    }
+
+    #[doc(hidden)]
+    #[must_use]
+    pub const fn inheritable(init: fn() -> T) -> Self
+    where
+        T: Clone + Send,
+    {
+        LocalKey {
+            init,
+            inherit: Some(Self::copy),
+        }
+    }
+
+    fn copy(val: &dyn Any) -> Box<dyn Any + Send>
+    where
+        T: Clone + Send,
+    {
+        Box::new(
+            val.downcast_ref::<T>()
+                .expect("the coroutine local value has another type")
+                .clone(),
+        )
+    }
+
+    /// Access the value of current coroutine.
+    ///
+    /// # Panics
+    /// if not in a coroutine.
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.try_with(f)
+            .expect("cannot access a coroutine local value outside of a coroutine")
+    }
+
+    /// Access the value of current coroutine, returns `None` if not in a coroutine.
+    pub fn try_with<F, R>(&'static self, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        CoroutineLocal::current().map(|local| local.with(self, f))
+    }
+}
+
+impl<T: 'static> Debug for LocalKey<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalKey").finish_non_exhaustive()
+    }
+}
+
+/// Declare new coroutine local storage keys of type `LocalKey`,
+/// the usage is the same as `thread_local!`.
+///
+/// # Examples
+/// ```
+/// use std::cell::Cell;
+/// use open_coroutine_core::constants::CoroutineState;
+/// use open_coroutine_core::coroutine::suspender::Suspender;
+/// use open_coroutine_core::coroutine::{Coroutine, SimpleCoroutine};
+/// use open_coroutine_core::{co, coroutine_local};
+///
+/// coroutine_local! {
+///     static COUNTER: Cell<usize> = Cell::new(0);
+/// }
+///
+/// let mut coroutine = co!(|_: &dyn Suspender<'_, Resume = (), Yield = ()>, ()| {
+///     COUNTER.with(|c| c.set(c.get() + 1));
+///     COUNTER.with(Cell::get)
+/// });
+/// assert_eq!(CoroutineState::Complete(1), coroutine.resume().unwrap());
+/// assert!(COUNTER.try_with(Cell::get).is_none());
+/// ```
+#[macro_export]
+macro_rules! coroutine_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::coroutine_local!($(#[$attr])* $vis static $name: $t = $init);
+        $crate::coroutine_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])*
+        $vis static $name: $crate::coroutine::local::LocalKey<$t> = {
+            fn __init() -> $t {
+                $init
+            }
+            $crate::coroutine::local::LocalKey::new(__init)
+        };
+    };
+}
+
+/// Declare new inheritable coroutine local storage keys of type `LocalKey`,
+/// the usage is the same as `coroutine_local!`, but the type must be `Clone + Send`.
+///
+/// When a coroutine submits a child coroutine or task by `Scheduler::submit`,
+/// `Pool::submit` or `EventLoops::submit`, the values of these keys are
+/// copied to the child, just like the `InheritableThreadLocal` in java.
+#[macro_export]
+macro_rules! inheritable_coroutine_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::inheritable_coroutine_local!($(#[$attr])* $vis static $name: $t = $init);
+        $crate::inheritable_coroutine_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])*
+        $vis static $name: $crate::coroutine::local::LocalKey<$t> = {
+            fn __init() -> $t {
+                $init
+            }
+            $crate::coroutine::local::LocalKey::inheritable(__init)
+        };
+    };
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::Cell;
 
     #[test]
     fn test_local() {
@@ -48,6 +326,46 @@ mod tests {
         assert_eq!(Some(1), local.put("1", 2));
         assert_eq!(2, *local.get("1").unwrap());
         *local.get_mut("1").unwrap() = 3;
+        assert!(local.get::<String>("1").is_none());
         assert_eq!(Some(3), local.remove("1"));
     }
+
+    #[test]
+    fn test_key() {
+        coroutine_local! {
+            static KEY1: Cell<usize> = Cell::new(1);
+            static KEY2: String = KEY1.with(|v| v.get().to_string());
+        }
+        let local = CoroutineLocal::default();
+        CoroutineLocal::init_current(&local);
+        KEY1.with(|v| v.set(2));
+        assert_eq!("2", KEY2.with(String::clone));
+        local.clean();
+        assert_eq!(1, KEY1.with(Cell::get));
+        CoroutineLocal::clean_current();
+        assert!(KEY1.try_with(Cell::get).is_none());
+    }
+
+    #[test]
+    fn test_inherit() {
+        coroutine_local! {
+            static KEY1: Cell<usize> = Cell::new(1);
+        }
+        inheritable_coroutine_local! {
+            static KEY2: Cell<usize> = Cell::new(1);
+        }
+        let parent = CoroutineLocal::default();
+        parent.with(&KEY1, |v| v.set(2));
+        parent.with(&KEY2, |v| v.set(2));
+        let child = CoroutineLocal::default();
+        child.inherit(parent.inherited());
+        assert_eq!(1, child.with(&KEY1, Cell::get));
+        assert_eq!(2, child.with(&KEY2, Cell::get));
+        child.scope(Inherited::default(), || {
+            assert_eq!(1, child.with(&KEY2, Cell::get));
+            child.with(&KEY2, |v| v.set(3));
+        });
+        assert_eq!(2, child.with(&KEY2, Cell::get));
+        assert_eq!(2, parent.with(&KEY2, Cell::get));
+    }
 }
@@ -1,4 +1,5 @@
-use crate::constants::{CoroutineState, Syscall, SyscallState};
+use crate::constants::{CoroutineState, Priority, Syscall, SyscallState};
+use crate::coroutine::error::CoroutineError;
 use crate::coroutine::local::CoroutineLocal;
 use crate::coroutine::suspender::Suspender;
 use std::cell::RefCell;
@@ -13,27 +14,53 @@ pub mod local;
 /// Coroutine suspender abstraction.
 pub mod suspender;
 
+/// Coroutine error abstraction.
+pub mod error;
+
+mod state;
+
+/// Coroutine stack cache.
+#[cfg(any(feature = "korosensei", feature = "boost"))]
+pub mod stack;
+
 use crate::common::{Current, Named};
-#[cfg(feature = "korosensei")]
-pub use korosensei::CoroutineImpl;
+#[cfg(any(feature = "korosensei", feature = "boost"))]
+pub use imp::CoroutineImpl;
 
+/// `CoroutineImpl` shared by all backends.
 #[allow(missing_docs)]
-#[cfg(feature = "korosensei")]
-mod korosensei;
+#[cfg(any(feature = "korosensei", feature = "boost"))]
+mod imp;
 
-#[cfg(all(feature = "boost", not(feature = "korosensei")))]
+// the `corosensei` backend
+#[cfg(all(feature = "korosensei", not(feature = "boost")))]
+use corosensei::{trap::TrapHandlerRegs, CoroutineResult, ScopedCoroutine, Yielder};
+
+/// The `boost` backend, provides the same API as `corosensei`,
+/// and takes precedence over `corosensei` when both enabled.
+#[cfg(feature = "boost")]
 mod boost;
 
+#[cfg(feature = "boost")]
+use boost::{CoroutineResult, ScopedCoroutine, TrapHandlerRegs, Yielder};
+
+#[cfg(any(feature = "korosensei", feature = "boost"))]
+pub use generator::Generator;
+
+/// Generator abstraction and impl.
+#[cfg(any(feature = "korosensei", feature = "boost"))]
+mod generator;
+
 /// A trait implemented for coroutines.
 pub trait Coroutine<'c>: Debug + Eq + PartialEq + Ord + PartialOrd + Named + Current<'c> {
     /// The type of value this coroutine accepts as a resume argument.
     type Resume: UnwindSafe;
 
     /// The type of value this coroutine yields.
-    type Yield: Copy + Eq + PartialEq + UnwindSafe;
+    type Yield: UnwindSafe;
 
     /// The type of value this coroutine returns upon completion.
-    type Return: Copy + Eq + PartialEq + UnwindSafe;
+    type Return: UnwindSafe;
 
     /// Create a new coroutine.
     ///
@@ -49,17 +76,46 @@ pub trait Coroutine<'c>: Debug + Eq + PartialEq + Ord + PartialOrd + Named + Cur
         F: 'c,
         Self: Sized;
 
+    /// Returns the unique id of this coroutine. It's cheaper than the name to
+    /// hash and compare, so it's used to identify coroutines on hot paths.
+    fn id(&self) -> usize;
+
+    /// Returns the priority of this coroutine.
+    fn priority(&self) -> Priority;
+
+    /// Change the priority of this coroutine and returns the previous one,
+    /// it takes effect the next time this coroutine is ready to run.
+    fn set_priority(&self, priority: Priority) -> Priority;
+
+    /// Returns the deadline timestamp of this coroutine in ns, if any.
+    fn deadline(&self) -> Option<u64>;
+
+    /// Change the deadline of this coroutine and returns the previous one,
+    /// it takes effect the next time this coroutine is ready to run.
+    fn set_deadline(&self, deadline: Option<u64>) -> Option<u64>;
+
     /// Resumes the execution of this coroutine.
     ///
     /// The argument will be passed into the coroutine as a resume argument.
+    /// The yielded or returned value is moved out to the caller, so unlike
+    /// the earlier versions, resuming a completed coroutine doesn't return
+    /// `CoroutineState::Complete` again, even if `Return` is `Copy`.
     ///
     /// # Errors
-    /// if current coroutine state is unexpected.
+    /// if current coroutine state is unexpected, including the coroutine has completed.
     fn resume_with(
         &mut self,
         arg: Self::Resume,
     ) -> std::io::Result<CoroutineState<Self::Yield, Self::Return>>;
 
+    /// Cancel this coroutine, the stack will be unwound from where it
+    /// suspended, so all values on it are dropped, then the coroutine
+    /// enters the `Cancelled` state.
+    ///
+    /// # Errors
+    /// if the coroutine is running or already finished.
+    fn cancel(&mut self) -> std::io::Result<()>;
+
     /// put/get some custom data to it.
     fn local(&self) -> &CoroutineLocal<'c>;
 }
@@ -67,7 +123,10 @@ pub trait Coroutine<'c>: Debug + Eq + PartialEq + Ord + PartialOrd + Named + Cur
 /// A trait implemented for describing changes in the state of the coroutine.
 pub trait StateMachine<'c>: Coroutine<'c> {
     /// Returns the current state of this `StateMachine`.
-    fn state(&self) -> CoroutineState<Self::Yield, Self::Return>;
+    fn state(&self) -> CoroutineState<Self::Yield, Self::Return>
+    where
+        Self::Yield: Copy,
+        Self::Return: Copy;
 
     /// created -> ready
     /// syscall -> ready
@@ -119,7 +178,16 @@ pub trait StateMachine<'c>: Coroutine<'c> {
     ///
     /// # Errors
     /// if change state fails.
-    fn error(&self, val: &'static str) -> std::io::Result<()>;
+    fn error(&self, val: CoroutineError) -> std::io::Result<()>;
+
+    /// created -> cancelled
+    /// ready -> cancelled
+    /// suspend -> cancelled
+    /// syscall -> cancelled
+    ///
+    /// # Errors
+    /// if change state fails.
+    fn cancelled(&self) -> std::io::Result<()>;
 }
 
 /// A trait implemented for coroutines when Resume is ().
@@ -181,6 +249,9 @@ mod tests {
             CoroutineState::Complete(1),
             coroutine.resume_with(0).unwrap()
         );
+        // the returned value has been moved out
+        assert!(coroutine.resume_with(0).is_err());
+        assert_eq!(CoroutineState::Complete(1), coroutine.state());
     }
 
     #[test]
@@ -218,6 +289,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_yield_owned() {
+        let mut coroutine =
+            co!(
+                |suspender: &dyn Suspender<'_, Resume = Vec<u8>, Yield = String>, input| {
+                    assert_eq!(vec![1], input);
+                    assert_eq!(vec![2], suspender.suspend_with(String::from("hello")));
+                    Box::new(String::from("world"))
+                }
+            );
+        match coroutine.resume_with(vec![1]).unwrap() {
+            CoroutineState::Suspend(y, 0) => assert_eq!("hello", y),
+            _ => unreachable!("should never execute to here"),
+        }
+        match coroutine.resume_with(vec![2]).unwrap() {
+            CoroutineState::Complete(r) => assert_eq!("world", *r),
+            _ => unreachable!("should never execute to here"),
+        }
+        assert!(coroutine.resume_with(vec![3]).is_err());
+    }
+
     #[test]
     fn test_current() {
         assert!(CoroutineImpl::<i32, i32, i32>::current().is_none());
@@ -312,6 +404,40 @@ mod tests {
         assert_eq!(Some(3), coroutine.local().remove("1"));
     }
 
+    #[test]
+    fn test_coroutine_local() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+
+        #[derive(Default)]
+        struct Value(usize);
+
+        impl Drop for Value {
+            fn drop(&mut self) {
+                DROPPED.store(true, Ordering::Release);
+            }
+        }
+
+        crate::coroutine_local! {
+            static VALUE: RefCell<Value> = RefCell::new(Value::default());
+        }
+
+        let mut coroutine = co!(
+            |suspender: &dyn Suspender<'_, Resume = (), Yield = ()>, ()| {
+                VALUE.with(|v| v.borrow_mut().0 = 1);
+                suspender.suspend_with(());
+                let current = CoroutineImpl::<(), (), usize>::current().unwrap();
+                current.local().with(&VALUE, |v| v.borrow().0)
+            }
+        );
+        assert!(VALUE.try_with(|_| ()).is_none());
+        assert_eq!(CoroutineState::Suspend((), 0), coroutine.resume().unwrap());
+        assert!(!DROPPED.load(Ordering::Acquire));
+        assert_eq!(CoroutineState::Complete(1), coroutine.resume().unwrap());
+        assert!(DROPPED.load(Ordering::Acquire));
+    }
+
     #[test]
     fn test_panic() {
         let mut coroutine = co!(|_: &dyn Suspender<'_, Yield = (), Resume = ()>, ()| {
@@ -319,11 +445,50 @@ mod tests {
         });
         let result = coroutine.resume();
         assert!(result.is_ok());
-        let error = match result.unwrap() {
-            CoroutineState::Error(_) => true,
-            _ => false,
-        };
-        assert!(error);
+        assert_eq!(
+            CoroutineState::Error(CoroutineError::from("test panic, just ignore it")),
+            result.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        struct Guard(Arc<AtomicBool>);
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::Release);
+            }
+        }
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = dropped.clone();
+        let mut coroutine = co!(
+            move |suspender: &dyn Suspender<'_, Yield = (), Resume = ()>, ()| {
+                let _guard = Guard(flag);
+                suspender.suspend_with(());
+                unreachable!("should never execute to here");
+            }
+        );
+        assert_eq!(CoroutineState::Suspend((), 0), coroutine.resume().unwrap());
+        assert!(!dropped.load(Ordering::Acquire));
+        coroutine.cancel().unwrap();
+        assert!(dropped.load(Ordering::Acquire));
+        assert_eq!(CoroutineState::Cancelled, coroutine.state());
+        assert!(coroutine.resume().is_err());
+        assert!(coroutine.cancel().is_err());
+    }
+
+    #[test]
+    fn test_cancel_created() {
+        let mut coroutine = co!(|_: &dyn Suspender<'_, Yield = (), Resume = ()>, ()| {
+            unreachable!("should never execute to here");
+        });
+        coroutine.cancel().unwrap();
+        assert_eq!(CoroutineState::Cancelled, coroutine.state());
     }
 
     #[test]
@@ -342,6 +507,70 @@ mod tests {
         assert!(error);
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_trap_info() {
+        let mut coroutine = co!(|_: &dyn Suspender<'_, Yield = (), Resume = ()>, ()| {
+            unsafe { std::ptr::write_volatile(16 as *mut u8, 0) };
+        });
+        match coroutine.resume().unwrap() {
+            CoroutineState::Error(CoroutineError::Trap {
+                kind,
+                signum,
+                address,
+            }) => {
+                assert_eq!(error::TrapKind::InvalidMemoryReference, kind);
+                assert_eq!(libc::SIGSEGV, signum);
+                assert_eq!(16, address);
+            }
+            state => panic!("unexpected state {state}"),
+        }
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        fn recurse(depth: usize) -> usize {
+            let buf = [depth; 64];
+            _ = std::hint::black_box(&buf);
+            if depth == 0 {
+                return 0;
+            }
+            std::hint::black_box(recurse(depth - 1))
+        }
+        let stack_size = 16 * 1024;
+        let mut coroutine = co!(
+            String::from("overflow"),
+            |_: &dyn Suspender<'_, Yield = (), Resume = ()>, ()| {
+                _ = recurse(usize::MAX);
+            },
+            stack_size
+        );
+        assert_eq!(
+            CoroutineState::Error(CoroutineError::StackOverflow {
+                name: String::from("overflow"),
+                stack_size,
+            }),
+            coroutine.resume().unwrap()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stack_usage() {
+        let measure = stack::scoped_measure(true);
+        let mut coroutine = co!(|_: &dyn Suspender<'_, Yield = (), Resume = ()>, ()| {
+            let buf = [1u8; 8192];
+            _ = std::hint::black_box(&buf);
+        });
+        drop(measure);
+        assert_eq!(None, coroutine.stack_usage());
+        assert_eq!(CoroutineState::Complete(()), coroutine.resume().unwrap());
+        let stack_usage = coroutine.stack_usage().unwrap();
+        assert!(stack_usage >= 8192);
+        assert!(stack_usage < crate::constants::DEFAULT_STACK_SIZE);
+        assert!(stack::stats().max >= stack_usage);
+    }
+
     #[cfg(not(debug_assertions))]
     #[test]
     fn test_invalid_memory_reference() {
@@ -0,0 +1,405 @@
+cfg_if::cfg_if! {
+    if #[cfg(not(feature = "boost"))] {
+        use corosensei::stack::{DefaultStack, Stack, StackPointer};
+    } else {
+        use context::stack::{ProtectedFixedSizeStack as DefaultStack, Stack};
+        use std::io::{Error, ErrorKind};
+        use std::ops::Deref;
+    }
+}
+#[cfg(test)]
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The default max number of cached stacks per stack size in each thread.
+pub const DEFAULT_STACK_CACHE_SIZE: usize = 64;
+
+/// The default max bytes of cached stacks in each thread.
+pub const DEFAULT_STACK_CACHE_MEMORY: usize = 16 * 1024 * 1024;
+
+static CACHE_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_STACK_CACHE_SIZE);
+
+static CACHE_MEMORY: AtomicUsize = AtomicUsize::new(DEFAULT_STACK_CACHE_MEMORY);
+
+static MEASURE: AtomicBool = AtomicBool::new(false);
+
+static MEASURED_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+static MEASURED_MAX: AtomicUsize = AtomicUsize::new(0);
+
+static MEASURED_TOTAL: AtomicUsize = AtomicUsize::new(0);
+
+//the pattern used to paint the stack
+const PAINT: u8 = 0xA5;
+
+thread_local! {
+    static CACHE: RefCell<StackCache> = RefCell::new(StackCache::default());
+}
+
+#[cfg(test)]
+thread_local! {
+    //overrides `MEASURE` for stacks created by current thread, so tests don't race on it
+    static MEASURE_OVERRIDE: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+#[derive(Default)]
+struct StackCache {
+    stacks: HashMap<usize, Vec<DefaultStack>>,
+    memory: usize,
+}
+
+/// Get the max number of cached stacks per stack size in each thread.
+#[must_use]
+pub fn get_cache_size() -> usize {
+    CACHE_SIZE.load(Ordering::Relaxed)
+}
+
+/// Set the max number of cached stacks per stack size in each thread,
+/// `0` means disable the cache.
+pub fn set_cache_size(cache_size: usize) {
+    CACHE_SIZE.store(cache_size, Ordering::Relaxed);
+}
+
+/// Get the max bytes of cached stacks in each thread.
+#[must_use]
+pub fn get_cache_memory() -> usize {
+    CACHE_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Set the max bytes of cached stacks in each thread, `0` means disable the cache.
+pub fn set_cache_memory(cache_memory: usize) {
+    CACHE_MEMORY.store(cache_memory, Ordering::Relaxed);
+}
+
+/// Whether paint new stacks and measure the peak usage when coroutines finished.
+#[must_use]
+pub fn get_measure() -> bool {
+    #[cfg(test)]
+    if let Some(measure) = MEASURE_OVERRIDE.with(Cell::get) {
+        return measure;
+    }
+    MEASURE.load(Ordering::Relaxed)
+}
+
+/// Enable or disable the stack usage measurement, it's disabled by default,
+/// because painting stacks makes creating coroutines slower.
+/// Only works on unix for now.
+pub fn set_measure(measure: bool) {
+    MEASURE.store(measure, Ordering::Relaxed);
+}
+
+/// Override the measurement in current thread until the returned guard dropped.
+#[cfg(test)]
+pub(crate) fn scoped_measure(measure: bool) -> ScopedMeasure {
+    ScopedMeasure(MEASURE_OVERRIDE.with(|o| o.replace(Some(measure))))
+}
+
+#[cfg(test)]
+pub(crate) struct ScopedMeasure(Option<bool>);
+
+#[cfg(test)]
+impl Drop for ScopedMeasure {
+    fn drop(&mut self) {
+        MEASURE_OVERRIDE.with(|o| o.set(self.0));
+    }
+}
+
+/// The statistics of measured stack usages.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct StackStats {
+    /// The number of measured coroutines.
+    pub count: usize,
+    /// The max stack usage in bytes.
+    pub max: usize,
+    /// The sum of stack usages in bytes.
+    pub total: usize,
+}
+
+impl StackStats {
+    /// The average stack usage in bytes.
+    #[must_use]
+    pub fn avg(&self) -> usize {
+        self.total.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+/// Get the statistics of measured stack usages in all threads.
+#[must_use]
+pub fn stats() -> StackStats {
+    StackStats {
+        count: MEASURED_COUNT.load(Ordering::Relaxed),
+        max: MEASURED_MAX.load(Ordering::Relaxed),
+        total: MEASURED_TOTAL.load(Ordering::Relaxed),
+    }
+}
+
+/// Reset the statistics of measured stack usages.
+pub fn reset_stats() {
+    MEASURED_COUNT.store(0, Ordering::Relaxed);
+    MEASURED_MAX.store(0, Ordering::Relaxed);
+    MEASURED_TOTAL.store(0, Ordering::Relaxed);
+}
+
+/// Get the number of cached stacks with `stack_size` in current thread.
+#[must_use]
+pub fn cached(stack_size: usize) -> usize {
+    CACHE
+        .try_with(|cache| cache.borrow().stacks.get(&stack_size).map_or(0, Vec::len))
+        .unwrap_or(0)
+}
+
+/// Release all cached stacks in current thread.
+pub fn clear() {
+    _ = CACHE.try_with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.stacks.clear();
+        cache.memory = 0;
+    });
+}
+
+/// A stack which will be put back to the cache of the dropping thread.
+pub struct PooledStack {
+    stack_size: usize,
+    painted: bool,
+    inner: Option<DefaultStack>,
+}
+
+/// The address range of a stack, it's still available after the stack moved.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct StackInfo {
+    //the lowest address of the stack, the guard page starts from here
+    limit: usize,
+    base: usize,
+    painted: bool,
+}
+
+impl StackInfo {
+    /// Whether the `address` is in the guard page of the stack.
+    #[cfg(unix)]
+    pub(crate) fn in_guard_page(&self, address: usize) -> bool {
+        self.limit <= address && address < self.limit + crate::common::page_size()
+    }
+
+    /// Measure the peak usage of the painted stack and record it to `stats`.
+    ///
+    /// # Safety
+    /// the stack should not be released.
+    pub(crate) unsafe fn measure(&self) -> Option<usize> {
+        if !self.painted {
+            return None;
+        }
+        let bottom = self.limit + crate::common::page_size();
+        let painted = std::slice::from_raw_parts(bottom as *const u8, self.base - bottom);
+        let untouched = painted
+            .iter()
+            .position(|b| *b != PAINT)
+            .unwrap_or(painted.len());
+        let used = painted.len() - untouched;
+        _ = MEASURED_COUNT.fetch_add(1, Ordering::Relaxed);
+        _ = MEASURED_MAX.fetch_max(used, Ordering::Relaxed);
+        _ = MEASURED_TOTAL.fetch_add(used, Ordering::Relaxed);
+        Some(used)
+    }
+}
+
+impl PooledStack {
+    /// Take a cached stack with `stack_size` from current thread,
+    /// or allocate a new one if there is none.
+    ///
+    /// # Errors
+    /// if stack allocate failed.
+    pub fn new(stack_size: usize) -> std::io::Result<Self> {
+        let cached = CACHE
+            .try_with(|cache| {
+                let mut cache = cache.borrow_mut();
+                let stack = cache.stacks.get_mut(&stack_size)?.pop()?;
+                cache.memory -= stack_size;
+                Some(stack)
+            })
+            .ok()
+            .flatten();
+        let inner = match cached {
+            Some(stack) => stack,
+            None => Self::allocate(stack_size)?,
+        };
+        let mut stack = PooledStack {
+            stack_size,
+            painted: false,
+            inner: Some(inner),
+        };
+        #[cfg(unix)]
+        if get_measure() {
+            stack.paint();
+        }
+        Ok(stack)
+    }
+
+    //the guard page is not writable
+    #[cfg(unix)]
+    fn paint(&mut self) {
+        let (limit, base) = self.bounds();
+        let bottom = limit + crate::common::page_size();
+        unsafe { std::ptr::write_bytes(bottom as *mut u8, PAINT, base - bottom) };
+        self.painted = true;
+    }
+
+    pub(crate) fn info(&self) -> StackInfo {
+        let (limit, base) = self.bounds();
+        StackInfo {
+            limit,
+            base,
+            painted: self.painted,
+        }
+    }
+
+    cfg_if::cfg_if! {
+        if #[cfg(not(feature = "boost"))] {
+            fn allocate(stack_size: usize) -> std::io::Result<DefaultStack> {
+                DefaultStack::new(stack_size)
+            }
+
+            //the guard page starts from `limit`
+            fn bounds(&self) -> (usize, usize) {
+                (self.limit().get(), self.base().get())
+            }
+        } else {
+            fn allocate(stack_size: usize) -> std::io::Result<DefaultStack> {
+                DefaultStack::new(stack_size).map_err(|e| Error::new(ErrorKind::Other, e))
+            }
+
+            //the guard page is just below the `bottom`
+            fn bounds(&self) -> (usize, usize) {
+                (
+                    self.bottom() as usize - crate::common::page_size(),
+                    self.top() as usize,
+                )
+            }
+        }
+    }
+
+    fn inner(&self) -> &DefaultStack {
+        self.inner.as_ref().expect("stack already released")
+    }
+}
+
+impl Debug for PooledStack {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PooledStack")
+            .field("stack_size", &self.stack_size)
+            .field("painted", &self.painted)
+            .field("bounds", &self.inner.as_ref().map(|_| self.bounds()))
+            .finish()
+    }
+}
+
+impl Drop for PooledStack {
+    fn drop(&mut self) {
+        let Some(stack) = self.inner.take() else {
+            return;
+        };
+        let stack_size = self.stack_size;
+        // 线程销毁时缓存不可用，直接释放
+        _ = CACHE.try_with(move |cache| {
+            let mut cache = cache.borrow_mut();
+            if cache.memory + stack_size > get_cache_memory() {
+                return;
+            }
+            let stacks = cache.stacks.entry(stack_size).or_default();
+            if stacks.len() >= get_cache_size() {
+                return;
+            }
+            stacks.push(stack);
+            cache.memory += stack_size;
+        });
+    }
+}
+
+#[cfg(feature = "boost")]
+impl Deref for PooledStack {
+    type Target = Stack;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner()
+    }
+}
+
+#[cfg(not(feature = "boost"))]
+unsafe impl Stack for PooledStack {
+    #[inline]
+    fn base(&self) -> StackPointer {
+        self.inner().base()
+    }
+
+    #[inline]
+    fn limit(&self) -> StackPointer {
+        self.inner().limit()
+    }
+
+    #[inline]
+    #[cfg(windows)]
+    fn teb_fields(&self) -> corosensei::stack::StackTebFields {
+        self.inner().teb_fields()
+    }
+
+    #[inline]
+    #[cfg(windows)]
+    fn update_teb_fields(&mut self, stack_limit: usize, guaranteed_stack_bytes: usize) {
+        self.inner
+            .as_mut()
+            .expect("stack already released")
+            .update_teb_fields(stack_limit, guaranteed_stack_bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuse() {
+        let _settings = crate::common::SETTINGS_LOCK.lock().unwrap();
+        let stack_size = 128 * 1024;
+        clear();
+        let stack = PooledStack::new(stack_size).unwrap();
+        let bounds = stack.bounds();
+        drop(stack);
+        assert_eq!(1, cached(stack_size));
+        let stack = PooledStack::new(stack_size).unwrap();
+        assert_eq!(bounds, stack.bounds());
+        assert_eq!(0, cached(stack_size));
+        drop(stack);
+        clear();
+        assert_eq!(0, cached(stack_size));
+    }
+
+    #[test]
+    fn test_bounded() {
+        let _settings = crate::common::SETTINGS_LOCK.lock().unwrap();
+        let stack_size = 32 * 1024;
+        clear();
+        let stacks: Vec<PooledStack> = (0..=get_cache_size())
+            .map(|_| PooledStack::new(stack_size).unwrap())
+            .collect();
+        drop(stacks);
+        assert_eq!(get_cache_size(), cached(stack_size));
+        clear();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_measure() {
+        let mut stack = PooledStack::new(64 * 1024).unwrap();
+        stack.paint();
+        let info = stack.info();
+        assert_eq!(Some(0), unsafe { info.measure() });
+        let used = 1024;
+        unsafe { std::ptr::write_bytes((info.base - used) as *mut u8, 0, used) };
+        assert_eq!(Some(used), unsafe { info.measure() });
+        assert!(stats().count >= 2);
+        assert!(stats().max >= used);
+    }
+}
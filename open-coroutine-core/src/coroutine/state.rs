@@ -0,0 +1,191 @@
+use crate::constants::CoroutineState;
+use std::cell::Cell;
+use std::fmt::{Debug, Formatter};
+use std::mem::ManuallyDrop;
+
+/// The storage of `CoroutineState`.
+///
+/// The yielded/returned value is owned by the cell until `take` moves it out,
+/// so `Yield` and `Return` are not required to be `Copy`. When they are `Copy`,
+/// `get` is still a plain `Cell::get`.
+pub(crate) struct StateCell<Y, R> {
+    state: Cell<CoroutineState<ManuallyDrop<Y>, ManuallyDrop<R>>>,
+    //whether the value in `state` has not been moved out
+    owned: Cell<bool>,
+}
+
+impl<Y, R> StateCell<Y, R> {
+    pub(crate) fn new(state: CoroutineState<Y, R>) -> Self {
+        let cell = StateCell {
+            state: Cell::new(CoroutineState::Created),
+            owned: Cell::new(false),
+        };
+        cell.set(state);
+        cell
+    }
+
+    /// Returns a copy of the current state.
+    pub(crate) fn get(&self) -> CoroutineState<Y, R>
+    where
+        Y: Copy,
+        R: Copy,
+    {
+        unsafe { read(&*self.state.as_ptr()) }
+    }
+
+    /// Returns the current state without the yielded/returned value.
+    pub(crate) fn shape(&self) -> CoroutineState<(), ()> {
+        match unsafe { &*self.state.as_ptr() } {
+            CoroutineState::Created => CoroutineState::Created,
+            CoroutineState::Ready => CoroutineState::Ready,
+            CoroutineState::Running => CoroutineState::Running,
+            CoroutineState::Suspend(_, timestamp) => CoroutineState::Suspend((), *timestamp),
+            CoroutineState::SystemCall(_, syscall, state) => {
+                CoroutineState::SystemCall((), *syscall, *state)
+            }
+            CoroutineState::Complete(_) => CoroutineState::Complete(()),
+            CoroutineState::Error(error) => CoroutineState::Error(error.clone()),
+            CoroutineState::Cancelled => CoroutineState::Cancelled,
+        }
+    }
+
+    /// Replace the current state, the value still owned by the previous state will be dropped.
+    pub(crate) fn set(&self, state: CoroutineState<Y, R>) {
+        let owned = has_value(&state);
+        let previous = self.state.replace(wrap(state));
+        if self.owned.replace(owned) {
+            drop(unwrap(previous));
+        }
+    }
+
+    /// Move the current state out, the value in this cell can only be moved out once.
+    ///
+    /// # Panics
+    /// if the value has already been moved out.
+    pub(crate) fn take(&self) -> CoroutineState<Y, R> {
+        let state = unsafe { &*self.state.as_ptr() };
+        assert!(
+            self.owned.replace(false) || !has_value(state),
+            "the value of {} has already been moved out",
+            self.shape()
+        );
+        unsafe { read(state) }
+    }
+}
+
+impl<Y, R> Drop for StateCell<Y, R> {
+    fn drop(&mut self) {
+        if self.owned.replace(false) {
+            drop(unwrap(unsafe { std::ptr::read(self.state.as_ptr()) }));
+        }
+    }
+}
+
+impl<Y, R> Debug for StateCell<Y, R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.shape(), f)
+    }
+}
+
+fn has_value<Y, R>(state: &CoroutineState<Y, R>) -> bool {
+    matches!(
+        state,
+        CoroutineState::Suspend(_, _)
+            | CoroutineState::SystemCall(_, _, _)
+            | CoroutineState::Complete(_)
+    )
+}
+
+fn wrap<Y, R>(state: CoroutineState<Y, R>) -> CoroutineState<ManuallyDrop<Y>, ManuallyDrop<R>> {
+    match state {
+        CoroutineState::Created => CoroutineState::Created,
+        CoroutineState::Ready => CoroutineState::Ready,
+        CoroutineState::Running => CoroutineState::Running,
+        CoroutineState::Suspend(val, timestamp) => {
+            CoroutineState::Suspend(ManuallyDrop::new(val), timestamp)
+        }
+        CoroutineState::SystemCall(val, syscall, state) => {
+            CoroutineState::SystemCall(ManuallyDrop::new(val), syscall, state)
+        }
+        CoroutineState::Complete(val) => CoroutineState::Complete(ManuallyDrop::new(val)),
+        CoroutineState::Error(error) => CoroutineState::Error(error),
+        CoroutineState::Cancelled => CoroutineState::Cancelled,
+    }
+}
+
+fn unwrap<Y, R>(state: CoroutineState<ManuallyDrop<Y>, ManuallyDrop<R>>) -> CoroutineState<Y, R> {
+    match state {
+        CoroutineState::Created => CoroutineState::Created,
+        CoroutineState::Ready => CoroutineState::Ready,
+        CoroutineState::Running => CoroutineState::Running,
+        CoroutineState::Suspend(val, timestamp) => {
+            CoroutineState::Suspend(ManuallyDrop::into_inner(val), timestamp)
+        }
+        CoroutineState::SystemCall(val, syscall, state) => {
+            CoroutineState::SystemCall(ManuallyDrop::into_inner(val), syscall, state)
+        }
+        CoroutineState::Complete(val) => CoroutineState::Complete(ManuallyDrop::into_inner(val)),
+        CoroutineState::Error(error) => CoroutineState::Error(error),
+        CoroutineState::Cancelled => CoroutineState::Cancelled,
+    }
+}
+
+/// Copy the yielded/returned value bitwise, the error is cloned.
+unsafe fn read<Y, R>(
+    state: &CoroutineState<ManuallyDrop<Y>, ManuallyDrop<R>>,
+) -> CoroutineState<Y, R> {
+    match state {
+        CoroutineState::Created => CoroutineState::Created,
+        CoroutineState::Ready => CoroutineState::Ready,
+        CoroutineState::Running => CoroutineState::Running,
+        CoroutineState::Suspend(val, timestamp) => {
+            CoroutineState::Suspend(ManuallyDrop::into_inner(std::ptr::read(val)), *timestamp)
+        }
+        CoroutineState::SystemCall(val, syscall, state) => CoroutineState::SystemCall(
+            ManuallyDrop::into_inner(std::ptr::read(val)),
+            *syscall,
+            *state,
+        ),
+        CoroutineState::Complete(val) => {
+            CoroutineState::Complete(ManuallyDrop::into_inner(std::ptr::read(val)))
+        }
+        CoroutineState::Error(error) => CoroutineState::Error(error.clone()),
+        CoroutineState::Cancelled => CoroutineState::Cancelled,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_take() {
+        let value = Rc::new(1);
+        let cell = StateCell::<Rc<i32>, ()>::new(CoroutineState::Running);
+        cell.set(CoroutineState::Suspend(value.clone(), 0));
+        assert_eq!(2, Rc::strong_count(&value));
+        assert_eq!(CoroutineState::Suspend((), 0), cell.shape());
+        match cell.take() {
+            CoroutineState::Suspend(v, 0) => assert_eq!(1, *v),
+            _ => unreachable!("should never execute to here"),
+        }
+        assert_eq!(1, Rc::strong_count(&value));
+        // the moved out value should not be dropped again
+        cell.set(CoroutineState::Running);
+        assert_eq!(1, Rc::strong_count(&value));
+        // the owned value should be dropped
+        cell.set(CoroutineState::Suspend(value.clone(), 0));
+        drop(cell);
+        assert_eq!(1, Rc::strong_count(&value));
+    }
+
+    #[test]
+    fn test_get() {
+        let cell = StateCell::<i32, i32>::new(CoroutineState::Created);
+        cell.set(CoroutineState::Complete(1));
+        assert_eq!(CoroutineState::Complete(1), cell.get());
+        assert_eq!(CoroutineState::Complete(1), cell.take());
+        assert_eq!(CoroutineState::Complete(1), cell.get());
+    }
+}
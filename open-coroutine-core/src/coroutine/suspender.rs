@@ -102,7 +102,9 @@ where
 
     fn current() -> Option<&'s Self> {
         SUSPENDER.with(|s| {
-            s.borrow()
+            //抢占信号可能在切换时到达，此时视为没有可挂起的协程
+            s.try_borrow()
+                .ok()?
                 .front()
                 .map(|ptr| unsafe { &*(*ptr).cast::<SuspenderImpl<'s, Param, Yield>>() })
         })
@@ -113,14 +115,13 @@ where
     }
 }
 
-#[cfg(feature = "korosensei")]
-pub use korosensei::SuspenderImpl;
+#[cfg(any(feature = "korosensei", feature = "boost"))]
+pub use imp::SuspenderImpl;
 #[allow(missing_docs, missing_debug_implementations)]
-#[cfg(feature = "korosensei")]
-mod korosensei {
+#[cfg(any(feature = "korosensei", feature = "boost"))]
+mod imp {
     use crate::coroutine::suspender::Suspender;
-    use crate::coroutine::Current;
-    use corosensei::Yielder;
+    use crate::coroutine::{Current, Yielder};
     use std::panic::UnwindSafe;
 
     #[repr(C)]
@@ -139,13 +140,10 @@ mod korosensei {
 
         fn suspend_with(&self, arg: Self::Yield) -> Self::Resume {
             Self::clean_current();
+            crate::dump::capture();
             let param = self.0.suspend(arg);
             Self::init_current(self);
             param
         }
     }
 }
-
-#[allow(missing_docs, missing_debug_implementations)]
-#[cfg(all(feature = "boost", not(feature = "corosensei")))]
-mod boost {}
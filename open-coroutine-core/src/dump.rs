@@ -0,0 +1,369 @@
+use crate::common::Named;
+use crate::constants::CoroutineState;
+use crate::coroutine::error::CoroutineError;
+use crate::coroutine::{Coroutine, StateMachine};
+use crate::scheduler::listener::Listener;
+use crate::scheduler::SchedulableCoroutine;
+use dashmap::DashMap;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+static RECORD: AtomicBool = AtomicBool::new(false);
+
+static BACKTRACE: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Whether record the live coroutines of the new schedulers.
+#[must_use]
+pub fn get_record() -> bool {
+    RECORD.load(Ordering::Relaxed)
+}
+
+/// Enable or disable recording the live coroutines of the schedulers created after,
+/// it's disabled by default, because recording costs a global map update per switch.
+pub fn set_record(record: bool) {
+    RECORD.store(record, Ordering::Relaxed);
+}
+
+/// Whether capture the backtrace when coroutines suspend.
+#[must_use]
+pub fn get_backtrace() -> bool {
+    BACKTRACE.load(Ordering::Relaxed)
+}
+
+/// Enable or disable capturing the backtrace when coroutines suspend, only works with
+/// `set_record(true)`.
+///
+/// This is a debug mode and it's disabled by default: the stack of the coroutine must be
+/// unwound on every suspend, because it's gone once the coroutine resumes, which makes
+/// the switch orders of magnitude slower. Only the frames are collected then, the symbols
+/// are resolved lazily when the dump is formatted. Don't enable it in production.
+pub fn set_backtrace(backtrace: bool) {
+    BACKTRACE.store(backtrace, Ordering::Relaxed);
+}
+
+/// Capture the backtrace of current coroutine, which is going to suspend,
+/// does nothing but two loads unless the debug mode of `set_backtrace` is on.
+#[inline]
+pub(crate) fn capture() {
+    if get_record() && get_backtrace() {
+        CAPTURED.with(|c| _ = c.borrow_mut().replace(Backtrace::force_capture()));
+    }
+}
+
+/// The information of a live coroutine.
+#[derive(Debug, Clone)]
+pub struct CoroutineInfo {
+    /// The name of the scheduler which owns the coroutine.
+    pub scheduler: String,
+    /// The id of the coroutine.
+    pub id: usize,
+    /// The name of the coroutine.
+    pub name: String,
+    /// The state of the coroutine, including the syscall and the syscall state.
+    pub state: CoroutineState<(), ()>,
+    /// When the coroutine entered the state, in ns.
+    pub since: u64,
+    /// The backtrace of the suspended stack, it's only captured
+    /// when `set_backtrace(true)`, and symbolized when formatting.
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl CoroutineInfo {
+    /// How long the coroutine has been in the state.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(open_coroutine_timer::now().saturating_sub(self.since))
+    }
+}
+
+impl Display for CoroutineInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "coroutine #{} {} [{}, {:?}] in scheduler {}:",
+            self.id,
+            self.name,
+            self.state,
+            self.elapsed(),
+            self.scheduler
+        )?;
+        match &self.backtrace {
+            Some(backtrace) => writeln!(f, "{backtrace}"),
+            None => writeln!(f, "<backtrace not captured>"),
+        }
+    }
+}
+
+fn coroutines_map() -> &'static DashMap<usize, CoroutineInfo> {
+    static COROUTINES: OnceLock<DashMap<usize, CoroutineInfo>> = OnceLock::new();
+    COROUTINES.get_or_init(DashMap::new)
+}
+
+/// List all live coroutines owned by the schedulers in current process,
+/// sorted by the scheduler name and the coroutine id.
+#[must_use]
+pub fn coroutines() -> Vec<CoroutineInfo> {
+    let mut coroutines: Vec<CoroutineInfo> =
+        coroutines_map().iter().map(|r| r.value().clone()).collect();
+    coroutines.sort_by(|a, b| (&a.scheduler, a.id).cmp(&(&b.scheduler, b.id)));
+    coroutines
+}
+
+/// Format all live coroutines with their backtraces, like the goroutine dump.
+#[must_use]
+pub fn dump() -> String {
+    let coroutines = coroutines();
+    let mut dump = format!("open-coroutine dump: {} coroutines\n", coroutines.len());
+    for coroutine in coroutines {
+        dump.push('\n');
+        dump.push_str(&coroutine.to_string());
+    }
+    dump
+}
+
+/// Update the state of the coroutine, it's used when the state changed
+/// without notifying the `Listener`.
+pub(crate) fn update(coroutine: &SchedulableCoroutine) {
+    if !get_record() {
+        return;
+    }
+    if let Some(mut info) = coroutines_map().get_mut(&coroutine.id()) {
+        info.state = coroutine.state();
+        info.since = open_coroutine_timer::now();
+    }
+}
+
+/// Record the coroutines of a scheduler.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    scheduler: String,
+}
+
+impl Recorder {
+    pub(crate) fn new(scheduler: String) -> Self {
+        Recorder { scheduler }
+    }
+
+    fn suspended(coroutine: &SchedulableCoroutine) {
+        let backtrace = CAPTURED.with(|c| c.borrow_mut().take()).map(Arc::new);
+        if let Some(mut info) = coroutines_map().get_mut(&coroutine.id()) {
+            info.state = coroutine.state();
+            info.since = open_coroutine_timer::now();
+            info.backtrace = backtrace;
+        }
+    }
+
+    fn finished(coroutine: &SchedulableCoroutine) {
+        _ = coroutines_map().remove(&coroutine.id());
+    }
+}
+
+impl Listener for Recorder {
+    fn on_create(&self, coroutine: &SchedulableCoroutine) {
+        _ = coroutines_map().insert(
+            coroutine.id(),
+            CoroutineInfo {
+                scheduler: self.scheduler.clone(),
+                id: coroutine.id(),
+                name: coroutine.get_name().to_string(),
+                state: coroutine.state(),
+                since: open_coroutine_timer::now(),
+                backtrace: None,
+            },
+        );
+    }
+
+    fn on_resume(&self, _: u64, coroutine: &SchedulableCoroutine) {
+        // drop the backtrace not taken, it doesn't belong to this coroutine
+        CAPTURED.with(|c| _ = c.borrow_mut().take());
+        if let Some(mut info) = coroutines_map().get_mut(&coroutine.id()) {
+            info.state = CoroutineState::Running;
+            info.since = open_coroutine_timer::now();
+            info.backtrace = None;
+        }
+    }
+
+    fn on_suspend(&self, _: u64, coroutine: &SchedulableCoroutine) {
+        Self::suspended(coroutine);
+    }
+
+    fn on_syscall(
+        &self,
+        _: u64,
+        coroutine: &SchedulableCoroutine,
+        _: crate::constants::Syscall,
+        _: crate::constants::SyscallState,
+    ) {
+        Self::suspended(coroutine);
+    }
+
+    fn on_complete(&self, _: u64, coroutine: &SchedulableCoroutine, _: Option<usize>) {
+        Self::finished(coroutine);
+    }
+
+    fn on_error(&self, _: u64, coroutine: &SchedulableCoroutine, _: &CoroutineError) {
+        Self::finished(coroutine);
+    }
+
+    fn on_cancel(&self, _: u64, coroutine: &SchedulableCoroutine) {
+        Self::finished(coroutine);
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        coroutines_map().retain(|_, info| info.scheduler != self.scheduler);
+    }
+}
+
+#[cfg(unix)]
+pub use signal::dump_on_signal;
+
+#[cfg(unix)]
+mod signal {
+    use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+    use std::ffi::c_int;
+    use std::io::Error;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    // the write end of the pipe, the dump thread reads the read end
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn on_signal(_: c_int) {
+        //信号处理函数中只能调用异步信号安全的函数，由单独的线程输出
+        let fd = PIPE.load(Ordering::Acquire);
+        if fd >= 0 {
+            _ = unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+        }
+    }
+
+    /// Dump all live coroutines to stderr when receive the `signal`,
+    /// for example `SIGQUIT`, like the goroutine dump, see `set_record`.
+    ///
+    /// # Errors
+    /// if create the pipe, start the dump thread or install the signal handler failed.
+    pub fn dump_on_signal(signal: Signal) -> std::io::Result<()> {
+        if PIPE.load(Ordering::Acquire) < 0 {
+            let mut fds = [0; 2];
+            if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+                return Err(Error::last_os_error());
+            }
+            let [read, write] = fds;
+            if PIPE
+                .compare_exchange(-1, write, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                _ = std::thread::Builder::new()
+                    .name(String::from("open-coroutine-dump"))
+                    .spawn(move || {
+                        let mut buf = [0u8; 1];
+                        while unsafe { libc::read(read, buf.as_mut_ptr().cast(), 1) } > 0 {
+                            eprintln!("{}", super::dump());
+                        }
+                    })?;
+            } else {
+                unsafe {
+                    _ = libc::close(read);
+                    _ = libc::close(write);
+                }
+            }
+        }
+        let sa = SigAction::new(
+            SigHandler::Handler(on_signal),
+            SaFlags::SA_RESTART,
+            SigSet::empty(),
+        );
+        _ = unsafe { sigaction(signal, &sa)? };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::DEFAULT_STACK_SIZE;
+    use crate::coroutine::suspender::SimpleSuspender;
+    use crate::coroutine::{Coroutine, SimpleCoroutine};
+
+    #[test]
+    fn test_dump() -> std::io::Result<()> {
+        let _settings = crate::common::SETTINGS_LOCK.lock().unwrap();
+        let (record, backtrace) = (get_record(), get_backtrace());
+        set_record(true);
+        set_backtrace(true);
+        let recorder = Recorder::new(String::from("test_dump"));
+        let mut coroutine = SchedulableCoroutine::new(
+            String::from("test_dump|coroutine"),
+            |suspender, ()| suspender.suspend(),
+            DEFAULT_STACK_SIZE,
+        )?;
+        recorder.on_create(&coroutine);
+        recorder.on_resume(0, &coroutine);
+        assert!(matches!(
+            coroutine.resume()?,
+            CoroutineState::Suspend((), 0)
+        ));
+        recorder.on_suspend(0, &coroutine);
+        let infos: Vec<CoroutineInfo> = coroutines()
+            .into_iter()
+            .filter(|c| c.scheduler == "test_dump")
+            .collect();
+        assert_eq!(1, infos.len());
+        let info = &infos[0];
+        assert_eq!("test_dump|coroutine", info.name);
+        assert!(matches!(info.state, CoroutineState::Suspend((), 0)));
+        assert!(info.backtrace.is_some());
+        assert!(dump().contains(&info.name));
+        recorder.on_resume(0, &coroutine);
+        assert!(matches!(coroutine.resume()?, CoroutineState::Complete(())));
+        recorder.on_complete(0, &coroutine, None);
+        assert!(coroutines().iter().all(|c| c.scheduler != "test_dump"));
+        set_record(record);
+        set_backtrace(backtrace);
+        Ok(())
+    }
+
+    #[test]
+    fn test_capture_only_in_debug_mode() {
+        let _settings = crate::common::SETTINGS_LOCK.lock().unwrap();
+        let (record, backtrace) = (get_record(), get_backtrace());
+        set_record(true);
+        set_backtrace(false);
+        capture();
+        assert!(CAPTURED.with(|c| c.borrow().is_none()));
+        set_backtrace(true);
+        capture();
+        assert!(CAPTURED.with(|c| c.borrow_mut().take().is_some()));
+        set_record(record);
+        set_backtrace(backtrace);
+    }
+
+    #[test]
+    fn test_drop_recorder() -> std::io::Result<()> {
+        let recorder = Recorder::new(String::from("test_drop_recorder"));
+        let coroutine = SchedulableCoroutine::new(
+            String::from("test_drop_recorder|coroutine"),
+            |_, ()| {},
+            DEFAULT_STACK_SIZE,
+        )?;
+        recorder.on_create(&coroutine);
+        assert!(coroutines()
+            .iter()
+            .any(|c| c.scheduler == "test_drop_recorder"));
+        // the scheduler is dropped without finishing the coroutine
+        drop(recorder);
+        assert!(coroutines()
+            .iter()
+            .all(|c| c.scheduler != "test_drop_recorder"));
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+use crate::common::Current;
+use crate::constants::{Syscall, SyscallState};
+use crate::coroutine::suspender::SimpleSuspender;
+use crate::coroutine::{Coroutine, StateMachine};
+use crate::scheduler::{SchedulableCoroutine, SchedulableSuspender, SchedulerImpl};
+use crossbeam_deque::{Injector, Steal};
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+use std::time::Duration;
+
+/// The woken coroutines of a scheduler, it's shared with the `CoroutineWaker`s,
+/// so waking after the scheduler dropped is safe.
+#[derive(Default)]
+pub(crate) struct WakeQueue {
+    wakers: Injector<Arc<CoroutineWaker>>,
+    // interrupt the scheduling thread which is blocking, e.g. in the selector
+    notify: OnceLock<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl WakeQueue {
+    pub(crate) fn push(&self, waker: Arc<CoroutineWaker>) {
+        self.wakers.push(waker);
+        if let Some(notify) = self.notify.get() {
+            notify();
+        }
+    }
+
+    pub(crate) fn steal(&self) -> Steal<Arc<CoroutineWaker>> {
+        self.wakers.steal()
+    }
+
+    /// Set the `notify` called after pushed, only the first one takes effect.
+    pub(crate) fn set_notify(&self, notify: impl Fn() + Send + Sync + 'static) {
+        _ = self.notify.set(Box::new(notify));
+    }
+}
+
+impl Debug for WakeQueue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WakeQueue")
+            .field("wakers", &self.wakers.len())
+            .field("notify", &self.notify.get().is_some())
+            .finish()
+    }
+}
+
+/// Wake a coroutine parked in `block_on`.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct CoroutineWaker {
+    // the wake queue of the scheduler which the coroutine is parked in
+    queue: Mutex<Weak<WakeQueue>>,
+    co_id: usize,
+    parked: AtomicBool,
+    woken: AtomicBool,
+}
+
+impl CoroutineWaker {
+    pub(crate) fn co_id(&self) -> usize {
+        self.co_id
+    }
+
+    /// Returns `true` if the coroutine is parked in the syscall table.
+    pub(crate) fn parked(&self) -> bool {
+        self.parked.load(Ordering::SeqCst)
+    }
+}
+
+impl Wake for CoroutineWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+        if self.parked() {
+            // 交给调度线程处理，此时协程可能还未放入系统调用表
+            let queue = self.queue.lock().expect("lock failed").upgrade();
+            if let Some(queue) = queue {
+                queue.push(self.clone());
+            }
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Run the `future` to completion.
+///
+/// Inside a coroutine, the coroutine is suspended until the future's `Waker` fires,
+/// then it will be resumed by `Scheduler::try_resume`, so the thread can run other
+/// coroutines meanwhile. Outside a coroutine, the current thread is parked instead.
+///
+/// # Panics
+/// if change the coroutine state failed.
+///
+/// # Examples
+/// ```
+/// use open_coroutine_core::future::block_on;
+///
+/// assert_eq!(1, block_on(async { 1 }));
+/// ```
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    park(future, None).expect("block_on should never timeout")
+}
+
+/// Like `block_on`, but gives up after `timeout`, returns `None` if the future
+/// is not ready in time.
+///
+/// # Panics
+/// if change the coroutine state failed.
+///
+/// # Examples
+/// ```
+/// use open_coroutine_core::future::block_on_timeout;
+/// use std::time::Duration;
+///
+/// let pending = std::future::pending::<()>();
+/// assert_eq!(None, block_on_timeout(pending, Duration::from_millis(10)));
+/// ```
+pub fn block_on_timeout<F: Future>(future: F, timeout: Duration) -> Option<F::Output> {
+    block_on_until(future, open_coroutine_timer::get_timeout_time(timeout))
+}
+
+/// Like `block_on`, but gives up at the `deadline` in ns.
+pub(crate) fn block_on_until<F: Future>(future: F, deadline: u64) -> Option<F::Output> {
+    park(future, Some(deadline))
+}
+
+fn park<F: Future>(future: F, deadline: Option<u64>) -> Option<F::Output> {
+    let mut future = std::pin::pin!(future);
+    if let (Some(coroutine), Some(suspender), Some(_)) = (
+        SchedulableCoroutine::current(),
+        SchedulableSuspender::current(),
+        SchedulerImpl::current(),
+    ) {
+        let waker = Arc::new(CoroutineWaker {
+            queue: Mutex::new(Weak::new()),
+            co_id: coroutine.id(),
+            parked: AtomicBool::new(false),
+            woken: AtomicBool::new(false),
+        });
+        let std_waker = Waker::from(waker.clone());
+        let mut cx = Context::from_waker(&std_waker);
+        loop {
+            // the coroutine may be stolen by another scheduler after resumed
+            if let Some(scheduler) = SchedulerImpl::current() {
+                *waker.queue.lock().expect("lock failed") = Arc::downgrade(scheduler.wake_queue());
+            }
+            waker.woken.store(false, Ordering::SeqCst);
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return Some(output);
+            }
+            let state = match deadline {
+                Some(deadline) if deadline <= open_coroutine_timer::now() => return None,
+                // 超时后由调度器放回就绪队列
+                Some(deadline) => SyscallState::Suspend(deadline),
+                None => SyscallState::Computing,
+            };
+            waker.parked.store(true, Ordering::SeqCst);
+            if !waker.woken.load(Ordering::SeqCst) {
+                // 停在系统调用表中，等待Waker通过try_resume唤醒
+                if let Some(coroutine) = SchedulableCoroutine::current() {
+                    coroutine
+                        .syscall((), Syscall::park, state)
+                        .expect("change to syscall state failed !");
+                }
+                suspender.suspend();
+                //协程在调度器的队列间移动，恢复后要重新获取
+                if let Some(coroutine) = SchedulableCoroutine::current() {
+                    coroutine
+                        .syscall((), Syscall::park, SyscallState::Finished)
+                        .expect("change to syscall state failed !");
+                    coroutine
+                        .syscall_resume()
+                        .expect("change to running state failed !");
+                }
+            }
+            waker.parked.store(false, Ordering::SeqCst);
+        }
+    }
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return Some(output);
+        }
+        match deadline {
+            Some(deadline) => {
+                let now = open_coroutine_timer::now();
+                if deadline <= now {
+                    return None;
+                }
+                std::thread::park_timeout(Duration::from_nanos(deadline - now));
+            }
+            None => std::thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct Shared {
+        value: Option<usize>,
+        waker: Option<Waker>,
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Oneshot(Arc<Mutex<Shared>>);
+
+    impl Oneshot {
+        fn send(&self, value: usize) {
+            let mut shared = self.0.lock().unwrap();
+            shared.value = Some(value);
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        }
+
+        fn send_later(&self, value: usize, delay: Duration) {
+            let sender = self.clone();
+            _ = std::thread::spawn(move || {
+                std::thread::sleep(delay);
+                sender.send(value);
+            });
+        }
+    }
+
+    impl Future for Oneshot {
+        type Output = usize;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let mut shared = self.0.lock().unwrap();
+            if let Some(value) = shared.value.take() {
+                return Poll::Ready(value);
+            }
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn test_block_on_thread() {
+        let oneshot = Oneshot::default();
+        oneshot.send_later(1, Duration::from_millis(10));
+        assert_eq!(1, block_on(oneshot));
+    }
+
+    #[test]
+    fn test_block_on_timeout_thread() {
+        let oneshot = Oneshot::default();
+        oneshot.send_later(1, Duration::from_millis(500));
+        assert_eq!(
+            None,
+            block_on_timeout(oneshot.clone(), Duration::from_millis(10))
+        );
+        assert_eq!(Some(1), block_on_timeout(oneshot, Duration::from_secs(3)));
+    }
+}
@@ -64,9 +64,24 @@ pub mod common;
 /// Scheduler abstraction and impl.
 pub mod scheduler;
 
+/// Dump the live coroutines.
+pub mod dump;
+
+/// Bridge between std futures and coroutines.
+pub mod future;
+
+/// Metrics of the schedulers and pools.
+pub mod metrics;
+
+/// Record the timeline in the Chrome Trace Event Format.
+pub mod trace;
+
 /// Coroutine pool abstraction and impl.
 pub mod pool;
 
+/// Coroutine-aware synchronization primitives.
+pub mod sync;
+
 /// Monitor abstraction and impl.
 #[cfg(all(unix, feature = "preemptive-schedule"))]
 pub mod monitor;
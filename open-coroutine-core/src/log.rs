@@ -1,4 +1,5 @@
-/// init log framework.
+/// init log framework, with the `tracing` feature the events are emitted by
+/// `tracing` instead, and the subscriber should be installed by the application.
 #[allow(box_pointers)]
 #[cfg(feature = "logs")]
 pub fn init() {
@@ -31,9 +32,15 @@ macro_rules! info {
     // info!(target: "my_target", "a {} event", "log")
     (target: $target:expr, $($arg:tt)+) => {
         cfg_if::cfg_if! {
-            if #[cfg(feature = "logs")] {
+            if #[cfg(feature = "tracing")] {
+                tracing::info!(target: $target, $($arg)+)
+            } else if #[cfg(feature = "logs")] {
                 $crate::log::init();
                 log::info!(target: $target, $($arg)+)
+            } else {
+                if false {
+                    _ = ($target, format_args!($($arg)+));
+                }
             }
         }
     };
@@ -41,9 +48,16 @@ macro_rules! info {
     // info!("a {} event", "log")
     ($($arg:tt)+) => {
         cfg_if::cfg_if! {
-            if #[cfg(feature = "logs")] {
+            if #[cfg(feature = "tracing")] {
+                tracing::info!($($arg)+)
+            } else if #[cfg(feature = "logs")] {
                 $crate::log::init();
                 log::info!($($arg)+)
+            } else {
+                //不输出日志时也使用参数，避免只用于日志的变量报未使用
+                if false {
+                    _ = format_args!($($arg)+);
+                }
             }
         }
     }
@@ -55,9 +69,15 @@ macro_rules! warn {
     // warn!(target: "my_target", "a {} event", "log")
     (target: $target:expr, $($arg:tt)+) => {
         cfg_if::cfg_if! {
-            if #[cfg(feature = "logs")] {
-                 $crate::log::init();
+            if #[cfg(feature = "tracing")] {
+                tracing::warn!(target: $target, $($arg)+)
+            } else if #[cfg(feature = "logs")] {
+                $crate::log::init();
                 log::warn!(target: $target, $($arg)+)
+            } else {
+                if false {
+                    _ = ($target, format_args!($($arg)+));
+                }
             }
         }
     };
@@ -65,9 +85,16 @@ macro_rules! warn {
     // warn!("a {} event", "log")
     ($($arg:tt)+) => {
         cfg_if::cfg_if! {
-            if #[cfg(feature = "logs")] {
-               $crate::log::init();
+            if #[cfg(feature = "tracing")] {
+                tracing::warn!($($arg)+)
+            } else if #[cfg(feature = "logs")] {
+                $crate::log::init();
                 log::warn!($($arg)+)
+            } else {
+                //不输出日志时也使用参数，避免只用于日志的变量报未使用
+                if false {
+                    _ = format_args!($($arg)+);
+                }
             }
         }
     }
@@ -79,9 +106,15 @@ macro_rules! error {
     // error!(target: "my_target", "a {} event", "log")
     (target: $target:expr, $($arg:tt)+) => {
         cfg_if::cfg_if! {
-            if #[cfg(feature = "logs")] {
-                 $crate::log::init();
+            if #[cfg(feature = "tracing")] {
+                tracing::error!(target: $target, $($arg)+)
+            } else if #[cfg(feature = "logs")] {
+                $crate::log::init();
                 log::error!(target: $target, $($arg)+)
+            } else {
+                if false {
+                    _ = ($target, format_args!($($arg)+));
+                }
             }
         }
 
@@ -90,11 +123,167 @@ macro_rules! error {
     // error!("a {} event", "log")
     ($($arg:tt)+) => {
         cfg_if::cfg_if! {
-            if #[cfg(feature = "logs")] {
-                 $crate::log::init();
+            if #[cfg(feature = "tracing")] {
+                tracing::error!($($arg)+)
+            } else if #[cfg(feature = "logs")] {
+                $crate::log::init();
                 log::error!($($arg)+)
+            } else {
+                //不输出日志时也使用参数，避免只用于日志的变量报未使用
+                if false {
+                    _ = format_args!($($arg)+);
+                }
             }
         }
 
     }
 }
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    static EVENT_LOOP: std::cell::RefCell<Option<std::sync::Arc<str>>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Mark current thread as scheduling the event loop until the guard is dropped.
+#[cfg(feature = "tracing")]
+#[derive(Debug)]
+pub(crate) struct EventLoopGuard(Option<std::sync::Arc<str>>);
+
+#[cfg(feature = "tracing")]
+impl EventLoopGuard {
+    pub(crate) fn enter(event_loop: &std::sync::Arc<str>) -> Self {
+        EventLoopGuard(EVENT_LOOP.with(|e| e.replace(Some(event_loop.clone()))))
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for EventLoopGuard {
+    fn drop(&mut self) {
+        EVENT_LOOP.with(|e| *e.borrow_mut() = self.0.take());
+    }
+}
+
+/// A `Listener` which gives every coroutine a `tracing` span, the fields of the span
+/// are the name of the coroutine, the pool and the event loop which runs it lately.
+///
+/// The span is built when the coroutine resumes first time if the subscriber enables it,
+/// it's entered when the coroutine resumes, and exited when it suspends, enters a syscall,
+/// completes or fails, so the events inside the coroutine are attributed to it, even if
+/// it's resumed in another thread.
+///
+/// Every state transition is emitted as a `trace` event with the `co`, `from` and `to` fields.
+#[cfg(feature = "tracing")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct TracingListener {
+    pool: String,
+    spans: dashmap::DashMap<usize, tracing::Span>,
+}
+
+#[cfg(feature = "tracing")]
+impl TracingListener {
+    /// Create a listener for the named pool or scheduler.
+    #[must_use]
+    pub fn new(pool: String) -> Self {
+        TracingListener {
+            pool,
+            spans: dashmap::DashMap::new(),
+        }
+    }
+
+    fn transition(
+        coroutine: &crate::scheduler::SchedulableCoroutine,
+        from: &dyn std::fmt::Display,
+        to: &dyn std::fmt::Display,
+    ) {
+        use crate::common::Named;
+        tracing::trace!(
+            target: "open_coroutine::coroutine",
+            co = coroutine.get_name(),
+            from = %from,
+            to = %to,
+            "state changed"
+        );
+    }
+
+    fn exit(&self, coroutine: &crate::scheduler::SchedulableCoroutine) {
+        use crate::coroutine::{Coroutine, StateMachine};
+        Self::transition(coroutine, &"Running", &coroutine.state());
+        if let Some(span) = self.spans.get(&coroutine.id()) {
+            _ = span.with_subscriber(|(id, dispatch)| dispatch.exit(id));
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl crate::scheduler::listener::Listener for TracingListener {
+    fn on_resume(&self, _: u64, coroutine: &crate::scheduler::SchedulableCoroutine) {
+        use crate::common::Named;
+        use crate::coroutine::{Coroutine, StateMachine};
+        Self::transition(coroutine, &coroutine.state(), &"Running");
+        let co_id = coroutine.id();
+        //订阅者不关心时不构造span
+        if !self.spans.contains_key(&co_id)
+            && !tracing::span_enabled!(target: "open_coroutine::coroutine", tracing::Level::INFO)
+        {
+            return;
+        }
+        let span = self.spans.entry(co_id).or_insert_with(|| {
+            tracing::info_span!(
+                target: "open_coroutine::coroutine",
+                "coroutine",
+                name = coroutine.get_name(),
+                pool = self.pool.as_str(),
+                event_loop = tracing::field::Empty,
+            )
+        });
+        EVENT_LOOP.with(|e| {
+            if let Some(event_loop) = e.borrow().as_deref() {
+                _ = span.record("event_loop", event_loop);
+            }
+        });
+        _ = span.with_subscriber(|(id, dispatch)| dispatch.enter(id));
+    }
+
+    fn on_suspend(&self, _: u64, coroutine: &crate::scheduler::SchedulableCoroutine) {
+        self.exit(coroutine);
+    }
+
+    fn on_syscall(
+        &self,
+        _: u64,
+        coroutine: &crate::scheduler::SchedulableCoroutine,
+        _: crate::constants::Syscall,
+        _: crate::constants::SyscallState,
+    ) {
+        self.exit(coroutine);
+    }
+
+    fn on_complete(
+        &self,
+        _: u64,
+        coroutine: &crate::scheduler::SchedulableCoroutine,
+        _: Option<usize>,
+    ) {
+        use crate::coroutine::Coroutine;
+        self.exit(coroutine);
+        _ = self.spans.remove(&coroutine.id());
+    }
+
+    fn on_error(
+        &self,
+        _: u64,
+        coroutine: &crate::scheduler::SchedulableCoroutine,
+        _: &crate::coroutine::error::CoroutineError,
+    ) {
+        use crate::coroutine::Coroutine;
+        self.exit(coroutine);
+        _ = self.spans.remove(&coroutine.id());
+    }
+
+    fn on_cancel(&self, _: u64, coroutine: &crate::scheduler::SchedulableCoroutine) {
+        use crate::coroutine::{Coroutine, StateMachine};
+        Self::transition(coroutine, &coroutine.state(), &"Cancelled");
+        _ = self.spans.remove(&coroutine.id());
+    }
+}
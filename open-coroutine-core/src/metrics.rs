@@ -0,0 +1,621 @@
+use crate::constants::Syscall;
+use crate::coroutine::error::CoroutineError;
+use crate::coroutine::Coroutine;
+use crate::scheduler::listener::Listener;
+use crate::scheduler::SchedulableCoroutine;
+use dashmap::DashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+static ENABLE: AtomicBool = AtomicBool::new(false);
+
+//注册表的键，名称可能重复
+static ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether collect the metrics of the new schedulers.
+#[must_use]
+pub fn get_enable() -> bool {
+    ENABLE.load(Ordering::Relaxed)
+}
+
+/// Enable or disable adding a `MetricsListener` to the pools created after,
+/// it's disabled by default, because the listener costs a map update per switch.
+pub fn set_enable(enable: bool) {
+    ENABLE.store(enable, Ordering::Relaxed);
+}
+
+/// The upper bounds of the histogram buckets in ns, from 1us to 10s.
+pub const BUCKETS: [u64; 8] = [
+    1_000,
+    10_000,
+    100_000,
+    1_000_000,
+    10_000_000,
+    100_000_000,
+    1_000_000_000,
+    10_000_000_000,
+];
+
+const READY: &str = "Ready";
+const RUNNING: &str = "Running";
+const SUSPEND: &str = "Suspend";
+const SYSTEM_CALL: &str = "SystemCall";
+const STATES: [&str; 4] = [READY, RUNNING, SUSPEND, SYSTEM_CALL];
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct Histogram {
+    //最后一个桶是+Inf
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    sum: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, value: u64) {
+        let index = BUCKETS
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(BUCKETS.len());
+        _ = self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        _ = self.sum.fetch_add(value, Ordering::Relaxed);
+        _ = self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = BUCKETS
+            .iter()
+            .chain(std::iter::once(&u64::MAX))
+            .zip(&self.buckets)
+            .map(|(bound, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                (*bound, cumulative)
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            sum: self.sum.load(Ordering::Relaxed),
+            count: self.count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The snapshot of a histogram of durations.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HistogramSnapshot {
+    /// The upper bound in ns and the cumulative count of each bucket,
+    /// the last bound is `u64::MAX`, which means `+Inf`.
+    pub buckets: Vec<(u64, u64)>,
+    /// The sum of all observed durations in ns.
+    pub sum: u64,
+    /// The number of observed durations.
+    pub count: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct SchedulerMetrics {
+    created: AtomicU64,
+    completed: AtomicU64,
+    errored: AtomicU64,
+    cancelled: AtomicU64,
+    suspended: AtomicU64,
+    deadline_missed: AtomicU64,
+    syscalls: DashMap<Syscall, AtomicU64>,
+    state_time: DashMap<&'static str, AtomicU64>,
+    run_slices: Histogram,
+    //协程当前的状态及进入该状态的时间
+    current: DashMap<usize, (&'static str, u64)>,
+}
+
+impl SchedulerMetrics {
+    fn transition(&self, co_id: usize, state: Option<&'static str>) {
+        let now = open_coroutine_timer::now();
+        let previous = match state {
+            Some(state) => self.current.insert(co_id, (state, now)),
+            None => self.current.remove(&co_id).map(|(_, v)| v),
+        };
+        if let Some((previous, since)) = previous {
+            let elapsed = now.saturating_sub(since);
+            _ = self
+                .state_time
+                .entry(previous)
+                .or_default()
+                .fetch_add(elapsed, Ordering::Relaxed);
+            if RUNNING == previous {
+                self.run_slices.observe(elapsed);
+            }
+        }
+    }
+
+    fn snapshot(&self, name: &str) -> SchedulerSnapshot {
+        let mut syscalls: Vec<(Syscall, u64)> = self
+            .syscalls
+            .iter()
+            .map(|r| (*r.key(), r.value().load(Ordering::Relaxed)))
+            .collect();
+        syscalls.sort_by_key(|(syscall, _)| syscall.to_string());
+        SchedulerSnapshot {
+            name: String::from(name),
+            created: self.created.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            errored: self.errored.load(Ordering::Relaxed),
+            cancelled: self.cancelled.load(Ordering::Relaxed),
+            suspended: self.suspended.load(Ordering::Relaxed),
+            deadline_missed: self.deadline_missed.load(Ordering::Relaxed),
+            syscalls,
+            state_time: STATES
+                .iter()
+                .map(|state| {
+                    let time = self
+                        .state_time
+                        .get(state)
+                        .map_or(0, |r| r.value().load(Ordering::Relaxed));
+                    (*state, time)
+                })
+                .collect(),
+            run_slices: self.run_slices.snapshot(),
+        }
+    }
+}
+
+/// The gauges and counters of a pool.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct PoolMetrics {
+    id: usize,
+    name: String,
+    pub(crate) queued: AtomicUsize,
+    pub(crate) running: AtomicUsize,
+    pub(crate) grown: AtomicU64,
+    pub(crate) shrunk: AtomicU64,
+}
+
+impl PoolMetrics {
+    /// Register the metrics of the pool, it's unregistered by `unregister`.
+    pub(crate) fn register(pool: &str) -> Arc<Self> {
+        let metrics = Arc::new(PoolMetrics {
+            id: ID.fetch_add(1, Ordering::Relaxed),
+            name: String::from(pool),
+            queued: AtomicUsize::new(0),
+            running: AtomicUsize::new(0),
+            grown: AtomicU64::new(0),
+            shrunk: AtomicU64::new(0),
+        });
+        _ = pools().insert(metrics.id, metrics.clone());
+        metrics
+    }
+
+    /// Unregister the metrics of the pool.
+    pub(crate) fn unregister(&self) {
+        _ = pools().remove(&self.id);
+    }
+}
+
+fn schedulers() -> &'static DashMap<usize, (String, Arc<SchedulerMetrics>)> {
+    static SCHEDULERS: OnceLock<DashMap<usize, (String, Arc<SchedulerMetrics>)>> = OnceLock::new();
+    SCHEDULERS.get_or_init(DashMap::new)
+}
+
+fn pools() -> &'static DashMap<usize, Arc<PoolMetrics>> {
+    static POOLS: OnceLock<DashMap<usize, Arc<PoolMetrics>>> = OnceLock::new();
+    POOLS.get_or_init(DashMap::new)
+}
+
+/// A `Listener` which keeps the counters and histograms of a scheduler,
+/// the metrics are available by `snapshot` until the listener is dropped.
+///
+/// The time of a state is counted until the next callback, so the time a
+/// coroutine waits in the ready queue after a suspension or a syscall is
+/// counted as `Suspend` or `SystemCall`.
+///
+/// The pools add it to their workers when `set_enable(true)`.
+///
+/// # Examples
+/// ```
+/// use open_coroutine_core::common::Named;
+/// use open_coroutine_core::metrics::MetricsListener;
+/// use open_coroutine_core::scheduler::{Scheduler, SchedulerImpl};
+///
+/// let mut scheduler = SchedulerImpl::default();
+/// scheduler.add_listener(MetricsListener::new(scheduler.get_name()));
+/// ```
+#[repr(C)]
+#[derive(Debug)]
+pub struct MetricsListener {
+    id: usize,
+    metrics: Arc<SchedulerMetrics>,
+}
+
+impl MetricsListener {
+    /// Create a listener for the named scheduler, the schedulers with the
+    /// same name are reported separately.
+    #[must_use]
+    pub fn new(scheduler: &str) -> Self {
+        let id = ID.fetch_add(1, Ordering::Relaxed);
+        let metrics = Arc::new(SchedulerMetrics::default());
+        _ = schedulers().insert(id, (String::from(scheduler), metrics.clone()));
+        MetricsListener { id, metrics }
+    }
+}
+
+impl Drop for MetricsListener {
+    fn drop(&mut self) {
+        _ = schedulers().remove(&self.id);
+    }
+}
+
+impl Listener for MetricsListener {
+    fn on_create(&self, coroutine: &SchedulableCoroutine) {
+        _ = self.metrics.created.fetch_add(1, Ordering::Relaxed);
+        self.metrics.transition(coroutine.id(), Some(READY));
+    }
+
+    fn on_resume(&self, _: u64, coroutine: &SchedulableCoroutine) {
+        self.metrics.transition(coroutine.id(), Some(RUNNING));
+    }
+
+    fn on_suspend(&self, _: u64, coroutine: &SchedulableCoroutine) {
+        _ = self.metrics.suspended.fetch_add(1, Ordering::Relaxed);
+        self.metrics.transition(coroutine.id(), Some(SUSPEND));
+    }
+
+    fn on_syscall(
+        &self,
+        _: u64,
+        coroutine: &SchedulableCoroutine,
+        syscall: Syscall,
+        _: crate::constants::SyscallState,
+    ) {
+        _ = self
+            .metrics
+            .syscalls
+            .entry(syscall)
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+        self.metrics.transition(coroutine.id(), Some(SYSTEM_CALL));
+    }
+
+    fn on_complete(&self, _: u64, coroutine: &SchedulableCoroutine, _: Option<usize>) {
+        _ = self.metrics.completed.fetch_add(1, Ordering::Relaxed);
+        self.metrics.transition(coroutine.id(), None);
+    }
+
+    fn on_error(&self, _: u64, coroutine: &SchedulableCoroutine, _: &CoroutineError) {
+        _ = self.metrics.errored.fetch_add(1, Ordering::Relaxed);
+        self.metrics.transition(coroutine.id(), None);
+    }
+
+    fn on_cancel(&self, _: u64, coroutine: &SchedulableCoroutine) {
+        _ = self.metrics.cancelled.fetch_add(1, Ordering::Relaxed);
+        self.metrics.transition(coroutine.id(), None);
+    }
+
+    fn on_deadline_missed(&self, _: u64, _: &SchedulableCoroutine) {
+        _ = self.metrics.deadline_missed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The metrics of a scheduler.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SchedulerSnapshot {
+    /// The name of the scheduler.
+    pub name: String,
+    /// The number of coroutines created.
+    pub created: u64,
+    /// The number of coroutines completed.
+    pub completed: u64,
+    /// The number of coroutines finished with an error.
+    pub errored: u64,
+    /// The number of coroutines cancelled.
+    pub cancelled: u64,
+    /// The number of suspensions.
+    pub suspended: u64,
+    /// The number of coroutines picked after their deadlines.
+    pub deadline_missed: u64,
+    /// The number of syscall entries per `Syscall`.
+    pub syscalls: Vec<(Syscall, u64)>,
+    /// The total time in ns spent in each `CoroutineState`.
+    pub state_time: Vec<(&'static str, u64)>,
+    /// The durations of the run slices, from `on_resume` to the next state change.
+    pub run_slices: HistogramSnapshot,
+}
+
+/// The metrics of a pool.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PoolSnapshot {
+    /// The name of the pool.
+    pub name: String,
+    /// The number of tasks waiting in the pool.
+    pub queued: usize,
+    /// The number of worker coroutines.
+    pub running: usize,
+    /// The number of worker coroutines created by `grow`.
+    pub grown: u64,
+    /// The number of worker coroutines recycled after the keep-alive time.
+    pub shrunk: u64,
+}
+
+/// The metrics of all schedulers and pools in current process.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Snapshot {
+    /// The metrics of the schedulers with a `MetricsListener`, sorted by name.
+    pub schedulers: Vec<SchedulerSnapshot>,
+    /// The metrics of the pools, sorted by name.
+    pub pools: Vec<PoolSnapshot>,
+}
+
+/// Take a snapshot of the metrics.
+#[must_use]
+pub fn snapshot() -> Snapshot {
+    let mut schedulers: Vec<SchedulerSnapshot> = schedulers()
+        .iter()
+        .map(|r| r.value().1.snapshot(&r.value().0))
+        .collect();
+    schedulers.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut pools: Vec<PoolSnapshot> = pools()
+        .iter()
+        .map(|r| PoolSnapshot {
+            name: r.value().name.clone(),
+            queued: r.value().queued.load(Ordering::Relaxed),
+            running: r.value().running.load(Ordering::Relaxed),
+            grown: r.value().grown.load(Ordering::Relaxed),
+            shrunk: r.value().shrunk.load(Ordering::Relaxed),
+        })
+        .collect();
+    pools.sort_by(|a, b| a.name.cmp(&b.name));
+    Snapshot { schedulers, pools }
+}
+
+/// Render the metrics in the Prometheus text format.
+#[must_use]
+pub fn render_prometheus() -> String {
+    snapshot().to_prometheus()
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn seconds(ns: u64) -> f64 {
+    ns as f64 / 1_000_000_000.0
+}
+
+fn write_family(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: impl Iterator<Item = (String, String)>,
+) {
+    _ = writeln!(out, "# HELP {name} {help}");
+    _ = writeln!(out, "# TYPE {name} {kind}");
+    for (labels, value) in samples {
+        _ = writeln!(out, "{name}{{{labels}}} {value}");
+    }
+}
+
+type SchedulerCounter = fn(&SchedulerSnapshot) -> u64;
+
+type PoolValue = fn(&PoolSnapshot) -> String;
+
+impl Snapshot {
+    /// Render the metrics in the Prometheus text format.
+    #[must_use]
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        self.write_schedulers(&mut out);
+        self.write_run_slices(&mut out);
+        self.write_pools(&mut out);
+        out
+    }
+
+    fn write_schedulers(&self, out: &mut String) {
+        let counters: [(&str, &str, SchedulerCounter); 6] = [
+            ("created", "Coroutines created.", |s| s.created),
+            ("completed", "Coroutines completed.", |s| s.completed),
+            ("errored", "Coroutines finished with an error.", |s| {
+                s.errored
+            }),
+            ("cancelled", "Coroutines cancelled.", |s| s.cancelled),
+            ("suspended", "Coroutine suspensions.", |s| s.suspended),
+            (
+                "deadline_missed",
+                "Coroutines picked after their deadlines.",
+                |s| s.deadline_missed,
+            ),
+        ];
+        for (name, help, value) in counters {
+            write_family(
+                out,
+                &format!("open_coroutine_coroutines_{name}_total"),
+                "counter",
+                help,
+                self.schedulers.iter().map(|s| {
+                    (
+                        format!("scheduler=\"{}\"", escape(&s.name)),
+                        value(s).to_string(),
+                    )
+                }),
+            );
+        }
+        write_family(
+            out,
+            "open_coroutine_syscalls_total",
+            "counter",
+            "Syscall entries.",
+            self.schedulers.iter().flat_map(|s| {
+                s.syscalls.iter().map(|(syscall, count)| {
+                    (
+                        format!("scheduler=\"{}\",syscall=\"{syscall}\"", escape(&s.name)),
+                        count.to_string(),
+                    )
+                })
+            }),
+        );
+        write_family(
+            out,
+            "open_coroutine_state_seconds_total",
+            "counter",
+            "Time spent in each coroutine state.",
+            self.schedulers.iter().flat_map(|s| {
+                s.state_time.iter().map(|(state, time)| {
+                    (
+                        format!("scheduler=\"{}\",state=\"{state}\"", escape(&s.name)),
+                        seconds(*time).to_string(),
+                    )
+                })
+            }),
+        );
+    }
+
+    fn write_run_slices(&self, out: &mut String) {
+        let name = "open_coroutine_run_slice_seconds";
+        _ = writeln!(out, "# HELP {name} Durations of the coroutine run slices.");
+        _ = writeln!(out, "# TYPE {name} histogram");
+        for s in &self.schedulers {
+            let scheduler = escape(&s.name);
+            for (bound, count) in &s.run_slices.buckets {
+                let le = if *bound == u64::MAX {
+                    String::from("+Inf")
+                } else {
+                    seconds(*bound).to_string()
+                };
+                _ = writeln!(
+                    out,
+                    "{name}_bucket{{scheduler=\"{scheduler}\",le=\"{le}\"}} {count}"
+                );
+            }
+            _ = writeln!(
+                out,
+                "{name}_sum{{scheduler=\"{scheduler}\"}} {}",
+                seconds(s.run_slices.sum)
+            );
+            _ = writeln!(
+                out,
+                "{name}_count{{scheduler=\"{scheduler}\"}} {}",
+                s.run_slices.count
+            );
+        }
+    }
+
+    fn write_pools(&self, out: &mut String) {
+        let gauges: [(&str, &str, &str, PoolValue); 4] = [
+            ("queued_tasks", "gauge", "Tasks waiting in the pool.", |p| {
+                p.queued.to_string()
+            }),
+            ("running_coroutines", "gauge", "Worker coroutines.", |p| {
+                p.running.to_string()
+            }),
+            ("grow_total", "counter", "Worker coroutines created.", |p| {
+                p.grown.to_string()
+            }),
+            (
+                "shrink_total",
+                "counter",
+                "Worker coroutines recycled.",
+                |p| p.shrunk.to_string(),
+            ),
+        ];
+        for (name, kind, help, value) in gauges {
+            write_family(
+                out,
+                &format!("open_coroutine_pool_{name}"),
+                kind,
+                help,
+                self.pools
+                    .iter()
+                    .map(|p| (format!("pool=\"{}\"", escape(&p.name)), value(p))),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{CoroutineState, SyscallState, DEFAULT_STACK_SIZE};
+    use crate::coroutine::suspender::SimpleSuspender;
+    use crate::coroutine::SimpleCoroutine;
+
+    #[test]
+    fn test_metrics() -> std::io::Result<()> {
+        let listener = MetricsListener::new("test_metrics");
+        let mut coroutine = SchedulableCoroutine::new(
+            String::from("test_metrics|coroutine"),
+            |suspender, ()| suspender.suspend(),
+            DEFAULT_STACK_SIZE,
+        )?;
+        listener.on_create(&coroutine);
+        listener.on_resume(0, &coroutine);
+        assert!(matches!(
+            coroutine.resume()?,
+            CoroutineState::Suspend((), 0)
+        ));
+        listener.on_suspend(0, &coroutine);
+        listener.on_resume(0, &coroutine);
+        listener.on_syscall(0, &coroutine, Syscall::nanosleep, SyscallState::Computing);
+        listener.on_resume(0, &coroutine);
+        assert!(matches!(coroutine.resume()?, CoroutineState::Complete(())));
+        listener.on_complete(0, &coroutine, None);
+
+        let snapshot = snapshot();
+        let metrics = snapshot
+            .schedulers
+            .iter()
+            .find(|s| s.name == "test_metrics")
+            .expect("metrics not found");
+        assert_eq!(1, metrics.created);
+        assert_eq!(1, metrics.completed);
+        assert_eq!(1, metrics.suspended);
+        assert_eq!(vec![(Syscall::nanosleep, 1)], metrics.syscalls);
+        assert_eq!(3, metrics.run_slices.count);
+        assert_eq!(Some(&(u64::MAX, 3)), metrics.run_slices.buckets.last());
+        let text = snapshot.to_prometheus();
+        assert!(
+            text.contains("open_coroutine_coroutines_created_total{scheduler=\"test_metrics\"} 1")
+        );
+        assert!(text.contains(
+            "open_coroutine_syscalls_total{scheduler=\"test_metrics\",syscall=\"nanosleep\"} 1"
+        ));
+        assert!(text.contains(
+            "open_coroutine_run_slice_seconds_bucket{scheduler=\"test_metrics\",le=\"+Inf\"} 3"
+        ));
+        drop(listener);
+        assert!(snapshot_names().iter().all(|name| name != "test_metrics"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_name() {
+        let first = MetricsListener::new("test_same_name");
+        let second = MetricsListener::new("test_same_name");
+        let count = || {
+            snapshot_names()
+                .iter()
+                .filter(|name| *name == "test_same_name")
+                .count()
+        };
+        assert_eq!(2, count());
+        // dropping one doesn't unregister the other
+        drop(first);
+        assert_eq!(1, count());
+        drop(second);
+        assert_eq!(0, count());
+    }
+
+    fn snapshot_names() -> Vec<String> {
+        snapshot().schedulers.into_iter().map(|s| s.name).collect()
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!("a\\\"b\\\\c\\n", escape("a\"b\\c\n"));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::coroutine::error::CoroutineError;
 use crate::coroutine::Coroutine;
 use crate::monitor::{Monitor, MonitorImpl};
 use crate::scheduler::listener::Listener;
@@ -26,13 +27,26 @@ impl Listener for MonitorTaskCreator {
         }
     }
 
-    fn on_complete(&self, _: u64, coroutine: &SchedulableCoroutine) {
+    fn on_syscall(
+        &self,
+        _: u64,
+        coroutine: &SchedulableCoroutine,
+        _: crate::constants::Syscall,
+        _: crate::constants::SyscallState,
+    ) {
+        //陷入系统调用的协程不需要抢占，恢复时会重新提交
         if let Some(timestamp) = coroutine.local().get(MONITOR_TIMESTAMP) {
             MonitorImpl::get_instance().remove(*timestamp, coroutine);
         }
     }
 
-    fn on_error(&self, _: u64, coroutine: &SchedulableCoroutine, _: &str) {
+    fn on_complete(&self, _: u64, coroutine: &SchedulableCoroutine, _: Option<usize>) {
+        if let Some(timestamp) = coroutine.local().get(MONITOR_TIMESTAMP) {
+            MonitorImpl::get_instance().remove(*timestamp, coroutine);
+        }
+    }
+
+    fn on_error(&self, _: u64, coroutine: &SchedulableCoroutine, _: &CoroutineError) {
         if let Some(timestamp) = coroutine.local().get(MONITOR_TIMESTAMP) {
             MonitorImpl::get_instance().remove(*timestamp, coroutine);
         }
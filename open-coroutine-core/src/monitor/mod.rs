@@ -1,20 +1,19 @@
-#[cfg(feature = "logs")]
 use crate::common::Named;
 use crate::common::{Blocker, Current};
-use crate::constants::{CoroutineState, DEFAULT_STACK_SIZE, MONITOR_CPU};
+use crate::constants::{CoroutineState, MONITOR_CPU};
 use crate::coroutine::suspender::SimpleSuspender;
 use crate::coroutine::StateMachine;
 use crate::monitor::node::TaskNode;
-use crate::pool::{CoroutinePool, CoroutinePoolImpl, Pool};
 use crate::scheduler::{SchedulableCoroutine, SchedulableSuspender};
 use nix::sys::pthread::pthread_kill;
 use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 use open_coroutine_timer::TimerList;
-use std::cell::{RefCell, UnsafeCell};
+use std::cell::{Cell, RefCell, UnsafeCell};
 use std::fmt::Debug;
 use std::io::{Error, ErrorKind};
 use std::mem::MaybeUninit;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
 use std::thread::JoinHandle;
 use std::time::Duration;
 
@@ -55,19 +54,30 @@ pub trait Monitor {
 #[derive(Debug)]
 pub struct MonitorImpl {
     cpu: usize,
-    tasks: UnsafeCell<TimerList<TaskNode>>,
-    clean_queue: UnsafeCell<Vec<TaskNode>>,
+    //任务在协程完成、出错或陷入系统调用时同步删除，早于协程被释放，
+    //监控线程只在持锁时访问协程，因此不会访问已释放的协程；
+    //之前的做法是把删除放入队列，由监控线程稍后处理，期间协程可能已被释放
+    tasks: Mutex<TimerList<TaskNode>>,
     run: AtomicBool,
     monitor: UnsafeCell<MaybeUninit<JoinHandle<()>>>,
     blocker: RefCell<Box<dyn Blocker>>,
 }
 
+thread_local! {
+    // whether current thread holds the lock of the monitor tasks
+    static LOCKED: Cell<bool> = const { Cell::new(false) };
+}
+
 extern "C" fn sigurg_handler(_: libc::c_int) {
     if let Ok(mut set) = SigSet::thread_get_mask() {
         //删除对SIGURG信号的屏蔽，使信号处理函数即使在处理中，也可以再次进入信号处理函数
         set.remove(Signal::SIGURG);
         set.thread_set_mask()
             .expect("Failed to remove SIGURG signal mask!");
+        //持锁时不能挂起，否则调度线程再次加锁会死锁，监控线程会在1ms后重试
+        if LOCKED.get() {
+            return;
+        }
         if let Some(suspender) = SchedulableSuspender::current() {
             suspender.suspend();
         }
@@ -89,8 +99,7 @@ impl Monitor for MonitorImpl {
             }
             let ptr: &'m mut MonitorImpl = Box::leak(Box::new(MonitorImpl {
                 cpu: MONITOR_CPU,
-                tasks: UnsafeCell::new(TimerList::default()),
-                clean_queue: UnsafeCell::new(Vec::new()),
+                tasks: Mutex::new(TimerList::default()),
                 run: AtomicBool::default(),
                 monitor: UnsafeCell::new(MaybeUninit::uninit()),
                 blocker: RefCell::new(blocker),
@@ -133,60 +142,29 @@ impl Monitor for MonitorImpl {
                         _ = core_affinity::set_for_current(core_affinity::CoreId {
                             id: monitor.cpu,
                         });
-                        let pool = CoroutinePoolImpl::new(
-                            String::from("open-coroutine-monitor"),
-                            monitor.cpu,
-                            DEFAULT_STACK_SIZE,
-                            1,
-                            1,
-                            0,
-                            crate::common::DelayBlocker::default(),
-                        );
-                        let tasks = unsafe { &*monitor.tasks.get() };
-                        while monitor.run.load(Ordering::Acquire) || !tasks.is_empty() {
+                        while monitor.run.load(Ordering::Acquire) || !monitor.is_empty() {
                             //只遍历，不删除，如果抢占调度失败，会在1ms后不断重试，相当于主动检测
-                            for (exec_time, entry) in tasks.iter() {
-                                if open_coroutine_timer::now() < *exec_time {
-                                    break;
-                                }
-                                if entry.is_empty() {
-                                    continue;
-                                }
-                                for node in entry.iter() {
-                                    _ = pool.submit(
-                                        None,
-                                        |_| {
-                                            let coroutine = node.coroutine();
-                                            if CoroutineState::Running == coroutine.state() {
-                                                //只对陷入重度计算的协程发送信号抢占，对陷入执行系统调用的协程
-                                                //不发送信号(如果发送信号，会打断系统调用，进而降低总体性能)
-                                                if pthread_kill(node.pthread(), Signal::SIGURG)
-                                                    .is_err()
-                                                {
-                                                    crate::error!("Attempt to preempt scheduling for the coroutine:{} in thread:{} failed !",
-                                                                coroutine.get_name(), node.pthread());
-                                                }
+                            monitor.with_tasks(|tasks| {
+                                let now = open_coroutine_timer::now();
+                                for (exec_time, entry) in tasks.iter() {
+                                    if now < *exec_time {
+                                        break;
+                                    }
+                                    for node in entry.iter() {
+                                        // the node is removed before the coroutine dropped
+                                        let coroutine = node.coroutine();
+                                        if CoroutineState::Running == coroutine.state() {
+                                            //只对陷入重度计算的协程发送信号抢占，对陷入执行系统调用的协程
+                                            //不发送信号(如果发送信号，会打断系统调用，进而降低总体性能)
+                                            crate::trace::preempt(node.tid(), coroutine);
+                                            if pthread_kill(node.pthread(), Signal::SIGURG).is_err() {
+                                                crate::error!("Attempt to preempt scheduling for the coroutine:{} in thread:{} failed !",
+                                                            coroutine.get_name(), node.pthread());
                                             }
-                                            None
-                                        },
-                                        None,
-                                    );
-                                }
-                                _ = pool.try_schedule();
-                            }
-                            {
-                                let queue = unsafe { &mut *monitor.clean_queue.get() };
-                                let tasks = unsafe { &mut *monitor.tasks.get() };
-                                while let Some(node) = queue.pop() {
-                                    let timestamp= node.timestamp();
-                                    if let Some(entry) = tasks.get_entry(&timestamp) {
-                                        _ = entry.remove(&node);
-                                        if entry.is_empty() {
-                                            _ = tasks.remove(&timestamp);
                                         }
                                     }
                                 }
-                            }
+                            });
                             //monitor线程不执行协程计算任务，每次循环至少wait 1ms
                             loop {
                                 #[allow(box_pointers)]
@@ -197,7 +175,6 @@ impl Monitor for MonitorImpl {
                             }
                         }
                         crate::warn!("open-coroutine-monitor has exited");
-                        _ = pool.stop(Duration::from_secs(30));
                     })
                     .map_err(|e| Error::new(ErrorKind::Other, format!("{e:?}")))?,
             );
@@ -211,14 +188,33 @@ impl Monitor for MonitorImpl {
 
     fn submit(&self, timestamp: u64, coroutine: &SchedulableCoroutine) -> std::io::Result<()> {
         self.start()?;
-        let tasks = unsafe { &mut *self.tasks.get() };
-        tasks.insert(timestamp, TaskNode::new(timestamp, coroutine));
+        self.with_tasks(|tasks| tasks.insert(timestamp, TaskNode::new(timestamp, coroutine)));
         Ok(())
     }
 
     fn remove(&self, timestamp: u64, coroutine: &SchedulableCoroutine) {
-        let queue = unsafe { &mut *self.clean_queue.get() };
-        queue.push(TaskNode::new(timestamp, coroutine));
+        let node = TaskNode::new(timestamp, coroutine);
+        self.with_tasks(|tasks| {
+            if let Some(entry) = tasks.get_entry(&timestamp) {
+                _ = entry.remove(&node);
+                if entry.is_empty() {
+                    _ = tasks.remove(&timestamp);
+                }
+            }
+        });
+    }
+}
+
+impl MonitorImpl {
+    fn with_tasks<R>(&self, f: impl FnOnce(&mut TimerList<TaskNode>) -> R) -> R {
+        LOCKED.set(true);
+        let result = f(&mut self.tasks.lock().expect("lock failed"));
+        LOCKED.set(false);
+        result
+    }
+
+    fn is_empty(&self) -> bool {
+        self.with_tasks(|tasks| tasks.is_empty())
     }
 }
 
@@ -244,6 +240,46 @@ mod tests {
         _ = MonitorImpl::change_blocker(blocker);
     }
 
+    #[test]
+    fn test_remove_synchronously() -> std::io::Result<()> {
+        use crate::constants::DEFAULT_STACK_SIZE;
+        use crate::coroutine::Coroutine;
+        let monitor = MonitorImpl::get_instance();
+        let coroutine = SchedulableCoroutine::new(
+            String::from("test_remove_synchronously"),
+            |_, ()| {},
+            DEFAULT_STACK_SIZE,
+        )?;
+        // far enough, the monitor thread never preempts it
+        let timestamp = u64::MAX - 1;
+        monitor.submit(timestamp, &coroutine)?;
+        assert!(monitor.with_tasks(|tasks| tasks.get_entry(&timestamp).is_some()));
+        monitor.remove(timestamp, &coroutine);
+        // the task is gone before the coroutine can be dropped
+        assert!(monitor.with_tasks(|tasks| tasks.get_entry(&timestamp).is_none()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_not_suspend_while_locked() -> std::io::Result<()> {
+        use crate::constants::DEFAULT_STACK_SIZE;
+        use crate::coroutine::{Coroutine, SimpleCoroutine};
+        let mut coroutine = SchedulableCoroutine::new(
+            String::from("test_not_suspend_while_locked"),
+            |_, ()| {
+                MonitorImpl::get_instance().with_tasks(|_| {
+                    assert!(LOCKED.get());
+                    // suspending here would deadlock the next lock of this thread
+                    sigurg_handler(libc::SIGURG);
+                });
+                assert!(!LOCKED.get());
+            },
+            DEFAULT_STACK_SIZE,
+        )?;
+        assert!(matches!(coroutine.resume()?, CoroutineState::Complete(())));
+        Ok(())
+    }
+
     #[cfg(not(target_arch = "riscv64"))]
     #[test]
     fn test() -> std::io::Result<()> {
@@ -7,6 +7,8 @@ use std::ffi::c_void;
 pub(crate) struct TaskNode {
     timestamp: u64,
     pthread: Pthread,
+    //线程在时间线中的id
+    tid: u64,
     coroutine: *const c_void,
 }
 
@@ -15,18 +17,19 @@ impl TaskNode {
         TaskNode {
             timestamp,
             pthread: pthread_self(),
+            tid: crate::trace::thread_id(),
             coroutine: coroutine.cast::<c_void>(),
         }
     }
 
-    pub(crate) fn timestamp(&self) -> u64 {
-        self.timestamp
-    }
-
     pub(crate) fn pthread(&self) -> Pthread {
         self.pthread
     }
 
+    pub(crate) fn tid(&self) -> u64 {
+        self.tid
+    }
+
     pub(crate) fn coroutine(&self) -> &SchedulableCoroutine {
         unsafe { &*(self.coroutine.cast::<SchedulableCoroutine>()) }
     }
@@ -1,4 +1,6 @@
 use crate::constants::DEFAULT_STACK_SIZE;
+use crate::coroutine::stack;
+use crate::{dump, metrics, trace};
 use crossbeam_utils::atomic::AtomicCell;
 use once_cell::sync::Lazy;
 use std::fmt::{Debug, Formatter};
@@ -9,6 +11,14 @@ static CONFIG: Lazy<Config> = Lazy::new(Config::default);
 pub struct Config {
     event_loop_size: AtomicCell<usize>,
     stack_size: AtomicCell<usize>,
+    stack_cache_size: AtomicCell<usize>,
+    stack_cache_memory: AtomicCell<usize>,
+    stack_measure: AtomicCell<bool>,
+    dump_record: AtomicCell<bool>,
+    dump_backtrace: AtomicCell<bool>,
+    dump_on_sigquit: AtomicCell<bool>,
+    metrics: AtomicCell<bool>,
+    trace: AtomicCell<bool>,
     min_size: AtomicCell<usize>,
     max_size: AtomicCell<usize>,
     keep_alive_time: AtomicCell<u64>,
@@ -30,6 +40,54 @@ impl Config {
         self.stack_size.load()
     }
 
+    /// Get the max number of cached stacks per stack size in each thread.
+    #[must_use]
+    pub fn get_stack_cache_size(&self) -> usize {
+        self.stack_cache_size.load()
+    }
+
+    /// Get the max bytes of cached stacks in each thread.
+    #[must_use]
+    pub fn get_stack_cache_memory(&self) -> usize {
+        self.stack_cache_memory.load()
+    }
+
+    /// Whether measure the peak stack usage of coroutines.
+    #[must_use]
+    pub fn get_stack_measure(&self) -> bool {
+        self.stack_measure.load()
+    }
+
+    /// Whether record the live coroutines for dumping.
+    #[must_use]
+    pub fn get_dump_record(&self) -> bool {
+        self.dump_record.load()
+    }
+
+    /// Whether capture the backtrace when coroutines suspend.
+    #[must_use]
+    pub fn get_dump_backtrace(&self) -> bool {
+        self.dump_backtrace.load()
+    }
+
+    /// Whether dump all live coroutines to stderr when receive `SIGQUIT`.
+    #[must_use]
+    pub fn get_dump_on_sigquit(&self) -> bool {
+        self.dump_on_sigquit.load()
+    }
+
+    /// Whether collect the metrics of the event-loops.
+    #[must_use]
+    pub fn get_metrics(&self) -> bool {
+        self.metrics.load()
+    }
+
+    /// Whether trace the coroutines of the event-loops.
+    #[must_use]
+    pub fn get_trace(&self) -> bool {
+        self.trace.load()
+    }
+
     #[must_use]
     pub fn get_min_size(&self) -> usize {
         self.min_size.load()
@@ -61,6 +119,80 @@ impl Config {
         self
     }
 
+    /// Set the max number of cached stacks per stack size in each thread,
+    /// `0` means disable the stack cache.
+    pub fn set_stack_cache_size(&self, stack_cache_size: usize) -> &Self {
+        self.stack_cache_size.store(stack_cache_size);
+        stack::set_cache_size(stack_cache_size);
+        self
+    }
+
+    /// Set the max bytes of cached stacks in each thread,
+    /// `0` means disable the stack cache.
+    pub fn set_stack_cache_memory(&self, stack_cache_memory: usize) -> &Self {
+        self.stack_cache_memory.store(stack_cache_memory);
+        stack::set_cache_memory(stack_cache_memory);
+        self
+    }
+
+    /// Paint new stacks and measure the peak stack usage of coroutines,
+    /// see `open_coroutine_core::coroutine::stack::stats`.
+    pub fn set_stack_measure(&self, stack_measure: bool) -> &Self {
+        self.stack_measure.store(stack_measure);
+        stack::set_measure(stack_measure);
+        self
+    }
+
+    /// Record the live coroutines of the schedulers created after,
+    /// see `open_coroutine_core::dump::dump`.
+    pub fn set_dump_record(&self, dump_record: bool) -> &Self {
+        self.dump_record.store(dump_record);
+        dump::set_record(dump_record);
+        self
+    }
+
+    /// Capture the backtrace when coroutines suspend, needs `set_dump_record(true)`,
+    /// it's a debug mode which unwinds the stack on every suspend,
+    /// see `open_coroutine_core::dump::set_backtrace`.
+    pub fn set_dump_backtrace(&self, dump_backtrace: bool) -> &Self {
+        self.dump_backtrace.store(dump_backtrace);
+        dump::set_backtrace(dump_backtrace);
+        self
+    }
+
+    /// Dump all live coroutines to stderr when receive `SIGQUIT`, needs
+    /// `set_dump_record(true)`, the handler can not be uninstalled once installed.
+    ///
+    /// The setter keeps chaining, so if the handler can't be installed, the error is
+    /// only logged and `get_dump_on_sigquit` keeps the previous value.
+    pub fn set_dump_on_sigquit(&self, dump_on_sigquit: bool) -> &Self {
+        #[cfg(unix)]
+        if dump_on_sigquit {
+            if let Err(e) = dump::dump_on_signal(nix::sys::signal::Signal::SIGQUIT) {
+                crate::error!("install the SIGQUIT dump handler failed: {e}");
+                return self;
+            }
+        }
+        self.dump_on_sigquit.store(dump_on_sigquit);
+        self
+    }
+
+    /// Collect the metrics of the event-loops created after,
+    /// see `open_coroutine_core::metrics::snapshot`.
+    pub fn set_metrics(&self, metrics: bool) -> &Self {
+        self.metrics.store(metrics);
+        metrics::set_enable(metrics);
+        self
+    }
+
+    /// Trace the coroutines of the event-loops created after,
+    /// see `open_coroutine_core::trace::start`.
+    pub fn set_trace(&self, trace: bool) -> &Self {
+        self.trace.store(trace);
+        trace::set_enable(trace);
+        self
+    }
+
     pub fn set_min_size(&self, min_size: usize) -> &Self {
         self.min_size.store(min_size);
         self
@@ -89,6 +221,14 @@ impl Default for Config {
         Config {
             event_loop_size: AtomicCell::new(num_cpus::get()),
             stack_size: AtomicCell::new(DEFAULT_STACK_SIZE),
+            stack_cache_size: AtomicCell::new(stack::DEFAULT_STACK_CACHE_SIZE),
+            stack_cache_memory: AtomicCell::new(stack::DEFAULT_STACK_CACHE_MEMORY),
+            stack_measure: AtomicCell::new(false),
+            dump_record: AtomicCell::new(false),
+            dump_backtrace: AtomicCell::new(false),
+            dump_on_sigquit: AtomicCell::new(false),
+            metrics: AtomicCell::new(false),
+            trace: AtomicCell::new(false),
             min_size: AtomicCell::new(0),
             max_size: AtomicCell::new(65536),
             keep_alive_time: AtomicCell::new(0),
@@ -101,6 +241,14 @@ impl Debug for Config {
         f.debug_struct("Config")
             .field("event_loop_size", &self.get_event_loop_size())
             .field("stack_size", &self.get_stack_size())
+            .field("stack_cache_size", &self.get_stack_cache_size())
+            .field("stack_cache_memory", &self.get_stack_cache_memory())
+            .field("stack_measure", &self.get_stack_measure())
+            .field("dump_record", &self.get_dump_record())
+            .field("dump_backtrace", &self.get_dump_backtrace())
+            .field("dump_on_sigquit", &self.get_dump_on_sigquit())
+            .field("metrics", &self.get_metrics())
+            .field("trace", &self.get_trace())
             .field("min_size", &self.get_min_size())
             .field("max_size", &self.get_max_size())
             .field("keep_alive_time", &self.get_keep_alive_time())
@@ -112,19 +260,56 @@ impl Debug for Config {
 mod tests {
     use super::*;
 
-    #[ignore]
     #[test]
     fn test_config() {
-        _ = Config::get_instance()
+        //use a local instance and restore the global settings, so other tests are not affected
+        let _settings = crate::common::SETTINGS_LOCK.lock().unwrap();
+        let config = Config::default();
+        _ = config
             .set_event_loop_size(2)
             .set_stack_size(4096)
+            .set_stack_cache_size(32)
+            .set_stack_cache_memory(1024 * 1024)
+            .set_stack_measure(true)
+            .set_dump_record(true)
+            .set_dump_backtrace(true)
+            .set_metrics(true)
+            .set_trace(true)
             .set_min_size(256)
             .set_max_size(256)
             .set_keep_alive_time(0);
-        assert_eq!(2, CONFIG.event_loop_size.load());
-        assert_eq!(4096, CONFIG.stack_size.load());
-        assert_eq!(256, CONFIG.min_size.load());
-        assert_eq!(256, CONFIG.max_size.load());
-        assert_eq!(0, CONFIG.keep_alive_time.load());
+        assert_eq!(2, config.get_event_loop_size());
+        assert_eq!(4096, config.get_stack_size());
+        assert_eq!(32, config.get_stack_cache_size());
+        assert_eq!(1024 * 1024, config.get_stack_cache_memory());
+        assert_eq!(32, stack::get_cache_size());
+        assert_eq!(1024 * 1024, stack::get_cache_memory());
+        assert!(config.get_stack_measure());
+        assert!(stack::get_measure());
+        assert!(config.get_dump_record());
+        assert!(dump::get_record());
+        assert!(config.get_dump_backtrace());
+        assert!(dump::get_backtrace());
+        assert!(config.get_metrics());
+        assert!(metrics::get_enable());
+        assert!(config.get_trace());
+        assert!(trace::get_enable());
+        assert_eq!(256, config.get_min_size());
+        assert_eq!(256, config.get_max_size());
+        assert_eq!(0, config.get_keep_alive_time());
+        _ = config
+            .set_stack_cache_size(CONFIG.get_stack_cache_size())
+            .set_stack_cache_memory(CONFIG.get_stack_cache_memory())
+            .set_stack_measure(CONFIG.get_stack_measure())
+            .set_dump_record(CONFIG.get_dump_record())
+            .set_dump_backtrace(CONFIG.get_dump_backtrace())
+            .set_metrics(CONFIG.get_metrics())
+            .set_trace(CONFIG.get_trace());
+        assert_eq!(stack::DEFAULT_STACK_CACHE_SIZE, stack::get_cache_size());
+        assert!(!stack::get_measure());
+        assert!(!dump::get_record());
+        assert!(!dump::get_backtrace());
+        assert!(!metrics::get_enable());
+        assert!(!trace::get_enable());
     }
 }
@@ -3,6 +3,7 @@ use crate::constants::MONITOR_CPU;
 use crate::monitor::Monitor;
 use crate::net::config::Config;
 use crate::net::event_loop::{EventLoop, EventLoopImpl, JoinHandleImpl};
+use crate::pool::group::GroupStats;
 #[cfg(all(unix, feature = "preemptive-schedule"))]
 use crate::pool::task::TaskImpl;
 use crate::pool::Pool;
@@ -132,6 +133,43 @@ impl EventLoops {
         EventLoops::next(true).0.submit(name, f, param)
     }
 
+    /// Submit a task into the scheduling `group`, see `Pool::set_group_weight`.
+    pub fn submit_to_group(
+        group: &str,
+        name: Option<String>,
+        f: impl FnOnce(Option<usize>) -> Option<usize> + UnwindSafe + 'static,
+        param: Option<usize>,
+    ) -> JoinHandleImpl<'static> {
+        EventLoops::next(true)
+            .0
+            .submit_to_group(group, name, f, param)
+    }
+
+    /// Set the weight of the scheduling `group` in all event-loops.
+    pub fn set_group_weight(group: &str, weight: usize) {
+        for event_loop in EVENT_LOOPS.iter() {
+            event_loop.set_group_weight(group, weight);
+        }
+    }
+
+    /// Returns the counters of the scheduling groups, summed over all event-loops.
+    pub fn group_stats() -> Vec<GroupStats> {
+        let mut stats: Vec<GroupStats> = Vec::new();
+        for event_loop in EVENT_LOOPS.iter() {
+            for group in event_loop.group_stats() {
+                match stats.iter_mut().find(|s| s.name == group.name) {
+                    Some(s) => {
+                        s.queued += group.queued;
+                        s.submitted += group.submitted;
+                        s.completed += group.completed;
+                    }
+                    None => stats.push(group),
+                }
+            }
+        }
+        stats
+    }
+
     #[cfg(all(unix, feature = "preemptive-schedule"))]
     pub(crate) fn submit_raw(task: TaskImpl<'static>) {
         _ = EventLoops::next(true).0.submit_raw(task);
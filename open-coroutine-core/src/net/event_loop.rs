@@ -1,29 +1,34 @@
 use crate::common::{Blocker, Current, Named};
-use crate::constants::{CoroutineState, PoolState, Syscall, SyscallState, DEFAULT_STACK_SIZE};
+use crate::constants::{
+    CoroutineState, PoolState, SchedulePolicy, Syscall, SyscallState, DEFAULT_STACK_SIZE,
+};
+use crate::coroutine::error::CoroutineError;
 use crate::coroutine::suspender::SimpleDelaySuspender;
-use crate::coroutine::StateMachine;
+use crate::coroutine::{Coroutine, StateMachine};
 use crate::net::selector::{Selector, SelectorImpl};
+use crate::pool::group::GroupStats;
 use crate::pool::join::JoinHandle;
 use crate::pool::task::TaskImpl;
 use crate::pool::{CoroutinePool, CoroutinePoolImpl, Pool};
+use crate::scheduler::listener::Listener;
 use crate::scheduler::{SchedulableCoroutine, SchedulableSuspender};
+use crate::sync::condvar::Condvar as CoCondvar;
+use crate::sync::mutex::Mutex as CoMutex;
 use std::ffi::{c_char, c_int, c_void, CStr, CString};
 use std::fmt::Debug;
+use std::future::Future;
 use std::io::{Error, ErrorKind};
 use std::panic::RefUnwindSafe;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll};
 use std::time::Duration;
 
 #[allow(trivial_numeric_casts, clippy::cast_possible_truncation)]
 fn token() -> usize {
     if let Some(co) = SchedulableCoroutine::current() {
-        #[allow(box_pointers)]
-        let boxed: &'static mut CString = Box::leak(Box::from(
-            CString::new(co.get_name()).expect("build name failed!"),
-        ));
-        let cstr: &'static CStr = boxed.as_c_str();
-        cstr.as_ptr().cast::<c_void>() as usize
+        co.id()
     } else {
         unsafe {
             cfg_if::cfg_if! {
@@ -66,6 +71,46 @@ impl<'e> JoinHandleImpl<'e> {
     }
 }
 
+/// The join failed, such as timeout or the coroutine not found.
+pub const JOIN_FAILED: c_int = 1;
+
+/// The coroutine finished with error.
+pub const COROUTINE_FAILED: c_int = 2;
+
+/// The error filled by the `coroutine_join_err` and `coroutine_timeout_join_err` hooks
+/// when they return -1.
+#[repr(C)]
+#[derive(Debug)]
+pub struct JoinError {
+    /// `JOIN_FAILED` or `COROUTINE_FAILED`.
+    pub code: c_int,
+    /// The boxed `CoroutineError` if the coroutine failed, otherwise null,
+    /// it should be released by `coroutine_error_free` or `JoinError::into_error`.
+    pub error: *mut c_void,
+}
+
+impl Default for JoinError {
+    fn default() -> Self {
+        JoinError {
+            code: 0,
+            error: std::ptr::null_mut(),
+        }
+    }
+}
+
+impl JoinError {
+    /// Take the `CoroutineError` if the coroutine failed, otherwise create an error
+    /// with the message.
+    #[must_use]
+    pub fn into_error(self, message: &str) -> Error {
+        if COROUTINE_FAILED == self.code && !self.error.is_null() {
+            let error = unsafe { Box::from_raw(self.error.cast::<CoroutineError>()) };
+            return Error::other(*error);
+        }
+        Error::other(message)
+    }
+}
+
 impl JoinHandle for JoinHandleImpl<'_> {
     fn get_name(&self) -> std::io::Result<&str> {
         unsafe { CStr::from_ptr(self.1) }
@@ -73,7 +118,10 @@ impl JoinHandle for JoinHandleImpl<'_> {
             .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid task name"))
     }
 
-    fn timeout_at_join(&self, timeout_time: u64) -> std::io::Result<Result<Option<usize>, &str>> {
+    fn timeout_at_join(
+        &self,
+        timeout_time: u64,
+    ) -> std::io::Result<Result<Option<usize>, CoroutineError>> {
         let name = self.get_name()?;
         if name.is_empty() {
             return Err(Error::new(ErrorKind::InvalidInput, "Invalid task name"));
@@ -88,14 +136,46 @@ impl JoinHandle for JoinHandleImpl<'_> {
     }
 }
 
+impl Future for JoinHandleImpl<'_> {
+    type Output = std::io::Result<Result<Option<usize>, CoroutineError>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let name = self.get_name()?;
+        if name.is_empty() {
+            return Poll::Ready(Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Invalid task name",
+            )));
+        }
+        let event_loop = unsafe { &*self.0 };
+        event_loop.pool.poll_result(name, cx).map(Ok)
+    }
+}
+
+/// Delete the interests of the cancelled coroutines, or the fd will never
+/// be registered again for other coroutines.
+#[derive(Debug)]
+struct SelectorCleaner(Arc<SelectorImpl>);
+
+impl Listener for SelectorCleaner {
+    fn on_cancel(&self, _: u64, coroutine: &SchedulableCoroutine) {
+        if let Err(e) = self.0.del_token_event(coroutine.id()) {
+            crate::error!("delete the events of {} failed: {e}", coroutine.get_name());
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct EventLoopImpl<'e> {
     cpu: usize,
     pool: CoroutinePoolImpl<'e>,
-    selector: SelectorImpl,
-    stop: Arc<(Mutex<bool>, Condvar)>,
+    selector: Arc<SelectorImpl>,
+    stop: Arc<(CoMutex<bool>, CoCondvar)>,
     shared_stop: Arc<(Mutex<AtomicUsize>, Condvar)>,
+    //共享名称，每次wait_event时不必分配
+    #[cfg(feature = "tracing")]
+    name: Arc<str>,
 }
 
 impl EventLoopImpl<'_> {
@@ -108,29 +188,36 @@ impl EventLoopImpl<'_> {
         keep_alive_time: u64,
         shared_stop: Arc<(Mutex<AtomicUsize>, Condvar)>,
     ) -> std::io::Result<Self> {
+        let selector = Arc::new(SelectorImpl::new()?);
+        #[cfg(feature = "tracing")]
+        let shared_name = Arc::from(name.as_str());
+        let mut pool = CoroutinePoolImpl::new(
+            name,
+            cpu,
+            stack_size,
+            min_size,
+            max_size,
+            keep_alive_time,
+            crate::common::DelayBlocker::default(),
+        );
+        pool.add_listener(SelectorCleaner(selector.clone()));
+        let notifier = selector.clone();
+        pool.set_wake_notify(move || {
+            if let Err(e) = notifier.notify() {
+                crate::error!("interrupt the selector failed: {e}");
+            }
+        });
         Ok(EventLoopImpl {
             cpu,
-            pool: CoroutinePoolImpl::new(
-                name,
-                cpu,
-                stack_size,
-                min_size,
-                max_size,
-                keep_alive_time,
-                crate::common::DelayBlocker::default(),
-            ),
-            selector: SelectorImpl::new()?,
-            stop: Arc::new((Mutex::new(false), Condvar::new())),
+            pool,
+            selector,
+            stop: Arc::new((CoMutex::new(false), CoCondvar::new())),
             shared_stop,
+            #[cfg(feature = "tracing")]
+            name: shared_name,
         })
     }
 
-    fn map_name<'c>(token: usize) -> Option<&'c str> {
-        unsafe { CStr::from_ptr((token as *const c_void).cast::<c_char>()) }
-            .to_str()
-            .ok()
-    }
-
     pub fn add_read_event(&self, fd: c_int) -> std::io::Result<()> {
         self.selector.add_read_event(fd, token())
     }
@@ -216,6 +303,22 @@ impl<'e> Pool<'e, JoinHandleImpl<'e>> for EventLoopImpl<'e> {
         self.pool.get_keep_alive_time()
     }
 
+    fn set_schedule_policy(&self, policy: SchedulePolicy) {
+        self.pool.set_schedule_policy(policy);
+    }
+
+    fn get_schedule_policy(&self) -> SchedulePolicy {
+        self.pool.get_schedule_policy()
+    }
+
+    fn set_group_weight(&self, group: &str, weight: usize) {
+        self.pool.set_group_weight(group, weight);
+    }
+
+    fn group_stats(&self) -> Vec<GroupStats> {
+        self.pool.group_stats()
+    }
+
     fn size(&self) -> usize {
         self.pool.size()
     }
@@ -224,7 +327,7 @@ impl<'e> Pool<'e, JoinHandleImpl<'e>> for EventLoopImpl<'e> {
         &self,
         task_name: &str,
         wait_time: Duration,
-    ) -> std::io::Result<Option<(String, Result<Option<usize>, &str>)>> {
+    ) -> std::io::Result<Option<(String, Result<Option<usize>, CoroutineError>)>> {
         let mut left = wait_time;
         let once = Duration::from_millis(10);
         loop {
@@ -286,7 +389,7 @@ impl<'e> Pool<'e, JoinHandleImpl<'e>> for EventLoopImpl<'e> {
                     _ = consumer.wait_event(Some(Duration::from_millis(10)));
                 }
                 let (lock, cvar) = &*consumer.stop.clone();
-                let mut pending = lock.lock().unwrap();
+                let mut pending = lock.lock();
                 *pending = false;
                 cvar.notify_one();
                 // notify shared stop flag
@@ -310,9 +413,7 @@ impl<'e> Pool<'e, JoinHandleImpl<'e>> for EventLoopImpl<'e> {
         if PoolState::Running == state {
             //开启了单独的线程
             let (lock, cvar) = &*self.stop;
-            let result = cvar
-                .wait_timeout_while(lock.lock().unwrap(), wait_time, |&mut pending| pending)
-                .unwrap();
+            let result = cvar.wait_timeout_while(lock.lock(), wait_time, |&mut pending| pending);
             if result.1.timed_out() {
                 return Err(Error::new(ErrorKind::TimedOut, "stop timeout !"));
             }
@@ -337,6 +438,8 @@ impl<'e> Pool<'e, JoinHandleImpl<'e>> for EventLoopImpl<'e> {
 
 impl<'e> EventLoop<'e> for EventLoopImpl<'e> {
     fn wait_event(&self, timeout: Option<Duration>) -> std::io::Result<usize> {
+        #[cfg(feature = "tracing")]
+        let _guard = crate::log::EventLoopGuard::enter(&self.name);
         let left_time = if SchedulableCoroutine::current().is_some() {
             timeout
         } else if let Some(time) = timeout {
@@ -442,10 +545,8 @@ impl<'e> EventLoop<'e> for EventLoopImpl<'e> {
         if count > 0 {
             //遍历events
             for event in &events {
-                if let Some(co_name) = Self::map_name(event.key) {
-                    //notify coroutine
-                    self.pool.try_resume(co_name).expect("has bug, notice !");
-                }
+                //notify coroutine
+                self.pool.try_resume(event.key).expect("has bug, notice !");
             }
         }
         Ok(count)
@@ -456,6 +557,27 @@ impl<'e> EventLoop<'e> for EventLoopImpl<'e> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_join_error() {
+        let error = JoinError::default().into_error("join failed");
+        assert_eq!("join failed", error.to_string());
+        let error = JoinError {
+            code: COROUTINE_FAILED,
+            error: Box::into_raw(Box::new(CoroutineError::from("test panic"))).cast::<c_void>(),
+        }
+        .into_error("join failed");
+        assert!(error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<CoroutineError>())
+            .is_some());
+        let error = JoinError {
+            code: JOIN_FAILED,
+            error: std::ptr::null_mut(),
+        }
+        .into_error("timeout join failed");
+        assert_eq!("timeout join failed", error.to_string());
+    }
+
     #[cfg(not(target_os = "linux"))]
     #[test]
     fn test_simple() -> std::io::Result<()> {
@@ -492,6 +614,53 @@ mod tests {
         event_loop.stop(Duration::from_secs(3))
     }
 
+    #[test]
+    fn test_wake_notify() -> std::io::Result<()> {
+        use std::sync::atomic::AtomicBool;
+
+        let event_loop = EventLoopImpl::default();
+        event_loop.set_max_size(1);
+        let waker: Arc<Mutex<Option<std::task::Waker>>> = Arc::new(Mutex::new(None));
+        let ready = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+        let (w, r, f) = (waker.clone(), ready.clone(), finished.clone());
+        _ = event_loop.submit(
+            None,
+            move |_| {
+                crate::future::block_on(std::future::poll_fn(|cx| {
+                    if r.load(Ordering::Acquire) {
+                        return Poll::Ready(());
+                    }
+                    *w.lock().unwrap() = Some(cx.waker().clone());
+                    Poll::Pending
+                }));
+                f.store(true, Ordering::Release);
+                None
+            },
+            None,
+        );
+        // park the coroutine in block_on
+        _ = event_loop.wait_event(Some(Duration::from_millis(10)))?;
+        assert!(waker.lock().unwrap().is_some());
+        _ = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            ready.store(true, Ordering::Release);
+            if let Some(waker) = waker.lock().unwrap().take() {
+                waker.wake();
+            }
+        });
+        // the wake interrupts the selector, no need to wait for the timeout
+        let start = open_coroutine_timer::now();
+        _ = event_loop.wait_event(Some(Duration::from_secs(3)))?;
+        assert!(open_coroutine_timer::now() - start < 1_000_000_000);
+        let deadline = open_coroutine_timer::get_timeout_time(Duration::from_secs(3));
+        while !finished.load(Ordering::Acquire) {
+            assert!(open_coroutine_timer::now() < deadline, "wake timeout");
+            _ = event_loop.wait_event(Some(Duration::from_millis(100)))?;
+        }
+        event_loop.stop(Duration::from_secs(3))
+    }
+
     #[test]
     fn test_simple_auto() -> std::io::Result<()> {
         let event_loop = EventLoopImpl::default().start()?;
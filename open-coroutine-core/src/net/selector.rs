@@ -112,6 +112,41 @@ impl SelectorImpl {
     }
 }
 
+impl SelectorImpl {
+    /// Interrupt the blocking `select`.
+    ///
+    /// # Errors
+    /// if notify failed.
+    pub fn notify(&self) -> std::io::Result<()> {
+        self.0.notify()
+    }
+
+    /// Delete the interests registered with `token`, e.g. the coroutine waiting for
+    /// them is cancelled.
+    ///
+    /// # Errors
+    /// if delete failed.
+    pub fn del_token_event(&self, token: usize) -> std::io::Result<()> {
+        let fd = match TOKEN_FD.get(&token) {
+            Some(r) => *r.value(),
+            None => return Ok(()),
+        };
+        if READABLE_TOKEN_RECORDS
+            .get(&fd)
+            .is_some_and(|r| *r.value() == token)
+        {
+            self.del_read_event(fd)?;
+        }
+        if WRITABLE_TOKEN_RECORDS
+            .get(&fd)
+            .is_some_and(|r| *r.value() == token)
+        {
+            self.del_write_event(fd)?;
+        }
+        Ok(())
+    }
+}
+
 impl Default for SelectorImpl {
     fn default() -> Self {
         Self::new().expect("create selector failed")
@@ -223,3 +258,29 @@ impl Selector for SelectorImpl {
         Ok(())
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_del_token_event() -> std::io::Result<()> {
+        let mut fds = [0; 2];
+        assert_eq!(0, unsafe { libc::pipe(fds.as_mut_ptr()) });
+        let selector = SelectorImpl::new()?;
+        let token = usize::MAX - 1;
+        selector.add_read_event(fds[0], token)?;
+        assert!(READABLE_RECORDS.contains(&fds[0]));
+        // other tokens are ignored
+        selector.del_token_event(token - 1)?;
+        assert!(READABLE_RECORDS.contains(&fds[0]));
+        selector.del_token_event(token)?;
+        assert!(!READABLE_RECORDS.contains(&fds[0]));
+        assert!(!READABLE_TOKEN_RECORDS.contains_key(&fds[0]));
+        unsafe {
+            _ = libc::close(fds[0]);
+            _ = libc::close(fds[1]);
+        }
+        Ok(())
+    }
+}
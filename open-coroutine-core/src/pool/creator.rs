@@ -1,5 +1,6 @@
 use crate::common::Current;
 use crate::constants::{Syscall, SyscallState};
+use crate::coroutine::error::CoroutineError;
 use crate::pool::{CoroutinePool, CoroutinePoolImpl};
 use crate::scheduler::listener::Listener;
 use crate::scheduler::SchedulableCoroutine;
@@ -22,11 +23,19 @@ impl Listener for CoroutineCreator {
         }
     }
 
-    fn on_error(&self, _: u64, _: &SchedulableCoroutine, _: &str) {
+    fn on_error(&self, _: u64, _: &SchedulableCoroutine, _: &CoroutineError) {
         if let Some(pool) = CoroutinePoolImpl::current() {
             //worker协程异常退出，需要先回收再创建
             _ = pool.running.fetch_sub(1, Ordering::Release);
             _ = pool.grow(true);
         }
     }
+
+    fn on_cancel(&self, _: u64, _: &SchedulableCoroutine) {
+        if let Some(pool) = CoroutinePoolImpl::current() {
+            //worker协程被取消，需要先回收再创建
+            _ = pool.running.fetch_sub(1, Ordering::Release);
+            _ = pool.grow(true);
+        }
+    }
 }
@@ -0,0 +1,233 @@
+use crate::constants::DEFAULT_GROUP;
+use crate::pool::task::{Task, TaskImpl};
+use crossbeam_deque::{Injector, Steal};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// The counters of a scheduling group.
+#[repr(C)]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct GroupStats {
+    /// The name of the group.
+    pub name: String,
+    /// The weight of the group.
+    pub weight: usize,
+    /// The number of tasks waiting in the group.
+    pub queued: usize,
+    /// The number of tasks submitted to the group.
+    pub submitted: u64,
+    /// The number of tasks finished in the group.
+    pub completed: u64,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct Group<'g> {
+    name: String,
+    weight: AtomicUsize,
+    //本轮剩余的配额
+    quota: AtomicUsize,
+    queue: Injector<TaskImpl<'g>>,
+    submitted: AtomicU64,
+    completed: AtomicU64,
+}
+
+impl<'g> Group<'g> {
+    fn new(name: &str, weight: usize) -> Self {
+        Group {
+            name: String::from(name),
+            weight: AtomicUsize::new(weight.max(1)),
+            quota: AtomicUsize::new(0),
+            queue: Injector::new(),
+            submitted: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+        }
+    }
+
+    //没有待执行和执行中的任务，且使用默认权重
+    fn is_idle(&self) -> bool {
+        DEFAULT_GROUP != self.name
+            && self.queue.is_empty()
+            && self.weight.load(Ordering::Acquire) == 1
+            && self.submitted.load(Ordering::Acquire) == self.completed.load(Ordering::Acquire)
+    }
+
+    fn pop(&self) -> Option<TaskImpl<'g>> {
+        loop {
+            match self.queue.steal() {
+                Steal::Success(item) => return Some(item),
+                Steal::Retry => {}
+                Steal::Empty => return None,
+            }
+        }
+    }
+}
+
+/// The weighted scheduling groups of a pool, the tasks are taken from the groups
+/// by weighted round robin, in every round a group gets at most as many tasks in a
+/// row as its weight. The cost of the tasks is unknown, so a group which runs longer
+/// tasks gets more time, and the unused quota of an empty group is dropped.
+///
+/// The tasks of `DEFAULT_GROUP` stay in the priority and deadline queues of the pool.
+/// The other groups are created by the first task or `set_weight`, and dropped when
+/// they are idle with the default weight.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct Groups<'g> {
+    //按创建顺序轮询，第一个是默认组
+    groups: RwLock<Vec<Group<'g>>>,
+    cursor: AtomicUsize,
+}
+
+impl Default for Groups<'_> {
+    fn default() -> Self {
+        Groups {
+            groups: RwLock::new(vec![Group::new(DEFAULT_GROUP, 1)]),
+            cursor: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<'g> Groups<'g> {
+    fn find<R>(&self, name: &str, f: impl FnOnce(&Group<'g>) -> R) -> Option<R> {
+        self.groups
+            .read()
+            .unwrap()
+            .iter()
+            .find(|g| g.name == name)
+            .map(f)
+    }
+
+    fn with<R>(&self, name: &str, f: impl FnOnce(&Group<'g>) -> R) -> R {
+        if let Some(group) = self.groups.read().unwrap().iter().find(|g| g.name == name) {
+            return f(group);
+        }
+        let mut groups = self.groups.write().unwrap();
+        if let Some(group) = groups.iter().find(|g| g.name == name) {
+            return f(group);
+        }
+        groups.push(Group::new(name, 1));
+        f(groups.last().expect("group not found"))
+    }
+
+    /// Set the weight of the group, the group is created if absent.
+    pub(crate) fn set_weight(&self, name: &str, weight: usize) {
+        self.with(name, |g| g.weight.store(weight.max(1), Ordering::Release));
+    }
+
+    /// Returns the number of tasks waiting in the named groups.
+    pub(crate) fn len(&self) -> usize {
+        self.groups
+            .read()
+            .unwrap()
+            .iter()
+            .map(|g| g.queue.len())
+            .sum()
+    }
+
+    /// Count a submitted task, the task is kept if it's in a named group,
+    /// otherwise it's returned to the caller.
+    pub(crate) fn push(&self, task: TaskImpl<'g>) -> Option<TaskImpl<'g>> {
+        let group = task.get_group();
+        let name = group.as_deref().unwrap_or(DEFAULT_GROUP);
+        self.with(name, |group| {
+            _ = group.submitted.fetch_add(1, Ordering::AcqRel);
+            if DEFAULT_GROUP == name {
+                return Some(task);
+            }
+            group.queue.push(task);
+            None
+        })
+    }
+
+    /// Count a finished task, the group is dropped if it becomes idle.
+    pub(crate) fn complete(&self, name: Option<&str>) {
+        let name = name.unwrap_or(DEFAULT_GROUP);
+        let idle = self
+            .find(name, |g| {
+                _ = g.completed.fetch_add(1, Ordering::AcqRel);
+                g.is_idle()
+            })
+            .unwrap_or(false);
+        if idle {
+            // 加写锁后再检查一次，期间可能有新任务提交
+            self.groups
+                .write()
+                .unwrap()
+                .retain(|g| g.name != name || !g.is_idle());
+        }
+    }
+
+    /// Count the task finished when the returned guard is dropped, even if
+    /// the task is unwound by cancelling its coroutine.
+    pub(crate) fn running<'r>(&'r self, name: Option<Arc<str>>) -> Running<'r, 'g> {
+        Running { groups: self, name }
+    }
+
+    /// Take a task, `pop_default` takes the tasks of `DEFAULT_GROUP`.
+    pub(crate) fn pop(
+        &self,
+        pop_default: impl Fn() -> Option<TaskImpl<'g>>,
+    ) -> Option<TaskImpl<'g>> {
+        let groups = self.groups.read().unwrap();
+        for _ in 0..groups.len() {
+            let index = self.cursor.load(Ordering::Acquire) % groups.len();
+            let group = &groups[index];
+            let task = if DEFAULT_GROUP == group.name {
+                pop_default()
+            } else {
+                group.pop()
+            };
+            if let Some(task) = task {
+                // 每轮开始时获得等于权重的配额，用完后轮到下一个组
+                if group.quota.load(Ordering::Acquire) == 0 {
+                    group
+                        .quota
+                        .store(group.weight.load(Ordering::Acquire), Ordering::Release);
+                }
+                if group.quota.fetch_sub(1, Ordering::AcqRel) == 1 {
+                    self.cursor.store(index + 1, Ordering::Release);
+                }
+                return Some(task);
+            }
+            // 空的组不能积攒配额
+            group.quota.store(0, Ordering::Release);
+            self.cursor.store(index + 1, Ordering::Release);
+        }
+        None
+    }
+
+    /// Returns the counters of all groups, `default_queued` is the number
+    /// of tasks waiting in `DEFAULT_GROUP`.
+    pub(crate) fn stats(&self, default_queued: usize) -> Vec<GroupStats> {
+        self.groups
+            .read()
+            .unwrap()
+            .iter()
+            .map(|g| GroupStats {
+                name: g.name.clone(),
+                weight: g.weight.load(Ordering::Acquire),
+                queued: if DEFAULT_GROUP == g.name {
+                    default_queued
+                } else {
+                    g.queue.len()
+                },
+                submitted: g.submitted.load(Ordering::Relaxed),
+                completed: g.completed.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// A task of the groups is running.
+#[derive(Debug)]
+pub(crate) struct Running<'r, 'g> {
+    groups: &'r Groups<'g>,
+    name: Option<Arc<str>>,
+}
+
+impl Drop for Running<'_, '_> {
+    fn drop(&mut self) {
+        self.groups.complete(self.name.as_deref());
+    }
+}
@@ -1,6 +1,10 @@
+use crate::coroutine::error::CoroutineError;
 use crate::pool::{CoroutinePoolImpl, Pool};
 use std::ffi::{c_char, CStr, CString};
+use std::future::Future;
 use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::time::Duration;
 
 /// Task join abstraction.
@@ -15,7 +19,10 @@ pub trait JoinHandle {
     ///
     /// # Errors
     /// see `timeout_at_join`.
-    fn timeout_join(&self, dur: Duration) -> std::io::Result<Result<Option<usize>, &str>> {
+    fn timeout_join(
+        &self,
+        dur: Duration,
+    ) -> std::io::Result<Result<Option<usize>, CoroutineError>> {
         self.timeout_at_join(open_coroutine_timer::get_timeout_time(dur))
     }
 
@@ -23,7 +30,7 @@ pub trait JoinHandle {
     ///
     /// # Errors
     /// see `timeout_at_join`.
-    fn join(&self) -> std::io::Result<Result<Option<usize>, &str>> {
+    fn join(&self) -> std::io::Result<Result<Option<usize>, CoroutineError>> {
         self.timeout_at_join(u64::MAX)
     }
 
@@ -31,7 +38,10 @@ pub trait JoinHandle {
     ///
     /// # Errors
     /// if join failed.
-    fn timeout_at_join(&self, timeout_time: u64) -> std::io::Result<Result<Option<usize>, &str>>;
+    fn timeout_at_join(
+        &self,
+        timeout_time: u64,
+    ) -> std::io::Result<Result<Option<usize>, CoroutineError>>;
 }
 
 #[allow(missing_docs)]
@@ -63,7 +73,10 @@ impl JoinHandle for JoinHandleImpl<'_> {
             .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid task name"))
     }
 
-    fn timeout_at_join(&self, timeout_time: u64) -> std::io::Result<Result<Option<usize>, &str>> {
+    fn timeout_at_join(
+        &self,
+        timeout_time: u64,
+    ) -> std::io::Result<Result<Option<usize>, CoroutineError>> {
         let name = self.get_name()?;
         if name.is_empty() {
             return Err(Error::new(ErrorKind::InvalidInput, "Invalid task name"));
@@ -77,6 +90,24 @@ impl JoinHandle for JoinHandleImpl<'_> {
     }
 }
 
+/// Await the task result from async code, the pool must be scheduled by another thread,
+/// or use `crate::future::block_on` in a coroutine of the pool.
+impl Future for JoinHandleImpl<'_> {
+    type Output = std::io::Result<Result<Option<usize>, CoroutineError>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let name = self.get_name()?;
+        if name.is_empty() {
+            return Poll::Ready(Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Invalid task name",
+            )));
+        }
+        let pool = unsafe { &*self.0 };
+        pool.poll_result(name, cx).map(Ok)
+    }
+}
+
 #[allow(box_pointers)]
 #[cfg(test)]
 mod tests {
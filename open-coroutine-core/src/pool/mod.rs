@@ -1,18 +1,28 @@
-use crate::common::{Blocker, Current, Named};
-use crate::constants::{PoolState, DEFAULT_STACK_SIZE};
+use crate::common::{Aging, Blocker, Current, Named};
+use crate::constants::{PoolState, Priority, SchedulePolicy, DEFAULT_STACK_SIZE};
+use crate::coroutine::error::CoroutineError;
 use crate::coroutine::suspender::SimpleSuspender;
+use crate::coroutine::Coroutine;
+use crate::metrics::{MetricsListener, PoolMetrics};
 use crate::pool::creator::CoroutineCreator;
+use crate::pool::group::{GroupStats, Groups};
 use crate::pool::join::{JoinHandle, JoinHandleImpl};
 use crate::pool::task::{Task, TaskImpl};
+use crate::scheduler::listener::Listener;
 use crate::scheduler::{SchedulableCoroutine, Scheduler, SchedulerImpl};
+use crate::sync::condvar::Condvar as CoCondvar;
+use crate::sync::mutex::Mutex as CoMutex;
+use crate::trace::TraceListener;
 use crossbeam_deque::{Injector, Steal};
 use dashmap::DashMap;
 use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::io::{Error, ErrorKind};
 use std::panic::{RefUnwindSafe, UnwindSafe};
 use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
 use std::time::Duration;
 
 /// Task abstraction and impl.
@@ -21,6 +31,9 @@ pub mod task;
 /// Task join abstraction and impl.
 pub mod join;
 
+/// Weighted scheduling groups.
+pub mod group;
+
 mod current;
 
 mod creator;
@@ -59,6 +72,21 @@ pub trait Pool<'p, Join: JoinHandle>: Debug + Default + RefUnwindSafe + Named {
     /// Returns in `ns` units.
     fn get_keep_alive_time(&self) -> u64;
 
+    /// Set the policy to order the tasks and the coroutines in this pool.
+    /// If it has not been set, it will be `SchedulePolicy::Priority`.
+    fn set_schedule_policy(&self, policy: SchedulePolicy);
+
+    /// Get the policy to order the tasks and the coroutines in this pool.
+    fn get_schedule_policy(&self) -> SchedulePolicy;
+
+    /// Set the weight of the scheduling group, the group is created if absent.
+    /// The tasks are taken from the groups in proportion to their weights, the
+    /// weight of `crate::constants::DEFAULT_GROUP` and the new groups is 1.
+    fn set_group_weight(&self, group: &str, weight: usize);
+
+    /// Returns the queue length and throughput counters of the scheduling groups.
+    fn group_stats(&self) -> Vec<GroupStats>;
+
     /// Returns `true` if the task queue is empty.
     fn is_empty(&self) -> bool {
         self.size() == 0
@@ -78,7 +106,7 @@ pub trait Pool<'p, Join: JoinHandle>: Debug + Default + RefUnwindSafe + Named {
         func: impl FnOnce(Option<usize>) -> Option<usize> + UnwindSafe + 'p,
         param: Option<usize>,
         wait_time: Duration,
-    ) -> std::io::Result<Option<(String, Result<Option<usize>, &str>)>> {
+    ) -> std::io::Result<Option<(String, Result<Option<usize>, CoroutineError>)>> {
         let join = self.submit(name, func, param);
         self.wait_result(join.get_name()?, wait_time)
     }
@@ -93,7 +121,7 @@ pub trait Pool<'p, Join: JoinHandle>: Debug + Default + RefUnwindSafe + Named {
         &self,
         task_name: &str,
         wait_time: Duration,
-    ) -> std::io::Result<Option<(String, Result<Option<usize>, &str>)>>;
+    ) -> std::io::Result<Option<(String, Result<Option<usize>, CoroutineError>)>>;
 
     /// Submit a new task to this pool.
     ///
@@ -105,11 +133,72 @@ pub trait Pool<'p, Join: JoinHandle>: Debug + Default + RefUnwindSafe + Named {
         func: impl FnOnce(Option<usize>) -> Option<usize> + UnwindSafe + 'p,
         param: Option<usize>,
     ) -> Join {
-        self.submit_raw(TaskImpl::new(
+        self.submit_with_priority(name, func, param, Priority::default())
+    }
+
+    /// Submit a new task with the given `priority` to this pool, the higher priority
+    /// tasks are always taken first, and the coroutine runs with the task's priority.
+    ///
+    /// Allow multiple threads to concurrently submit task to the pool,
+    /// but only allow one thread to execute scheduling.
+    fn submit_with_priority(
+        &self,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + UnwindSafe + 'p,
+        param: Option<usize>,
+        priority: Priority,
+    ) -> Join {
+        let task = TaskImpl::new(
             name.unwrap_or(format!("{}|{}", self.get_name(), uuid::Uuid::new_v4())),
             func,
             param,
-        ))
+        );
+        _ = task.set_priority(priority);
+        self.submit_raw(task)
+    }
+
+    /// Submit a new task with the given `deadline` timestamp in ns to this pool,
+    /// with `SchedulePolicy::Deadline` the task with the earliest deadline is taken
+    /// first, and the coroutine runs with the task's deadline. If the task is taken
+    /// after its deadline, `Listener::on_deadline_missed` is called with the coroutine.
+    ///
+    /// Allow multiple threads to concurrently submit task to the pool,
+    /// but only allow one thread to execute scheduling.
+    fn submit_with_deadline(
+        &self,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + UnwindSafe + 'p,
+        param: Option<usize>,
+        deadline: u64,
+    ) -> Join {
+        let task = TaskImpl::new(
+            name.unwrap_or(format!("{}|{}", self.get_name(), uuid::Uuid::new_v4())),
+            func,
+            param,
+        );
+        _ = task.set_deadline(Some(deadline));
+        self.submit_raw(task)
+    }
+
+    /// Submit a new task into the scheduling `group` of this pool,
+    /// see `set_group_weight`.
+    ///
+    /// Allow multiple threads to concurrently submit task to the pool,
+    /// but only allow one thread to execute scheduling.
+    fn submit_to_group(
+        &self,
+        group: &str,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> Option<usize> + UnwindSafe + 'p,
+        param: Option<usize>,
+    ) -> Join {
+        let task = TaskImpl::new(
+            name.unwrap_or(format!("{}|{}", self.get_name(), uuid::Uuid::new_v4())),
+            func,
+            param,
+        );
+        _ = task.set_group(Some(Arc::from(group)));
+        self.submit_raw(task)
     }
 
     /// Submit new task to this pool.
@@ -170,7 +259,7 @@ pub trait CoroutinePool<'p>: Current<'p> + Pool<'p, JoinHandleImpl<'p>> {
     ///
     /// # Errors
     /// if change to ready fails.
-    fn try_resume(&self, co_name: &'p str) -> std::io::Result<()>;
+    fn try_resume(&self, co_id: usize) -> std::io::Result<()>;
 
     /// Attempt to run a task in current coroutine or thread.
     fn try_run(&self) -> Option<()>;
@@ -216,7 +305,10 @@ pub trait CoroutinePool<'p>: Current<'p> + Pool<'p, JoinHandleImpl<'p>> {
     fn try_timeout_schedule(&self, timeout_time: u64) -> std::io::Result<u64>;
 
     /// Attempt to obtain task results with the given `task_name`.
-    fn try_get_result(&self, task_name: &str) -> Option<(String, Result<Option<usize>, &str>)>;
+    fn try_get_result(
+        &self,
+        task_name: &str,
+    ) -> Option<(String, Result<Option<usize>, CoroutineError>)>;
 }
 
 #[allow(missing_docs, box_pointers, dead_code)]
@@ -227,8 +319,20 @@ pub struct CoroutinePoolImpl<'p> {
     cpu: usize,
     //协程池状态
     state: Cell<PoolState>,
-    //任务队列
-    task_queue: Injector<TaskImpl<'p>>,
+    //任务队列，按优先级从高到低
+    task_queue: [Injector<TaskImpl<'p>>; 3],
+    //防止低优先级任务饿死
+    aging: Aging,
+    //按(截止时间, 序号)排序的任务队列
+    deadline_queue: Mutex<BTreeMap<(u64, usize), TaskImpl<'p>>>,
+    //截止时间任务数，为0时无需加锁
+    deadline_len: AtomicUsize,
+    //保证截止时间相同的任务先进先出
+    deadline_seq: AtomicUsize,
+    //按权重轮询的调度组
+    groups: Groups<'p>,
+    //当前调度的超时时间
+    timeout_time: AtomicU64,
     //工作协程组
     workers: UnsafeCell<SchedulerImpl<'p>>,
     //是否正在调度，不允许多线程并行调度
@@ -246,15 +350,20 @@ pub struct CoroutinePoolImpl<'p> {
     //阻滞器
     blocker: RefCell<Box<dyn Blocker + 'p>>,
     //任务执行结果
-    results: DashMap<String, Result<Option<usize>, &'p str>>,
+    results: DashMap<String, Result<Option<usize>, CoroutineError>>,
     //正在等待结果的
-    waits: DashMap<&'p str, Arc<(Mutex<bool>, Condvar)>>,
+    waits: DashMap<&'p str, Arc<(CoMutex<bool>, CoCondvar)>>,
+    //正在异步等待结果的
+    wakers: DashMap<String, Waker>,
     //用于停止额外线程
     stop: Arc<(Mutex<bool>, Condvar)>,
+    //队列深度、协程数等指标
+    metrics: Arc<PoolMetrics>,
 }
 
 impl Drop for CoroutinePoolImpl<'_> {
     fn drop(&mut self) {
+        self.metrics.unregister();
         if !std::thread::panicking() {
             assert_eq!(
                 0,
@@ -272,6 +381,107 @@ unsafe impl Sync for CoroutinePoolImpl<'_> {}
 
 impl RefUnwindSafe for CoroutinePoolImpl<'_> {}
 
+impl<'p> CoroutinePoolImpl<'p> {
+    /// Poll the result of the task, the `Waker` will be woken when the task finished.
+    pub(crate) fn poll_result(
+        &self,
+        task_name: &str,
+        cx: &Context<'_>,
+    ) -> Poll<Result<Option<usize>, CoroutineError>> {
+        if let Some((_, result)) = self.try_get_result(task_name) {
+            return Poll::Ready(result);
+        }
+        _ = self
+            .wakers
+            .insert(task_name.to_string(), cx.waker().clone());
+        // 注册Waker前任务可能已经完成
+        if let Some((_, result)) = self.try_get_result(task_name) {
+            _ = self.wakers.remove(task_name);
+            return Poll::Ready(result);
+        }
+        Poll::Pending
+    }
+
+    /// Add a listener to the scheduler of this pool.
+    pub fn add_listener(&mut self, listener: impl Listener + 'p) {
+        self.workers.get_mut().add_listener(listener);
+    }
+
+    /// Call `notify` when a coroutine parked in `block_on` is woken, see
+    /// `SchedulerImpl::set_wake_notify`.
+    pub fn set_wake_notify(&mut self, notify: impl Fn() + Send + Sync + 'static) {
+        self.workers.get_mut().set_wake_notify(notify);
+    }
+
+    fn task_queue(&self, priority: Priority) -> &Injector<TaskImpl<'p>> {
+        match priority {
+            Priority::High => &self.task_queue[0],
+            Priority::Normal => &self.task_queue[1],
+            Priority::Low => &self.task_queue[2],
+        }
+    }
+
+    fn pop_priority(&self) -> Option<TaskImpl<'p>> {
+        loop {
+            let priority = self
+                .aging
+                .pick(|priority| self.task_queue(priority).is_empty())?;
+            match self.task_queue(priority).steal() {
+                Steal::Success(item) => return Some(item),
+                // other priorities may still have tasks
+                Steal::Retry | Steal::Empty => continue,
+            }
+        }
+    }
+
+    fn default_size(&self) -> usize {
+        self.task_queue.iter().map(Injector::len).sum::<usize>()
+            + self.deadline_len.load(Ordering::Acquire)
+    }
+
+    fn pop_default(&self) -> Option<TaskImpl<'p>> {
+        // 切换策略后，另一种队列中剩余的任务仍需被执行
+        match self.get_schedule_policy() {
+            SchedulePolicy::Deadline
+                if self
+                    .aging
+                    .pick_deadline(self.task_queue.iter().all(Injector::is_empty)) =>
+            {
+                self.pop_deadline().or_else(|| self.pop_priority())
+            }
+            // 没有截止时间的任务也会老化，不会被饿死
+            SchedulePolicy::Deadline | SchedulePolicy::Priority => {
+                self.pop_priority().or_else(|| self.pop_deadline())
+            }
+        }
+    }
+
+    fn push(&self, task: TaskImpl<'p>) {
+        let Some(task) = self.groups.push(task) else {
+            return;
+        };
+        if let (SchedulePolicy::Deadline, Some(deadline)) =
+            (self.get_schedule_policy(), task.get_deadline())
+        {
+            let seq = self.deadline_seq.fetch_add(1, Ordering::Relaxed);
+            let mut deadline_queue = self.deadline_queue.lock().unwrap();
+            _ = deadline_queue.insert((deadline, seq), task);
+            _ = self.deadline_len.fetch_add(1, Ordering::Release);
+            return;
+        }
+        self.task_queue(task.get_priority()).push(task);
+    }
+
+    fn pop_deadline(&self) -> Option<TaskImpl<'p>> {
+        if self.deadline_len.load(Ordering::Acquire) == 0 {
+            return None;
+        }
+        let (_, task) = self.deadline_queue.lock().unwrap().pop_first()?;
+        _ = self.deadline_len.fetch_sub(1, Ordering::Release);
+        Some(task)
+    }
+}
+
 impl Named for CoroutinePoolImpl<'_> {
     fn get_name(&self) -> &str {
         unsafe { (*self.workers.get()).get_name() }
@@ -343,8 +553,24 @@ impl<'p> Pool<'p, JoinHandleImpl<'p>> for CoroutinePoolImpl<'p> {
         self.keep_alive_time.load(Ordering::Acquire)
     }
 
+    fn set_schedule_policy(&self, policy: SchedulePolicy) {
+        unsafe { (*self.workers.get()).set_schedule_policy(policy) };
+    }
+
+    fn get_schedule_policy(&self) -> SchedulePolicy {
+        unsafe { (*self.workers.get()).get_schedule_policy() }
+    }
+
+    fn set_group_weight(&self, group: &str, weight: usize) {
+        self.groups.set_weight(group, weight);
+    }
+
+    fn group_stats(&self) -> Vec<GroupStats> {
+        self.groups.stats(self.default_size())
+    }
+
     fn size(&self) -> usize {
-        self.task_queue.len()
+        self.default_size() + self.groups.len()
     }
 
     #[allow(box_pointers)]
@@ -352,35 +578,40 @@ impl<'p> Pool<'p, JoinHandleImpl<'p>> for CoroutinePoolImpl<'p> {
         &self,
         task_name: &str,
         wait_time: Duration,
-    ) -> std::io::Result<Option<(String, Result<Option<usize>, &str>)>> {
+    ) -> std::io::Result<Option<(String, Result<Option<usize>, CoroutineError>)>> {
         let key = Box::leak(Box::from(task_name));
         if let Some(r) = self.try_get_result(key) {
             _ = self.waits.remove(key);
             return Ok(Some(r));
         }
+        let timeout_time = open_coroutine_timer::get_timeout_time(wait_time);
         if SchedulableCoroutine::current().is_some() {
-            let timeout_time = open_coroutine_timer::get_timeout_time(wait_time);
-            loop {
-                _ = self.try_run();
+            //协程中先执行排队的任务，避免唯一的工作协程等待自己
+            while open_coroutine_timer::now() < timeout_time && self.try_run().is_some() {
                 if let Some(r) = self.try_get_result(key) {
                     return Ok(Some(r));
                 }
-                if timeout_time.saturating_sub(open_coroutine_timer::now()) == 0 {
-                    return Err(Error::new(ErrorKind::TimedOut, "wait timeout"));
-                }
             }
         }
         let arc = if let Some(arc) = self.waits.get(key) {
             arc.clone()
         } else {
-            let arc = Arc::new((Mutex::new(true), Condvar::new()));
+            let arc = Arc::new((CoMutex::new(true), CoCondvar::new()));
             assert!(self.waits.insert(key, arc.clone()).is_none());
             arc
         };
         let (lock, cvar) = &*arc;
-        _ = cvar
-            .wait_timeout_while(lock.lock().unwrap(), wait_time, |&mut pending| pending)
-            .unwrap();
+        let pending = lock.lock();
+        // 登记等待前任务可能已经完成
+        if self.try_get_result(key).is_none() {
+            //协程中挂起而不是阻塞线程
+            let left_time = timeout_time.saturating_sub(open_coroutine_timer::now());
+            _ = cvar.wait_timeout_while(pending, Duration::from_nanos(left_time), |&mut pending| {
+                pending
+            });
+        } else {
+            drop(pending);
+        }
         if let Some(r) = self.try_get_result(key) {
             assert!(self.waits.remove(key).is_some());
             return Ok(Some(r));
@@ -391,7 +622,11 @@ impl<'p> Pool<'p, JoinHandleImpl<'p>> for CoroutinePoolImpl<'p> {
     #[allow(box_pointers)]
     fn submit_raw(&self, task: TaskImpl<'p>) -> JoinHandleImpl<'p> {
         let task_name = Box::leak(Box::from(task.get_name()));
-        self.task_queue.push(task);
+        if crate::trace::is_recording() {
+            crate::trace::instant("task", "submit", vec![("task", String::from(&*task_name))]);
+        }
+        self.push(task);
+        self.metrics.queued.store(self.size(), Ordering::Relaxed);
         JoinHandleImpl::new(self, task_name)
     }
 
@@ -400,13 +635,11 @@ impl<'p> Pool<'p, JoinHandleImpl<'p>> for CoroutinePoolImpl<'p> {
         if self.is_empty() {
             return None;
         }
-        loop {
-            match self.task_queue.steal() {
-                Steal::Success(item) => return Some(item),
-                Steal::Retry => continue,
-                Steal::Empty => return None,
-            }
+        let task = self.groups.pop(|| self.pop_default());
+        if task.is_some() {
+            self.metrics.queued.store(self.size(), Ordering::Relaxed);
         }
+        task
     }
 
     #[allow(box_pointers)]
@@ -527,6 +760,7 @@ impl<'p> CoroutinePool<'p> for CoroutinePoolImpl<'p> {
     where
         Self: Sized,
     {
+        let metrics = PoolMetrics::register(&name);
         let mut pool = CoroutinePoolImpl {
             cpu,
             state: Cell::new(PoolState::Created),
@@ -536,12 +770,20 @@ impl<'p> CoroutinePool<'p> for CoroutinePoolImpl<'p> {
             pop_fail_times: AtomicUsize::new(0),
             min_size: AtomicUsize::new(min_size),
             max_size: AtomicUsize::new(max_size),
-            task_queue: Injector::default(),
+            task_queue: Default::default(),
+            aging: Aging::default(),
+            deadline_queue: Mutex::new(BTreeMap::new()),
+            deadline_len: AtomicUsize::new(0),
+            deadline_seq: AtomicUsize::new(0),
+            timeout_time: AtomicU64::new(0),
+            groups: Groups::default(),
             keep_alive_time: AtomicU64::new(keep_alive_time),
             blocker: RefCell::new(Box::new(blocker)),
             results: DashMap::new(),
             waits: DashMap::new(),
+            wakers: DashMap::new(),
             stop: Arc::new((Mutex::new(true), Condvar::new())),
+            metrics,
         };
         pool.init();
         pool
@@ -549,32 +791,71 @@ impl<'p> CoroutinePool<'p> for CoroutinePoolImpl<'p> {
 
     #[allow(box_pointers)]
     fn init(&mut self) {
-        unsafe { (*self.workers.get()).add_listener(CoroutineCreator::default()) };
+        let workers = self.workers.get_mut();
+        workers.add_listener(CoroutineCreator::default());
+        if crate::metrics::get_enable() {
+            let listener = MetricsListener::new(workers.get_name());
+            workers.add_listener(listener);
+        }
+        if crate::trace::get_enable() {
+            workers.add_listener(TraceListener::default());
+        }
     }
 
     fn set_stack_size(&self, stack_size: usize) {
         unsafe { (*self.workers.get()).set_stack_size(stack_size) };
     }
 
-    fn try_resume(&self, co_name: &'p str) -> std::io::Result<()> {
-        unsafe { (*self.workers.get()).try_resume(co_name) }
+    fn try_resume(&self, co_id: usize) -> std::io::Result<()> {
+        unsafe { (*self.workers.get()).try_resume(co_id) }
     }
 
     fn try_run(&self) -> Option<()> {
         #[allow(box_pointers)]
         self.pop().map(|task| {
+            // 协程以任务的优先级和截止时间运行
+            let previous = SchedulableCoroutine::current().map(|coroutine| {
+                (
+                    coroutine.set_priority(task.get_priority()),
+                    coroutine.set_deadline(task.get_deadline()),
+                )
+            });
+            let running = self.groups.running(task.get_group());
+            let begin = open_coroutine_timer::now();
+            if let (Some(coroutine), Some(deadline)) =
+                (SchedulableCoroutine::current(), task.get_deadline())
+            {
+                if deadline < begin {
+                    let timeout_time = self.timeout_time.load(Ordering::Acquire);
+                    unsafe { (*self.workers.get()).on_deadline_missed(timeout_time, coroutine) };
+                }
+            }
             let (task_name, result) = task.run();
+            if crate::trace::is_recording() {
+                let ok = result.is_ok().to_string();
+                crate::trace::slice("task", &task_name, begin, vec![("ok", ok)]);
+            }
+            drop(running);
+            if let (Some(coroutine), Some((priority, deadline))) =
+                (SchedulableCoroutine::current(), previous)
+            {
+                _ = coroutine.set_priority(priority);
+                _ = coroutine.set_deadline(deadline);
+            }
             assert!(
                 self.results.insert(task_name.clone(), result).is_none(),
                 "The previous result was not retrieved in a timely manner"
             );
             if let Some(arc) = self.waits.get(&*task_name) {
                 let (lock, cvar) = &**arc;
-                let mut pending = lock.lock().unwrap();
+                let mut pending = lock.lock();
                 *pending = false;
                 // Notify the condvar that the value has changed.
                 cvar.notify_one();
             }
+            if let Some((_, waker)) = self.wakers.remove(&task_name) {
+                waker.wake();
+            }
         })
     }
 
@@ -605,6 +886,8 @@ impl<'p> CoroutinePool<'p> for CoroutinePoolImpl<'p> {
                             //回收worker协程
                             pool.running
                                 .store(running.saturating_sub(1), Ordering::Release);
+                            _ = pool.metrics.shrunk.fetch_add(1, Ordering::Relaxed);
+                            _ = pool.metrics.running.fetch_sub(1, Ordering::Relaxed);
                             return;
                         }
                         _ = pool.pop_fail_times.fetch_add(1, Ordering::Release);
@@ -629,6 +912,8 @@ impl<'p> CoroutinePool<'p> for CoroutinePoolImpl<'p> {
             )?;
         }
         _ = self.running.fetch_add(1, Ordering::Release);
+        _ = self.metrics.grown.fetch_add(1, Ordering::Relaxed);
+        _ = self.metrics.running.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
 
@@ -639,6 +924,7 @@ impl<'p> CoroutinePool<'p> for CoroutinePoolImpl<'p> {
             .is_ok()
         {
             Self::init_current(self);
+            self.timeout_time.store(timeout_time, Ordering::Release);
             let should_grow = match self.get_state() {
                 PoolState::Created | PoolState::Running => true,
                 PoolState::Stopping(_) | PoolState::Stopped => false,
@@ -655,7 +941,10 @@ impl<'p> CoroutinePool<'p> for CoroutinePoolImpl<'p> {
         Ok(timeout_time.saturating_sub(open_coroutine_timer::now()))
     }
 
-    fn try_get_result(&self, task_name: &str) -> Option<(String, Result<Option<usize>, &str>)> {
+    fn try_get_result(
+        &self,
+        task_name: &str,
+    ) -> Option<(String, Result<Option<usize>, CoroutineError>)> {
         self.results.remove(task_name)
     }
 }
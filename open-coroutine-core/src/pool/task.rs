@@ -1,7 +1,13 @@
-use crate::common::Named;
+use crate::common::{Current, Named};
+use crate::constants::{CoroutineState, Priority};
+use crate::coroutine::error::CoroutineError;
+use crate::coroutine::local::{CoroutineLocal, Inherited};
+use crate::coroutine::StateMachine;
+use crate::scheduler::SchedulableCoroutine;
 use std::cell::Cell;
 use std::fmt::{Debug, Formatter};
 use std::panic::UnwindSafe;
+use std::sync::Arc;
 
 /// A trait implemented for describing task.
 /// Note: the param and the result is raw pointer.
@@ -19,11 +25,29 @@ pub trait Task<'t>: Named + UnwindSafe {
     /// Get param from this task.
     fn get_param(&self) -> Option<usize>;
 
+    /// Set the priority for this task, returns the previous one.
+    fn set_priority(&self, priority: Priority) -> Priority;
+
+    /// Get the priority of this task.
+    fn get_priority(&self) -> Priority;
+
+    /// Set the deadline timestamp in ns for this task, returns the previous one.
+    fn set_deadline(&self, deadline: Option<u64>) -> Option<u64>;
+
+    /// Get the deadline timestamp of this task.
+    fn get_deadline(&self) -> Option<u64>;
+
+    /// Set the scheduling group for this task, returns the previous one.
+    fn set_group(&self, group: Option<Arc<str>>) -> Option<Arc<str>>;
+
+    /// Get the scheduling group of this task.
+    fn get_group(&self) -> Option<Arc<str>>;
+
     /// exec the task
     ///
     /// # Errors
     /// if an exception occurred while executing this task.
-    fn run<'e>(self) -> (String, Result<Option<usize>, &'e str>);
+    fn run(self) -> (String, Result<Option<usize>, CoroutineError>);
 }
 
 #[repr(C)]
@@ -32,6 +56,10 @@ pub struct TaskImpl<'t> {
     name: String,
     func: Box<dyn FnOnce(Option<usize>) -> Option<usize> + UnwindSafe + 't>,
     param: Cell<Option<usize>>,
+    priority: Cell<Priority>,
+    deadline: Cell<Option<u64>>,
+    group: Cell<Option<Arc<str>>>,
+    inherited: Inherited,
 }
 
 impl UnwindSafe for TaskImpl<'_> {}
@@ -41,6 +69,10 @@ impl Debug for TaskImpl<'_> {
         f.debug_struct("Task")
             .field("name", &self.name)
             .field("param", &self.param)
+            .field("priority", &self.priority)
+            .field("deadline", &self.deadline)
+            .field("group", &self.get_group())
+            .field("inherited", &self.inherited)
             .finish_non_exhaustive()
     }
 }
@@ -62,6 +94,13 @@ impl<'t> Task<'t> for TaskImpl<'t> {
             name,
             func: Box::new(func),
             param: Cell::new(param),
+            priority: Cell::new(Priority::default()),
+            deadline: Cell::new(None),
+            group: Cell::new(None),
+            // 提交任务的协程中可继承的本地变量
+            inherited: CoroutineLocal::current()
+                .map(CoroutineLocal::inherited)
+                .unwrap_or_default(),
         }
     }
 
@@ -73,19 +112,61 @@ impl<'t> Task<'t> for TaskImpl<'t> {
         self.param.get()
     }
 
+    fn set_priority(&self, priority: Priority) -> Priority {
+        self.priority.replace(priority)
+    }
+
+    fn get_priority(&self) -> Priority {
+        self.priority.get()
+    }
+
+    fn set_deadline(&self, deadline: Option<u64>) -> Option<u64> {
+        self.deadline.replace(deadline)
+    }
+
+    fn get_deadline(&self) -> Option<u64> {
+        self.deadline.get()
+    }
+
+    fn set_group(&self, group: Option<Arc<str>>) -> Option<Arc<str>> {
+        self.group.replace(group)
+    }
+
+    fn get_group(&self) -> Option<Arc<str>> {
+        let group = self.group.take();
+        self.group.set(group.clone());
+        group
+    }
+
     #[allow(box_pointers)]
-    fn run<'e>(self) -> (String, Result<Option<usize>, &'e str>) {
+    fn run(self) -> (String, Result<Option<usize>, CoroutineError>) {
         let paran = self.get_param();
-        (
-            self.name.clone(),
-            std::panic::catch_unwind(|| (self.func)(paran)).map_err(|e| {
-                let message = *e
-                    .downcast_ref::<&'static str>()
-                    .unwrap_or(&"task failed without message");
-                crate::error!("task:{} finish with error:{}", self.name, message);
-                message
-            }),
-        )
+        let name = self.name;
+        let func = self.func;
+        let run = || {
+            std::panic::catch_unwind(|| func(paran)).map_err(|e| {
+                // 协程被取消，继续展开协程栈
+                if SchedulableCoroutine::current()
+                    .is_some_and(|c| CoroutineState::Cancelled == c.state())
+                {
+                    std::panic::resume_unwind(e);
+                }
+                let error = CoroutineError::from(e);
+                crate::error!("task:{} finish with error:{}", name, error);
+                error
+            })
+        };
+        // 每个任务都有自己的协程本地变量
+        let result = if let Some(local) = CoroutineLocal::current() {
+            local.scope(self.inherited, run)
+        } else {
+            let local = CoroutineLocal::default();
+            CoroutineLocal::init_current(&local);
+            let result = local.scope(self.inherited, run);
+            CoroutineLocal::clean_current();
+            result
+        };
+        (name, result)
     }
 }
 
@@ -116,8 +197,28 @@ mod tests {
             None,
         );
         assert_eq!(
-            (String::from("test"), Err("test panic, just ignore it")),
+            (
+                String::from("test"),
+                Err(CoroutineError::from("test panic, just ignore it"))
+            ),
             task.run()
         );
     }
+
+    #[test]
+    fn test_inherit() {
+        crate::inheritable_coroutine_local! {
+            static REQUEST_ID: Cell<usize> = Cell::new(0);
+        }
+        let local = CoroutineLocal::default();
+        CoroutineLocal::init_current(&local);
+        REQUEST_ID.with(|id| id.set(1));
+        let task = TaskImpl::new(
+            String::from("test"),
+            |_| Some(REQUEST_ID.with(Cell::get)),
+            None,
+        );
+        CoroutineLocal::clean_current();
+        assert_eq!((String::from("test"), Ok(Some(1))), task.run());
+    }
 }
@@ -1,4 +1,5 @@
 use super::*;
+use crate::constants::DEFAULT_GROUP;
 use crate::coroutine::suspender::SimpleDelaySuspender;
 use crate::scheduler::SchedulableSuspender;
 
@@ -27,7 +28,7 @@ fn test_simple() {
     assert_eq!(
         Some((
             String::from("test_panic"),
-            Err("test panic, just ignore it")
+            Err(CoroutineError::from("test panic, just ignore it"))
         )),
         pool.try_get_result("test_panic")
     );
@@ -200,3 +201,262 @@ fn test_wait_auto() -> std::io::Result<()> {
     assert_eq!(Some((task_name, Ok(Some(2)))), result.unwrap());
     pool.stop(Duration::from_secs(3))
 }
+
+#[allow(box_pointers)]
+#[test]
+fn test_wait_in_coroutine() -> std::io::Result<()> {
+    use crate::sync::tests::{schedule, submit};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    let pool = CoroutinePoolImpl::default();
+    _ = pool.change_blocker(crate::common::DelayBlocker::default());
+    let pool = pool.start()?;
+    let task_name = uuid::Uuid::new_v4().to_string();
+    let started = Arc::new(AtomicBool::new(false));
+    let s = started.clone();
+    _ = pool.submit(
+        Some(task_name.clone()),
+        move |_| {
+            s.store(true, Ordering::Release);
+            std::thread::sleep(Duration::from_millis(100));
+            Some(2)
+        },
+        None,
+    );
+    while !started.load(Ordering::Acquire) {
+        std::thread::yield_now();
+    }
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let mut scheduler = SchedulerImpl::default();
+    let (p, o) = (pool.clone(), order.clone());
+    submit(&scheduler, move |_, ()| {
+        let result = p.wait_result(&task_name, Duration::from_secs(3));
+        assert_eq!(Some((task_name, Ok(Some(2)))), result.unwrap());
+        o.lock().unwrap().push("wait");
+    })?;
+    let o = order.clone();
+    submit(&scheduler, move |_, ()| o.lock().unwrap().push("other"))?;
+    schedule(&mut scheduler)?;
+    // the local queues are shared with the schedulers of other tests
+    drop(scheduler);
+    //等待结果的协程被挂起，不会阻塞其他协程
+    assert_eq!(vec!["other", "wait"], *order.lock().unwrap());
+    pool.stop(Duration::from_secs(3))
+}
+
+#[test]
+fn test_priority() -> std::io::Result<()> {
+    let executed = Arc::new(Mutex::new(Vec::new()));
+    let pool = CoroutinePoolImpl::default();
+    pool.set_max_size(1);
+    for priority in [Priority::Low, Priority::Normal, Priority::High] {
+        let executed = executed.clone();
+        _ = pool.submit_with_priority(
+            None,
+            move |_| {
+                let coroutine = SchedulableCoroutine::current().expect("not in coroutine");
+                assert_eq!(priority, coroutine.priority());
+                executed.lock().unwrap().push(priority);
+                None
+            },
+            None,
+            priority,
+        );
+    }
+    pool.try_schedule()?;
+    assert_eq!(
+        vec![Priority::High, Priority::Normal, Priority::Low],
+        *executed.lock().unwrap()
+    );
+    Ok(())
+}
+
+#[allow(box_pointers)]
+#[test]
+fn test_await() -> std::io::Result<()> {
+    let pool = CoroutinePoolImpl::default();
+    _ = pool.change_blocker(crate::common::DelayBlocker::default());
+    let pool = pool.start()?;
+    let handle = pool.submit(
+        None,
+        |_| {
+            // await another task in the coroutine without blocking the thread
+            let pool = CoroutinePoolImpl::current().expect("current pool not found");
+            let handle = pool.submit(None, |_| Some(1), None);
+            let result = crate::future::block_on(handle).expect("await failed");
+            result.expect("task failed").map(|r| r + 1)
+        },
+        None,
+    );
+    assert_eq!(Some(2), crate::future::block_on(handle)?.unwrap());
+    pool.stop(Duration::from_secs(3))
+}
+
+#[test]
+fn test_deadline() -> std::io::Result<()> {
+    let executed = Arc::new(Mutex::new(Vec::new()));
+    let pool = CoroutinePoolImpl::default();
+    pool.set_max_size(1);
+    pool.set_schedule_policy(SchedulePolicy::Deadline);
+    let now = open_coroutine_timer::now();
+    for offset in [3, 1, 2] {
+        let executed = executed.clone();
+        let deadline = now + offset * 1_000_000_000;
+        _ = pool.submit_with_deadline(
+            None,
+            move |_| {
+                let coroutine = SchedulableCoroutine::current().expect("not in coroutine");
+                assert_eq!(Some(deadline), coroutine.deadline());
+                executed.lock().unwrap().push(offset);
+                None
+            },
+            None,
+            deadline,
+        );
+    }
+    pool.try_schedule()?;
+    assert_eq!(vec![1, 2, 3], *executed.lock().unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_deadline_missed() -> std::io::Result<()> {
+    #[derive(Debug)]
+    struct Missed(Arc<Mutex<Vec<Option<u64>>>>);
+
+    impl Listener for Missed {
+        fn on_deadline_missed(&self, _: u64, coroutine: &SchedulableCoroutine) {
+            self.0.lock().unwrap().push(coroutine.deadline());
+        }
+    }
+
+    let missed = Arc::new(Mutex::new(Vec::new()));
+    let mut pool = CoroutinePoolImpl::default();
+    pool.set_max_size(1);
+    pool.add_listener(Missed(missed.clone()));
+    let now = open_coroutine_timer::now();
+    let (late, early) = (now - 1, now + 3_000_000_000);
+    for deadline in [late, early] {
+        _ = pool.submit_with_deadline(None, |_| None, None, deadline);
+    }
+    // the worker may be stolen by other schedulers, schedule until the tasks are taken
+    let timeout_time = open_coroutine_timer::get_timeout_time(Duration::from_secs(3));
+    while !pool.is_empty() {
+        assert!(
+            open_coroutine_timer::now() < timeout_time,
+            "schedule timeout"
+        );
+        _ = pool.try_timed_schedule(Duration::from_millis(10))?;
+    }
+    assert_eq!(vec![Some(late)], *missed.lock().unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_group() -> std::io::Result<()> {
+    let executed = Arc::new(Mutex::new(String::new()));
+    let pool = CoroutinePoolImpl::default();
+    pool.set_max_size(1);
+    pool.set_group_weight("a", 2);
+    for (group, count) in [("a", 6), ("b", 3)] {
+        for _ in 0..count {
+            let executed = executed.clone();
+            _ = pool.submit_to_group(
+                group,
+                None,
+                move |_| {
+                    executed.lock().unwrap().push_str(group);
+                    None
+                },
+                None,
+            );
+        }
+    }
+    let stats = pool.group_stats();
+    assert_eq!(9, pool.size());
+    assert_eq!(6, stats.iter().find(|s| s.name == "a").unwrap().queued);
+    pool.try_schedule()?;
+    // the flooding group can't starve the other one
+    assert_eq!("aabaabaab", *executed.lock().unwrap());
+    // the idle group with the default weight is dropped
+    let stats = pool.group_stats();
+    assert_eq!(
+        vec![
+            (String::from(DEFAULT_GROUP), 1, 0, 0, 0),
+            (String::from("a"), 2, 0, 6, 6),
+        ],
+        stats
+            .into_iter()
+            .map(|s| (s.name, s.weight, s.queued, s.submitted, s.completed))
+            .collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cancel_group() -> std::io::Result<()> {
+    use std::sync::atomic::AtomicUsize;
+    let pool = CoroutinePoolImpl::default();
+    pool.set_max_size(1);
+    let co_id = Arc::new(AtomicUsize::new(0));
+    let id = co_id.clone();
+    _ = pool.submit_to_group(
+        "cancelled",
+        None,
+        move |_| {
+            if let Some(coroutine) = SchedulableCoroutine::current() {
+                id.store(coroutine.id(), Ordering::Release);
+            }
+            if let Some(suspender) = SchedulableSuspender::current() {
+                suspender.delay(Duration::from_secs(3));
+            }
+            unreachable!("the cancelled task should not go on");
+        },
+        None,
+    );
+    _ = pool.try_timed_schedule(Duration::from_millis(10))?;
+    unsafe { (*pool.workers.get()).try_cancel(co_id.load(Ordering::Acquire)) };
+    _ = pool.try_timed_schedule(Duration::from_millis(10))?;
+    // the cancelled task is counted as finished, so the idle group is dropped
+    assert!(pool.group_stats().iter().all(|s| s.name != "cancelled"));
+    Ok(())
+}
+
+#[test]
+fn test_metrics() -> std::io::Result<()> {
+    let pool = {
+        let _settings = crate::common::SETTINGS_LOCK.lock().unwrap();
+        crate::metrics::set_enable(true);
+        let pool = CoroutinePoolImpl::default();
+        crate::metrics::set_enable(false);
+        pool
+    };
+    pool.set_max_size(1);
+    for _ in 0..3 {
+        _ = pool.submit(None, |_| None, None);
+    }
+    let find = || {
+        crate::metrics::snapshot()
+            .pools
+            .into_iter()
+            .find(|p| p.name == pool.get_name())
+            .expect("pool metrics not found")
+    };
+    assert_eq!(3, find().queued);
+    pool.try_schedule()?;
+    let metrics = find();
+    assert_eq!((0, 1), (metrics.queued, metrics.grown));
+    assert_eq!(pool.get_running_size(), metrics.running);
+    assert_eq!(metrics.grown - metrics.shrunk, metrics.running as u64);
+    let text = crate::metrics::render_prometheus();
+    assert!(text.contains(&format!(
+        "open_coroutine_pool_grow_total{{pool=\"{}\"}} 1",
+        pool.get_name()
+    )));
+    assert!(text.contains(&format!(
+        "open_coroutine_coroutines_created_total{{scheduler=\"{}\"}} 1",
+        pool.get_name()
+    )));
+    Ok(())
+}
@@ -1,8 +1,12 @@
 use crate::constants::{Syscall, SyscallState};
+use crate::coroutine::error::CoroutineError;
 use crate::scheduler::{SchedulableCoroutine, SchedulerImpl};
 use std::fmt::Debug;
 
 /// A trait implemented for schedulers, mainly used for monitoring.
+///
+/// The id and the priority of the coroutine are available by
+/// `Coroutine::id` and `Coroutine::priority` in all callbacks.
 pub trait Listener: Debug {
     /// callback when a coroutine is created.
     /// This will be called by `Scheduler` when a coroutine is created.
@@ -21,12 +25,22 @@ pub trait Listener: Debug {
     fn on_syscall(&self, _: u64, _: &SchedulableCoroutine, _: Syscall, _: SyscallState) {}
 
     /// callback when a coroutine is completed.
-    /// This will be called by `Scheduler` when a coroutine is completed.
-    fn on_complete(&self, _: u64, _: &SchedulableCoroutine) {}
+    /// This will be called by `Scheduler` when a coroutine is completed,
+    /// the last argument is the peak stack usage in bytes if the stack measurement enabled.
+    fn on_complete(&self, _: u64, _: &SchedulableCoroutine, _: Option<usize>) {}
 
     /// callback when a coroutine is panic.
     /// This will be called by `Scheduler` when a coroutine is panic.
-    fn on_error(&self, _: u64, _: &SchedulableCoroutine, _: &str) {}
+    fn on_error(&self, _: u64, _: &SchedulableCoroutine, _: &CoroutineError) {}
+
+    /// callback when a coroutine is cancelled.
+    /// This will be called by `Scheduler` when a coroutine is cancelled.
+    fn on_cancel(&self, _: u64, _: &SchedulableCoroutine) {}
+
+    /// callback when a coroutine is picked to run after its deadline.
+    /// This will be called by `Scheduler` before resuming the coroutine,
+    /// use `Scheduler::try_cancel` to shed it.
+    fn on_deadline_missed(&self, _: u64, _: &SchedulableCoroutine) {}
 }
 
 #[allow(box_pointers)]
@@ -61,15 +75,37 @@ impl Listener for SchedulerImpl<'_> {
         }
     }
 
-    fn on_complete(&self, timeout_time: u64, coroutine: &SchedulableCoroutine) {
+    fn on_complete(
+        &self,
+        timeout_time: u64,
+        coroutine: &SchedulableCoroutine,
+        stack_usage: Option<usize>,
+    ) {
+        for listener in &self.listeners {
+            listener.on_complete(timeout_time, coroutine, stack_usage);
+        }
+    }
+
+    fn on_error(
+        &self,
+        timeout_time: u64,
+        coroutine: &SchedulableCoroutine,
+        error: &CoroutineError,
+    ) {
+        for listener in &self.listeners {
+            listener.on_error(timeout_time, coroutine, error);
+        }
+    }
+
+    fn on_cancel(&self, timeout_time: u64, coroutine: &SchedulableCoroutine) {
         for listener in &self.listeners {
-            listener.on_complete(timeout_time, coroutine);
+            listener.on_cancel(timeout_time, coroutine);
         }
     }
 
-    fn on_error(&self, timeout_time: u64, coroutine: &SchedulableCoroutine, message: &str) {
+    fn on_deadline_missed(&self, timeout_time: u64, coroutine: &SchedulableCoroutine) {
         for listener in &self.listeners {
-            listener.on_error(timeout_time, coroutine, message);
+            listener.on_deadline_missed(timeout_time, coroutine);
         }
     }
 }
@@ -77,7 +113,11 @@ impl Listener for SchedulerImpl<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::Named;
+    use crate::constants::Priority;
+    use crate::coroutine::Coroutine;
     use crate::scheduler::Scheduler;
+    use std::sync::{Arc, Mutex};
 
     #[derive(Debug, Default)]
     struct TestListener {}
@@ -88,11 +128,16 @@ mod tests {
         fn on_resume(&self, _: u64, coroutine: &SchedulableCoroutine) {
             println!("{:?}", coroutine);
         }
-        fn on_complete(&self, _: u64, coroutine: &SchedulableCoroutine) {
-            println!("{:?}", coroutine);
+        fn on_complete(
+            &self,
+            _: u64,
+            coroutine: &SchedulableCoroutine,
+            stack_usage: Option<usize>,
+        ) {
+            println!("{:?} {:?}", coroutine, stack_usage);
         }
-        fn on_error(&self, _: u64, coroutine: &SchedulableCoroutine, message: &str) {
-            println!("{:?} {message}", coroutine);
+        fn on_error(&self, _: u64, coroutine: &SchedulableCoroutine, error: &CoroutineError) {
+            println!("{:?} {error}", coroutine);
         }
     }
 
@@ -104,4 +149,33 @@ mod tests {
         scheduler.submit(|_, _| println!("2"), None)?;
         scheduler.try_schedule()
     }
+
+    #[derive(Debug, Default)]
+    struct PriorityListener(String, Arc<Mutex<Vec<Priority>>>);
+    impl Listener for PriorityListener {
+        fn on_resume(&self, _: u64, coroutine: &SchedulableCoroutine) {
+            // ignore the coroutines stolen from the other tests
+            if coroutine.get_name().starts_with(&self.0) {
+                self.1.lock().unwrap().push(coroutine.priority());
+            }
+        }
+    }
+
+    #[test]
+    fn test_priority() -> std::io::Result<()> {
+        let resumed = Arc::new(Mutex::new(Vec::new()));
+        let mut scheduler = SchedulerImpl::default();
+        scheduler.add_listener(PriorityListener(
+            String::from(scheduler.get_name()),
+            resumed.clone(),
+        ));
+        scheduler.submit_with_priority(|_, ()| println!("low"), None, Priority::Low)?;
+        scheduler.submit_with_priority(|_, ()| println!("high"), None, Priority::High)?;
+        scheduler.try_schedule()?;
+        assert_eq!(
+            vec![Priority::High, Priority::Low],
+            *resumed.lock().unwrap()
+        );
+        Ok(())
+    }
 }
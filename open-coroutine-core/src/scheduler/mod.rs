@@ -1,16 +1,22 @@
 use crate::common::{Current, Named};
-use crate::constants::{CoroutineState, SyscallState, DEFAULT_STACK_SIZE};
+use crate::constants::{
+    CoroutineState, Priority, SchedulePolicy, SyscallState, DEFAULT_STACK_SIZE,
+};
+use crate::coroutine::local::CoroutineLocal;
 use crate::coroutine::suspender::{Suspender, SuspenderImpl};
 use crate::coroutine::{Coroutine, CoroutineImpl, SimpleCoroutine, StateMachine};
+use crate::future::WakeQueue;
 use crate::scheduler::listener::Listener;
-use dashmap::DashMap;
-use open_coroutine_queue::LocalQueue;
+use crate::scheduler::ready::ReadyQueue;
+use crossbeam_deque::Steal;
+use dashmap::{DashMap, DashSet};
 use open_coroutine_timer::TimerList;
 use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::io::{Error, ErrorKind};
 use std::panic::UnwindSafe;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Listener abstraction and impl.
@@ -18,12 +24,33 @@ pub mod listener;
 
 mod current;
 
+mod ready;
+
 /// A type for Scheduler.
 pub type SchedulableCoroutine<'s> = CoroutineImpl<'s, (), (), ()>;
 
 /// A type for Scheduler.
 pub type SchedulableSuspender<'s> = SuspenderImpl<'s, (), ()>;
 
+/// A trait implemented for transfer the execution to another coroutine.
+pub trait TransferSuspender<'s>: Suspender<'s, Yield = ()> {
+    /// Suspend the current coroutine and run the ready coroutine `co_id` of the
+    /// current scheduler directly, the current coroutine is pushed back to the
+    /// ready queue. If the target is not ready, it behaves like `suspend`.
+    fn yield_to(&self, co_id: usize) -> Self::Resume;
+}
+
+impl<'s, TransferSuspenderImpl: ?Sized + Suspender<'s, Yield = ()>> TransferSuspender<'s>
+    for TransferSuspenderImpl
+{
+    fn yield_to(&self, co_id: usize) -> Self::Resume {
+        if let Some(scheduler) = SchedulerImpl::current() {
+            scheduler.try_run_next(co_id);
+        }
+        self.suspend_with(())
+    }
+}
+
 /// A trait implemented for schedulers.
 pub trait Scheduler<'s>: Debug + Default + Named + Current<'s> + Listener {
     /// Extension points within the open-coroutine framework.
@@ -33,6 +60,13 @@ pub trait Scheduler<'s>: Debug + Default + Named + Current<'s> + Listener {
     /// If it has not been set, it will be `crate::constants::DEFAULT_STACK_SIZE`.
     fn set_stack_size(&self, stack_size: usize);
 
+    /// Set the policy to order the ready coroutines in this scheduler.
+    /// If it has not been set, it will be `SchedulePolicy::Priority`.
+    fn set_schedule_policy(&self, policy: SchedulePolicy);
+
+    /// Get the policy to order the ready coroutines in this scheduler.
+    fn get_schedule_policy(&self) -> SchedulePolicy;
+
     /// Submit a closure to new coroutine, then the coroutine will be push into ready queue.
     ///
     /// Allow multiple threads to concurrently submit coroutine to the scheduler,
@@ -44,6 +78,33 @@ pub trait Scheduler<'s>: Debug + Default + Named + Current<'s> + Listener {
         &self,
         f: impl FnOnce(&dyn Suspender<Resume = (), Yield = ()>, ()) + UnwindSafe + 's,
         stack_size: Option<usize>,
+    ) -> std::io::Result<()> {
+        self.submit_with_priority(f, stack_size, Priority::default())
+    }
+
+    /// Submit a closure to new coroutine with the given `priority`, the higher priority
+    /// ready coroutines always run first, see `crate::constants::PRIORITY_AGING`.
+    ///
+    /// # Errors
+    /// if create coroutine fails.
+    fn submit_with_priority(
+        &self,
+        f: impl FnOnce(&dyn Suspender<Resume = (), Yield = ()>, ()) + UnwindSafe + 's,
+        stack_size: Option<usize>,
+        priority: Priority,
+    ) -> std::io::Result<()>;
+
+    /// Submit a closure to new coroutine with the given `deadline` timestamp in ns,
+    /// with `SchedulePolicy::Deadline` the ready coroutine with the earliest deadline
+    /// runs first, and `Listener::on_deadline_missed` is called if it's picked too late.
+    ///
+    /// # Errors
+    /// if create coroutine fails.
+    fn submit_with_deadline(
+        &self,
+        f: impl FnOnce(&dyn Suspender<Resume = (), Yield = ()>, ()) + UnwindSafe + 's,
+        stack_size: Option<usize>,
+        deadline: u64,
     ) -> std::io::Result<()>;
 
     /// Resume a coroutine from the system call table to the ready queue,
@@ -53,7 +114,20 @@ pub trait Scheduler<'s>: Debug + Default + Named + Current<'s> + Listener {
     ///
     /// # Errors
     /// if change to ready fails.
-    fn try_resume(&self, co_name: &'s str) -> std::io::Result<()>;
+    fn try_resume(&self, co_id: usize) -> std::io::Result<()>;
+
+    /// Cancel a coroutine owned by this scheduler, the coroutine will be unwound
+    /// and dropped when the scheduler schedules next time, it's generally only
+    /// required for framework level crates.
+    ///
+    /// If the coroutine is not owned by this scheduler or has finished, nothing happens.
+    fn try_cancel(&self, co_id: usize);
+
+    /// Run the ready coroutine next time this scheduler picks a coroutine, ahead of
+    /// the ready queue and regardless of its priority, see `TransferSuspender::yield_to`.
+    ///
+    /// If we can't find the coroutine in the ready queue, nothing happens.
+    fn try_run_next(&self, co_id: usize);
 
     /// Schedule the coroutines.
     ///
@@ -113,30 +187,62 @@ pub trait Scheduler<'s>: Debug + Default + Named + Current<'s> + Listener {
 pub struct SchedulerImpl<'s> {
     name: String,
     stack_size: AtomicUsize,
-    ready: LocalQueue<'s, SchedulableCoroutine<'s>>,
+    ready: ReadyQueue<'s>,
     suspend: TimerList<SchedulableCoroutine<'s>>,
-    syscall: DashMap<&'s str, SchedulableCoroutine<'s>>,
-    syscall_suspend: TimerList<&'s str>,
+    syscall: DashMap<usize, SchedulableCoroutine<'s>>,
+    syscall_suspend: TimerList<usize>,
+    cancel: DashSet<usize>,
+    owned: DashSet<usize>,
+    wake: Arc<WakeQueue>,
+    next: AtomicUsize,
     listeners: VecDeque<Box<dyn Listener + 's>>,
 }
 
-impl SchedulerImpl<'_> {
+impl<'s> SchedulerImpl<'s> {
     #[allow(missing_docs, box_pointers)]
     #[must_use]
     pub fn new(name: String, stack_size: usize) -> Self {
         let mut scheduler = SchedulerImpl {
             name,
             stack_size: AtomicUsize::new(stack_size),
-            ready: LocalQueue::default(),
+            ready: ReadyQueue::default(),
             suspend: TimerList::default(),
             syscall: DashMap::default(),
             syscall_suspend: TimerList::default(),
+            cancel: DashSet::default(),
+            owned: DashSet::default(),
+            wake: Arc::default(),
+            next: AtomicUsize::new(0),
             listeners: VecDeque::default(),
         };
         scheduler.init();
         scheduler
     }
 
+    fn submit_coroutine(
+        &self,
+        f: impl FnOnce(&dyn Suspender<Resume = (), Yield = ()>, ()) + UnwindSafe + 's,
+        stack_size: Option<usize>,
+        priority: Priority,
+        deadline: Option<u64>,
+    ) -> std::io::Result<()> {
+        let coroutine = SchedulableCoroutine::new(
+            format!("{}|{}", self.name, uuid::Uuid::new_v4()),
+            f,
+            stack_size.unwrap_or(self.stack_size.load(Ordering::Acquire)),
+        )?;
+        _ = coroutine.set_priority(priority);
+        _ = coroutine.set_deadline(deadline);
+        if let Some(local) = CoroutineLocal::current() {
+            coroutine.local().inherit(local.inherited());
+        }
+        coroutine.ready()?;
+        _ = self.owned.insert(coroutine.id());
+        self.on_create(&coroutine);
+        self.ready.push_back(coroutine);
+        Ok(())
+    }
+
     fn check_ready(&mut self) -> std::io::Result<()> {
         // Check if the elements in the suspend queue are ready
         for _ in 0..self.suspend.entry_len() {
@@ -151,6 +257,7 @@ impl SchedulerImpl<'_> {
                                 Self::clean_current();
                                 return Err(e);
                             }
+                            crate::dump::update(&coroutine);
                             self.ready.push_back(coroutine);
                         }
                     }
@@ -165,8 +272,8 @@ impl SchedulerImpl<'_> {
                 }
                 if let Some((_, mut entry)) = self.syscall_suspend.pop_front() {
                     while !entry.is_empty() {
-                        if let Some(co_name) = entry.pop_front() {
-                            if let Some(r) = self.syscall.remove(&co_name) {
+                        if let Some(co_id) = entry.pop_front() {
+                            if let Some(r) = self.syscall.remove(&co_id) {
                                 let coroutine = r.1;
                                 match coroutine.state() {
                                     CoroutineState::SystemCall(val, syscall, state) => {
@@ -180,6 +287,7 @@ impl SchedulerImpl<'_> {
                                                 return Err(e);
                                             }
                                         }
+                                        crate::dump::update(&coroutine);
                                         self.ready.push_back(coroutine);
                                     }
                                     _ => unreachable!("check_ready should never execute to here"),
@@ -192,6 +300,94 @@ impl SchedulerImpl<'_> {
         }
         Ok(())
     }
+
+    /// The coroutines parked in `block_on` are resumed when pushed to this queue.
+    pub(crate) fn wake_queue(&self) -> &Arc<WakeQueue> {
+        &self.wake
+    }
+
+    /// Call `notify` when a coroutine parked in `block_on` is woken, so the scheduling
+    /// thread blocked in other places can be interrupted, only the first one takes effect.
+    pub fn set_wake_notify(&self, notify: impl Fn() + Send + Sync + 'static) {
+        self.wake.set_notify(notify);
+    }
+
+    fn check_wake(&self) -> std::io::Result<()> {
+        // 在调度线程中处理，保证被唤醒的协程已经放入系统调用表
+        loop {
+            match self.wake.steal() {
+                Steal::Success(waker) => {
+                    if waker.parked() {
+                        if let Err(e) = self.try_resume(waker.co_id()) {
+                            Self::clean_current();
+                            return Err(e);
+                        }
+                    }
+                }
+                Steal::Retry => {}
+                Steal::Empty => return Ok(()),
+            }
+        }
+    }
+
+    fn pop_next(&self) -> Option<SchedulableCoroutine<'s>> {
+        match self.next.swap(0, Ordering::AcqRel) {
+            // the coroutine id starts from 1
+            0 => None,
+            co_id => self.ready.take(co_id),
+        }
+    }
+
+    fn check_cancel(&mut self, timeout_time: u64) -> std::io::Result<()> {
+        if self.cancel.is_empty() {
+            return Ok(());
+        }
+        // Cancel the coroutines in the syscall table
+        for co_id in self.cancel.iter().map(|r| *r).collect::<Vec<_>>() {
+            if let Some((_, coroutine)) = self.syscall.remove(&co_id) {
+                _ = self.cancel.remove(&co_id);
+                self.do_cancel(timeout_time, coroutine)?;
+            }
+        }
+        // Cancel the coroutines in the suspend queue
+        let mut cancelled = VecDeque::new();
+        for (_, entry) in self.suspend.iter_mut() {
+            for _ in 0..entry.len() {
+                if let Some(coroutine) = entry.pop_front() {
+                    if self.cancel.remove(&coroutine.id()).is_some() {
+                        cancelled.push_back(coroutine);
+                    } else {
+                        entry.push_back(coroutine);
+                    }
+                }
+            }
+        }
+        for coroutine in cancelled {
+            self.do_cancel(timeout_time, coroutine)?;
+        }
+        // The coroutines in the ready queue are cancelled when they are popped
+        Ok(())
+    }
+
+    fn finish(&self, co_id: usize) {
+        _ = self.owned.remove(&co_id);
+        // the coroutine may be cancelled after it's picked to run
+        _ = self.cancel.remove(&co_id);
+    }
+
+    fn do_cancel(
+        &self,
+        timeout_time: u64,
+        mut coroutine: SchedulableCoroutine<'s>,
+    ) -> std::io::Result<()> {
+        self.finish(coroutine.id());
+        if let Err(e) = coroutine.cancel() {
+            Self::clean_current();
+            return Err(e);
+        }
+        self.on_cancel(timeout_time, &coroutine);
+        Ok(())
+    }
 }
 
 impl Default for SchedulerImpl<'_> {
@@ -241,6 +437,11 @@ impl Named for SchedulerImpl<'_> {
 
 impl<'s> Scheduler<'s> for SchedulerImpl<'s> {
     fn init(&mut self) {
+        if crate::dump::get_record() {
+            self.add_listener(crate::dump::Recorder::new(self.name.clone()));
+        }
+        #[cfg(feature = "tracing")]
+        self.add_listener(crate::log::TracingListener::new(self.name.clone()));
         #[cfg(all(unix, feature = "preemptive-schedule"))]
         self.add_listener(crate::monitor::creator::MonitorTaskCreator::default());
     }
@@ -249,6 +450,14 @@ impl<'s> Scheduler<'s> for SchedulerImpl<'s> {
         self.stack_size.store(stack_size, Ordering::Release);
     }
 
+    fn set_schedule_policy(&self, policy: SchedulePolicy) {
+        self.ready.set_policy(policy);
+    }
+
+    fn get_schedule_policy(&self) -> SchedulePolicy {
+        self.ready.policy()
+    }
+
     /// # Examples
     /// ```
     /// use std::time::Duration;
@@ -283,24 +492,26 @@ impl<'s> Scheduler<'s> for SchedulerImpl<'s> {
     /// std::thread::sleep(Duration::from_millis(10));
     /// scheduler.try_schedule().expect("schedule failed");
     /// ```
-    fn submit(
+    fn submit_with_priority(
         &self,
         f: impl FnOnce(&dyn Suspender<Resume = (), Yield = ()>, ()) + UnwindSafe + 's,
         stack_size: Option<usize>,
+        priority: Priority,
     ) -> std::io::Result<()> {
-        let coroutine = SchedulableCoroutine::new(
-            format!("{}|{}", self.name, uuid::Uuid::new_v4()),
-            f,
-            stack_size.unwrap_or(self.stack_size.load(Ordering::Acquire)),
-        )?;
-        coroutine.ready()?;
-        self.on_create(&coroutine);
-        self.ready.push_back(coroutine);
-        Ok(())
+        self.submit_coroutine(f, stack_size, priority, None)
+    }
+
+    fn submit_with_deadline(
+        &self,
+        f: impl FnOnce(&dyn Suspender<Resume = (), Yield = ()>, ()) + UnwindSafe + 's,
+        stack_size: Option<usize>,
+        deadline: u64,
+    ) -> std::io::Result<()> {
+        self.submit_coroutine(f, stack_size, Priority::default(), Some(deadline))
     }
 
-    fn try_resume(&self, co_name: &'s str) -> std::io::Result<()> {
-        if let Some(r) = self.syscall.remove(&co_name) {
+    fn try_resume(&self, co_id: usize) -> std::io::Result<()> {
+        if let Some(r) = self.syscall.remove(&co_id) {
             let coroutine = r.1;
             match coroutine.state() {
                 CoroutineState::SystemCall(val, syscall, _) => {
@@ -308,11 +519,22 @@ impl<'s> Scheduler<'s> for SchedulerImpl<'s> {
                 }
                 _ => unreachable!("try_resume should never execute to here"),
             }
+            crate::dump::update(&coroutine);
             self.ready.push_back(coroutine);
         }
         Ok(())
     }
 
+    fn try_cancel(&self, co_id: usize) {
+        if self.owned.contains(&co_id) {
+            _ = self.cancel.insert(co_id);
+        }
+    }
+
+    fn try_run_next(&self, co_id: usize) {
+        self.next.store(co_id, Ordering::Release);
+    }
+
     fn try_timeout_schedule(&mut self, timeout_time: u64) -> std::io::Result<u64> {
         Self::init_current(self);
         loop {
@@ -321,14 +543,35 @@ impl<'s> Scheduler<'s> for SchedulerImpl<'s> {
                 Self::clean_current();
                 return Ok(0);
             }
+            self.check_cancel(timeout_time)?;
+            self.check_wake()?;
             self.check_ready()?;
             // schedule coroutines
-            match self.ready.pop_front() {
+            let coroutine = self.pop_next().or_else(|| self.ready.pop_front());
+            match coroutine {
                 None => {
+                    if self.size() == 0 {
+                        // nothing to cancel
+                        self.cancel.clear();
+                    }
                     Self::clean_current();
                     return Ok(left_time);
                 }
+                Some(coroutine) if self.cancel.remove(&coroutine.id()).is_some() => {
+                    self.do_cancel(timeout_time, coroutine)?;
+                }
                 Some(mut coroutine) => {
+                    if coroutine
+                        .deadline()
+                        .is_some_and(|deadline| deadline < open_coroutine_timer::now())
+                    {
+                        self.on_deadline_missed(timeout_time, &coroutine);
+                        //监听器可能取消错过截止时间的协程
+                        if self.cancel.remove(&coroutine.id()).is_some() {
+                            self.do_cancel(timeout_time, coroutine)?;
+                            continue;
+                        }
+                    }
                     self.on_resume(timeout_time, &coroutine);
                     match coroutine.resume() {
                         Ok(state) => {
@@ -343,18 +586,20 @@ impl<'s> Scheduler<'s> for SchedulerImpl<'s> {
                                 }
                                 CoroutineState::SystemCall((), syscall, state) => {
                                     self.on_syscall(timeout_time, &coroutine, syscall, state);
-                                    #[allow(box_pointers)]
-                                    let co_name = Box::leak(Box::from(coroutine.get_name()));
+                                    let co_id = coroutine.id();
                                     if let SyscallState::Suspend(timestamp) = state {
-                                        self.syscall_suspend.insert(timestamp, co_name);
+                                        self.syscall_suspend.insert(timestamp, co_id);
                                     }
-                                    _ = self.syscall.insert(co_name, coroutine);
+                                    _ = self.syscall.insert(co_id, coroutine);
                                 }
                                 CoroutineState::Complete(()) => {
-                                    self.on_complete(timeout_time, &coroutine);
+                                    self.finish(coroutine.id());
+                                    let stack_usage = coroutine.stack_usage();
+                                    self.on_complete(timeout_time, &coroutine, stack_usage);
                                 }
-                                CoroutineState::Error(message) => {
-                                    self.on_error(timeout_time, &coroutine, message);
+                                CoroutineState::Error(error) => {
+                                    self.finish(coroutine.id());
+                                    self.on_error(timeout_time, &coroutine, &error);
                                 }
                                 _ => {
                                     Self::clean_current();
@@ -444,7 +689,532 @@ mod tests {
         scheduler.try_schedule()
     }
 
-    #[cfg(feature = "korosensei")]
+    #[test]
+    fn test_inherit() -> std::io::Result<()> {
+        use std::cell::Cell;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        crate::inheritable_coroutine_local! {
+            static REQUEST_ID: Cell<usize> = Cell::new(0);
+        }
+        let inherited = Arc::new(AtomicUsize::new(0));
+        let result = inherited.clone();
+        let mut scheduler = SchedulerImpl::default();
+        scheduler.submit(
+            move |_, ()| {
+                REQUEST_ID.with(|id| id.set(1));
+                let scheduler = SchedulerImpl::current().unwrap();
+                scheduler
+                    .submit(
+                        move |_, ()| result.store(REQUEST_ID.with(Cell::get), Ordering::Release),
+                        None,
+                    )
+                    .unwrap();
+            },
+            None,
+        )?;
+        scheduler.try_schedule()?;
+        assert_eq!(1, inherited.load(Ordering::Acquire));
+        Ok(())
+    }
+
+    #[test]
+    fn test_priority_aging() -> std::io::Result<()> {
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Arc;
+
+        let done = Arc::new(AtomicBool::new(false));
+        let passed = Arc::new(AtomicUsize::new(0));
+        let (flag, count) = (done.clone(), passed.clone());
+        let mut scheduler = SchedulerImpl::default();
+        scheduler.submit_with_priority(
+            move |suspender, ()| {
+                while !flag.load(Ordering::Acquire) {
+                    _ = count.fetch_add(1, Ordering::Release);
+                    suspender.suspend();
+                }
+            },
+            None,
+            Priority::High,
+        )?;
+        let flag = done.clone();
+        scheduler.submit_with_priority(
+            move |_, ()| flag.store(true, Ordering::Release),
+            None,
+            Priority::Low,
+        )?;
+        scheduler.try_schedule()?;
+        assert!(done.load(Ordering::Acquire));
+        // the low priority coroutine is aged and picked
+        assert!(passed.load(Ordering::Acquire) <= crate::constants::PRIORITY_AGING + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_on() -> std::io::Result<()> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Mutex};
+        use std::task::{Poll, Waker};
+
+        let woken = Arc::new(Mutex::new((false, None::<Waker>)));
+        let done = Arc::new(AtomicBool::new(false));
+        let others = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        let (w, d) = (woken.clone(), done.clone());
+        scheduler.submit(
+            move |_, ()| {
+                crate::future::block_on(std::future::poll_fn(|cx| {
+                    let mut woken = w.lock().unwrap();
+                    if woken.0 {
+                        return Poll::Ready(());
+                    }
+                    woken.1 = Some(cx.waker().clone());
+                    Poll::Pending
+                }));
+                d.store(true, Ordering::Release);
+            },
+            None,
+        )?;
+        let o = others.clone();
+        scheduler.submit(move |_, ()| _ = o.fetch_add(1, Ordering::Release), None)?;
+        _ = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            let mut woken = woken.lock().unwrap();
+            woken.0 = true;
+            if let Some(waker) = woken.1.take() {
+                waker.wake();
+            }
+        });
+        let timeout_time = open_coroutine_timer::get_timeout_time(Duration::from_secs(3));
+        while !done.load(Ordering::Acquire) {
+            assert!(
+                open_coroutine_timer::now() < timeout_time,
+                "block_on timeout"
+            );
+            _ = scheduler.try_timed_schedule(Duration::from_millis(1))?;
+        }
+        // the thread was not blocked by the parked coroutine
+        assert_eq!(1, others.load(Ordering::Acquire));
+        assert!(scheduler.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_on_timeout() -> std::io::Result<()> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let done = Arc::new(AtomicBool::new(false));
+        let mut scheduler = SchedulerImpl::default();
+        let d = done.clone();
+        scheduler.submit_with_priority(
+            move |_, ()| {
+                let timeout = Duration::from_millis(10);
+                let pending = std::future::pending::<()>();
+                assert_eq!(None, crate::future::block_on_timeout(pending, timeout));
+                d.store(true, Ordering::Release);
+            },
+            None,
+            Priority::High,
+        )?;
+        let timeout_time = open_coroutine_timer::get_timeout_time(Duration::from_secs(3));
+        while !done.load(Ordering::Acquire) {
+            assert!(
+                open_coroutine_timer::now() < timeout_time,
+                "block_on_timeout timeout"
+            );
+            _ = scheduler.try_timed_schedule(Duration::from_millis(1))?;
+        }
+        assert!(scheduler.is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "tracing")]
+    #[allow(clippy::too_many_lines)]
+    #[test]
+    fn test_tracing_span() -> std::io::Result<()> {
+        use std::sync::Mutex;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        #[derive(Debug, Default)]
+        struct Collected {
+            spans: Mutex<Vec<String>>,
+            entered: Mutex<Vec<u64>>,
+            events: Mutex<Vec<(String, Option<String>)>>,
+            transitions: Mutex<Vec<String>>,
+        }
+
+        #[derive(Debug, Clone, Default)]
+        struct Collector(Arc<Collected>, bool);
+
+        #[derive(Debug, Default)]
+        struct Visitor(String, Vec<String>);
+
+        impl Visit for Visitor {
+            fn record_str(&mut self, field: &Field, value: &str) {
+                match field.name() {
+                    "name" => self.0 = String::from(value),
+                    name => self.1.push(format!("{name}={value}")),
+                }
+            }
+
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                match field.name() {
+                    "message" => self.0 = format!("{value:?}"),
+                    name => self.1.push(format!("{name}={value:?}")),
+                }
+            }
+        }
+
+        impl tracing::Subscriber for Collector {
+            fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+                // the second field disables the spans
+                !(self.1 && metadata.is_span())
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut visitor = Visitor::default();
+                span.record(&mut visitor);
+                let mut spans = self.0.spans.lock().unwrap();
+                spans.push(visitor.0);
+                Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut visitor = Visitor::default();
+                event.record(&mut visitor);
+                if !visitor.1.is_empty() {
+                    self.0.transitions.lock().unwrap().push(visitor.1.join(","));
+                    return;
+                }
+                let span = self.0.entered.lock().unwrap().last().map(|id| {
+                    self.0.spans.lock().unwrap()[usize::try_from(*id).unwrap() - 1].clone()
+                });
+                self.0.events.lock().unwrap().push((visitor.0, span));
+            }
+
+            fn enter(&self, span: &Id) {
+                self.0.entered.lock().unwrap().push(span.into_u64());
+            }
+
+            fn exit(&self, _: &Id) {
+                _ = self.0.entered.lock().unwrap().pop();
+            }
+        }
+
+        let collector = Collector::default();
+        tracing::subscriber::with_default(collector.clone(), || {
+            let mut scheduler = SchedulerImpl::default();
+            scheduler.submit_with_priority(
+                |suspender, ()| {
+                    tracing::info!("before");
+                    suspender.suspend();
+                    tracing::info!("after");
+                },
+                None,
+                Priority::High,
+            )?;
+            scheduler.try_schedule()?;
+            tracing::info!("outside");
+            Ok::<(), Error>(())
+        })?;
+        let name = collector.0.spans.lock().unwrap()[0].clone();
+        assert!(name.starts_with("open-coroutine-scheduler-"));
+        assert_eq!(
+            vec![
+                (String::from("before"), Some(name.clone())),
+                (String::from("after"), Some(name)),
+                (String::from("outside"), None),
+            ],
+            *collector.0.events.lock().unwrap()
+        );
+        let transitions = collector.0.transitions.lock().unwrap().clone();
+        assert_eq!(4, transitions.len());
+        assert!(transitions[0].ends_with(",from=Ready,to=Running"));
+        assert!(transitions[1].ends_with(",from=Running,to=Suspend((), 0)"));
+        assert!(transitions[3].ends_with(",from=Running,to=Complete(())"));
+
+        // the spans aren't built if the subscriber disables them
+        let collector = Collector(Arc::default(), true);
+        tracing::subscriber::with_default(collector.clone(), || {
+            let mut scheduler = SchedulerImpl::default();
+            scheduler.submit_with_priority(
+                |_, ()| tracing::info!("inside"),
+                None,
+                Priority::High,
+            )?;
+            scheduler.try_schedule()
+        })?;
+        assert!(collector.0.spans.lock().unwrap().is_empty());
+        assert_eq!(
+            vec![(String::from("inside"), None)],
+            *collector.0.events.lock().unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_yield_to() -> std::io::Result<()> {
+        use std::sync::{Arc, Mutex};
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let target = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        let (o, t) = (order.clone(), target.clone());
+        scheduler.submit_with_priority(
+            move |suspender, ()| {
+                suspender.suspend();
+                o.lock().unwrap().push("a1");
+                suspender.yield_to(t.load(Ordering::Acquire));
+                o.lock().unwrap().push("a2");
+            },
+            None,
+            Priority::High,
+        )?;
+        let o = order.clone();
+        scheduler.submit_with_priority(
+            move |suspender, ()| {
+                suspender.suspend();
+                o.lock().unwrap().push("b");
+            },
+            None,
+            Priority::High,
+        )?;
+        let (o, t) = (order.clone(), target.clone());
+        scheduler.submit_with_priority(
+            move |suspender, ()| {
+                if let Some(coroutine) = SchedulableCoroutine::current() {
+                    t.store(coroutine.id(), Ordering::Release);
+                }
+                suspender.suspend();
+                o.lock().unwrap().push("c");
+            },
+            None,
+            Priority::High,
+        )?;
+        scheduler.try_schedule()?;
+        // c runs directly after a1, ahead of b
+        assert_eq!(vec!["a1", "c", "b", "a2"], *order.lock().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_yield_to_keep_order() -> std::io::Result<()> {
+        use std::sync::{Arc, Mutex};
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let targets = Arc::new(Mutex::new(Vec::new()));
+        let mut scheduler = SchedulerImpl::default();
+        for name in ["a", "b", "c", "d"] {
+            let (o, t) = (order.clone(), targets.clone());
+            scheduler.submit_with_priority(
+                move |suspender, ()| {
+                    if let Some(coroutine) = SchedulableCoroutine::current() {
+                        t.lock().unwrap().push(coroutine.id());
+                    }
+                    suspender.suspend();
+                    o.lock().unwrap().push(name);
+                    let target = match name {
+                        // b and c are skipped
+                        "a" => t.lock().unwrap()[3],
+                        // c is taken from the skipped
+                        "d" => t.lock().unwrap()[2],
+                        _ => return,
+                    };
+                    suspender.yield_to(target);
+                    o.lock().unwrap().push(name);
+                },
+                None,
+                Priority::High,
+            )?;
+        }
+        scheduler.try_schedule()?;
+        assert_eq!(vec!["a", "d", "c", "b", "a", "d"], *order.lock().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deadline() -> std::io::Result<()> {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Debug)]
+        struct Shedder(Arc<Mutex<Vec<usize>>>);
+
+        impl Listener for Shedder {
+            fn on_deadline_missed(&self, _: u64, coroutine: &SchedulableCoroutine) {
+                self.0.lock().unwrap().push(coroutine.id());
+                if let Some(scheduler) = SchedulerImpl::current() {
+                    scheduler.try_cancel(coroutine.id());
+                }
+            }
+        }
+
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let missed = Arc::new(Mutex::new(Vec::new()));
+        let mut scheduler = SchedulerImpl::default();
+        scheduler.set_schedule_policy(SchedulePolicy::Deadline);
+        assert_eq!(SchedulePolicy::Deadline, scheduler.get_schedule_policy());
+        scheduler.add_listener(Shedder(missed.clone()));
+        let now = open_coroutine_timer::now();
+        for offset in [3, 1, 2] {
+            let executed = executed.clone();
+            scheduler.submit_with_deadline(
+                move |_, ()| executed.lock().unwrap().push(offset),
+                None,
+                now + offset * 1_000_000_000,
+            )?;
+        }
+        let e = executed.clone();
+        scheduler.submit_with_priority(
+            move |_, ()| e.lock().unwrap().push(0),
+            None,
+            Priority::High,
+        )?;
+        let e = executed.clone();
+        scheduler.submit_with_deadline(
+            move |_, ()| e.lock().unwrap().push(u64::MAX),
+            None,
+            now.saturating_sub(1),
+        )?;
+        scheduler.try_schedule()?;
+        // the missed one is shed, the one without deadline runs at last
+        assert_eq!(vec![1, 2, 3, 0], *executed.lock().unwrap());
+        assert_eq!(1, missed.lock().unwrap().len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deadline_cancelled() -> std::io::Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        #[derive(Debug)]
+        struct Recorder {
+            id: Arc<AtomicUsize>,
+            missed: Arc<AtomicUsize>,
+            cancelled: Arc<AtomicUsize>,
+        }
+
+        impl Listener for Recorder {
+            fn on_create(&self, coroutine: &SchedulableCoroutine) {
+                self.id.store(coroutine.id(), Ordering::Release);
+            }
+
+            fn on_deadline_missed(&self, _: u64, _: &SchedulableCoroutine) {
+                _ = self.missed.fetch_add(1, Ordering::Release);
+            }
+
+            fn on_cancel(&self, _: u64, _: &SchedulableCoroutine) {
+                _ = self.cancelled.fetch_add(1, Ordering::Release);
+            }
+        }
+
+        let id = Arc::new(AtomicUsize::new(0));
+        let missed = Arc::new(AtomicUsize::new(0));
+        let cancelled = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        scheduler.set_schedule_policy(SchedulePolicy::Deadline);
+        scheduler.add_listener(Recorder {
+            id: id.clone(),
+            missed: missed.clone(),
+            cancelled: cancelled.clone(),
+        });
+        scheduler.submit_with_deadline(
+            |_, ()| panic!("the cancelled coroutine should not run"),
+            None,
+            open_coroutine_timer::now().saturating_sub(1),
+        )?;
+        scheduler.try_cancel(id.load(Ordering::Acquire));
+        scheduler.try_schedule()?;
+        // the cancelled coroutine is not reported as missing its deadline
+        assert_eq!(0, missed.load(Ordering::Acquire));
+        assert_eq!(1, cancelled.load(Ordering::Acquire));
+        Ok(())
+    }
+
+    #[test]
+    fn test_deadline_aging() -> std::io::Result<()> {
+        use crate::constants::PRIORITY_AGING;
+        use std::sync::{Arc, Mutex};
+
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let mut scheduler = SchedulerImpl::default();
+        scheduler.set_schedule_policy(SchedulePolicy::Deadline);
+        let now = open_coroutine_timer::now();
+        for i in 0..PRIORITY_AGING * 2 {
+            let executed = executed.clone();
+            scheduler.submit_with_deadline(
+                move |_, ()| executed.lock().unwrap().push(Some(i)),
+                None,
+                now + 1_000_000_000 + i as u64,
+            )?;
+        }
+        let e = executed.clone();
+        scheduler.submit_with_priority(
+            move |_, ()| e.lock().unwrap().push(None),
+            None,
+            Priority::High,
+        )?;
+        scheduler.try_schedule()?;
+        // the one without deadline is aged instead of waiting for all the deadlines
+        let executed = executed.lock().unwrap();
+        assert_eq!(PRIORITY_AGING * 2 + 1, executed.len());
+        assert_eq!(
+            Some(PRIORITY_AGING - 1),
+            executed.iter().position(Option::is_none)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel() -> std::io::Result<()> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        struct Guard(Arc<AtomicBool>);
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::Release);
+            }
+        }
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = dropped.clone();
+        let id = Arc::new(AtomicUsize::new(0));
+        let co_id = id.clone();
+        let mut scheduler = SchedulerImpl::default();
+        scheduler.submit(
+            move |suspender, ()| {
+                let _guard = Guard(flag);
+                if let Some(coroutine) = SchedulableCoroutine::current() {
+                    co_id.store(coroutine.id(), Ordering::Release);
+                }
+                suspender.delay(Duration::from_secs(1));
+                unreachable!("should never execute to here");
+            },
+            None,
+        )?;
+        scheduler.try_schedule()?;
+        assert_eq!(1, scheduler.size());
+        assert!(!dropped.load(Ordering::Acquire));
+        // unknown coroutines are ignored
+        scheduler.try_cancel(usize::MAX);
+        assert!(scheduler.cancel.is_empty());
+        scheduler.try_cancel(id.load(Ordering::Acquire));
+        scheduler.try_schedule()?;
+        assert!(scheduler.is_empty());
+        assert!(dropped.load(Ordering::Acquire));
+        Ok(())
+    }
+
+    #[cfg(all(feature = "korosensei", not(feature = "boost")))]
     #[test]
     fn test_trap() -> std::io::Result<()> {
         let mut scheduler = SchedulerImpl::default();
@@ -460,7 +1230,7 @@ mod tests {
         scheduler.try_schedule()
     }
 
-    #[cfg(all(feature = "korosensei", not(debug_assertions)))]
+    #[cfg(all(feature = "korosensei", not(feature = "boost"), not(debug_assertions)))]
     #[test]
     fn test_invalid_memory_reference() -> std::io::Result<()> {
         use std::ffi::c_void;
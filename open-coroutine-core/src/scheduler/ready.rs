@@ -0,0 +1,173 @@
+use crate::common::Aging;
+use crate::constants::{Priority, SchedulePolicy};
+use crate::coroutine::Coroutine;
+use crate::scheduler::SchedulableCoroutine;
+use crossbeam_deque::{Injector, Steal};
+use open_coroutine_queue::LocalQueue;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The ready queue of the scheduler.
+///
+/// The normal priority coroutines are kept in the work stealing queue, so they
+/// can be stolen by other schedulers, the others stay in this scheduler.
+/// With `SchedulePolicy::Deadline`, the coroutines with deadline are ordered
+/// by the earliest deadline and run before the others, but the others run
+/// once every `PRIORITY_AGING` picks, so they don't starve.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub(crate) struct ReadyQueue<'r> {
+    high: Injector<SchedulableCoroutine<'r>>,
+    normal: LocalQueue<'r, SchedulableCoroutine<'r>>,
+    low: Injector<SchedulableCoroutine<'r>>,
+    aging: Aging,
+    //按(截止时间, 协程id)排序
+    deadline: Mutex<BTreeMap<(u64, usize), SchedulableCoroutine<'r>>>,
+    //截止时间协程数，为0时无需加锁
+    deadline_len: AtomicUsize,
+    deadline_first: AtomicBool,
+    //被跳过的协程，按原顺序先于其他协程调度
+    skipped: Injector<SchedulableCoroutine<'r>>,
+}
+
+impl<'r> ReadyQueue<'r> {
+    fn pop(injector: &Injector<SchedulableCoroutine<'r>>) -> Option<SchedulableCoroutine<'r>> {
+        loop {
+            match injector.steal() {
+                Steal::Success(item) => return Some(item),
+                Steal::Retry => {}
+                Steal::Empty => return None,
+            }
+        }
+    }
+
+    fn is_priority_empty(&self, priority: Priority) -> bool {
+        match priority {
+            Priority::High => self.high.is_empty(),
+            Priority::Normal => self.normal.is_empty(),
+            Priority::Low => self.low.is_empty(),
+        }
+    }
+
+    pub(crate) fn policy(&self) -> SchedulePolicy {
+        if self.deadline_first.load(Ordering::Acquire) {
+            SchedulePolicy::Deadline
+        } else {
+            SchedulePolicy::Priority
+        }
+    }
+
+    pub(crate) fn set_policy(&self, policy: SchedulePolicy) {
+        self.deadline_first
+            .store(SchedulePolicy::Deadline == policy, Ordering::Release);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.skipped.is_empty()
+            && self.high.is_empty()
+            && self.normal.is_empty()
+            && self.low.is_empty()
+            && self.deadline_len.load(Ordering::Acquire) == 0
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.skipped.len() + self.queued_len()
+    }
+
+    fn queued_len(&self) -> usize {
+        self.high.len()
+            + self.normal.len()
+            + self.low.len()
+            + self.deadline_len.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn push_back(&self, coroutine: SchedulableCoroutine<'r>) {
+        if let (SchedulePolicy::Deadline, Some(deadline)) = (self.policy(), coroutine.deadline()) {
+            let mut queue = self.deadline.lock().unwrap();
+            if queue
+                .insert((deadline, coroutine.id()), coroutine)
+                .is_none()
+            {
+                _ = self.deadline_len.fetch_add(1, Ordering::Release);
+            }
+            return;
+        }
+        match coroutine.priority() {
+            Priority::High => self.high.push(coroutine),
+            Priority::Normal => self.normal.push_back(coroutine),
+            Priority::Low => self.low.push(coroutine),
+        }
+    }
+
+    pub(crate) fn pop_front(&self) -> Option<SchedulableCoroutine<'r>> {
+        Self::pop(&self.skipped).or_else(|| self.pop_queued())
+    }
+
+    fn pop_queued(&self) -> Option<SchedulableCoroutine<'r>> {
+        // 切换策略后，另一种队列中剩余的协程仍需被调度
+        match self.policy() {
+            SchedulePolicy::Deadline
+                if self.aging.pick_deadline(
+                    Priority::ALL
+                        .into_iter()
+                        .all(|priority| self.is_priority_empty(priority)),
+                ) =>
+            {
+                self.pop_deadline().or_else(|| self.pop_priority())
+            }
+            // 没有截止时间的协程也会老化，不会被饿死
+            SchedulePolicy::Deadline | SchedulePolicy::Priority => {
+                self.pop_priority().or_else(|| self.pop_deadline())
+            }
+        }
+    }
+
+    fn pop_deadline(&self) -> Option<SchedulableCoroutine<'r>> {
+        if self.deadline_len.load(Ordering::Acquire) == 0 {
+            return None;
+        }
+        let (_, coroutine) = self.deadline.lock().unwrap().pop_first()?;
+        _ = self.deadline_len.fetch_sub(1, Ordering::Release);
+        Some(coroutine)
+    }
+
+    fn pop_priority(&self) -> Option<SchedulableCoroutine<'r>> {
+        match self.aging.pick(|priority| self.is_priority_empty(priority)) {
+            Some(Priority::High) => Self::pop(&self.high),
+            Some(Priority::Low) => Self::pop(&self.low),
+            // the normal queue may steal from other schedulers even if it's empty
+            Some(Priority::Normal) | None => self.normal.pop_front(),
+        }
+    }
+
+    /// Take the coroutine out of the ready queue to run it next. The coroutines ahead
+    /// of it are skipped lazily, they keep their order and are popped before the others,
+    /// so each coroutine is moved once at most.
+    pub(crate) fn take(&self, co_id: usize) -> Option<SchedulableCoroutine<'r>> {
+        if !self.skipped.is_empty() {
+            // the target may be skipped by the previous take, rotate to keep the order
+            let mut found = None;
+            for _ in 0..self.skipped.len() {
+                match Self::pop(&self.skipped) {
+                    Some(coroutine) if found.is_none() && coroutine.id() == co_id => {
+                        found = Some(coroutine);
+                    }
+                    Some(coroutine) => self.skipped.push(coroutine),
+                    None => break,
+                }
+            }
+            if found.is_some() {
+                return found;
+            }
+        }
+        for _ in 0..self.queued_len() {
+            let coroutine = self.pop_queued()?;
+            if coroutine.id() == co_id {
+                return Some(coroutine);
+            }
+            self.skipped.push(coroutine);
+        }
+        None
+    }
+}
@@ -0,0 +1,726 @@
+use crate::future::{block_on, block_on_until};
+use crate::sync::queue::WaitQueue;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The error of `Sender::send`, the channel is closed and the value is returned.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SendError<T>(pub T);
+
+/// The error of `Sender::try_send`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TrySendError<T> {
+    /// The channel is full.
+    Full(T),
+    /// The channel is closed.
+    Closed(T),
+}
+
+/// The error of `Sender::send_timeout`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SendTimeoutError<T> {
+    /// The channel is still full when the timeout expires.
+    Timeout(T),
+    /// The channel is closed.
+    Closed(T),
+}
+
+/// The error of `Receiver::recv`, the channel is closed and empty.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RecvError;
+
+/// The error of `Receiver::try_recv`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TryRecvError {
+    /// The channel is empty.
+    Empty,
+    /// The channel is closed and empty.
+    Closed,
+}
+
+/// The error of `Receiver::recv_timeout`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RecvTimeoutError {
+    /// The channel is still empty when the timeout expires.
+    Timeout,
+    /// The channel is closed and empty.
+    Closed,
+}
+
+impl<T> Display for SendError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sending on a closed channel")
+    }
+}
+
+impl<T> Display for TrySendError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "sending on a full channel"),
+            TrySendError::Closed(_) => write!(f, "sending on a closed channel"),
+        }
+    }
+}
+
+impl<T> Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => write!(f, "timed out sending on a full channel"),
+            SendTimeoutError::Closed(_) => write!(f, "sending on a closed channel"),
+        }
+    }
+}
+
+impl Display for RecvError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "receiving on a closed channel")
+    }
+}
+
+impl Display for TryRecvError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "receiving on an empty channel"),
+            TryRecvError::Closed => write!(f, "receiving on a closed channel"),
+        }
+    }
+}
+
+impl Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => write!(f, "timed out receiving on an empty channel"),
+            RecvTimeoutError::Closed => write!(f, "receiving on a closed channel"),
+        }
+    }
+}
+
+impl<T: Debug> std::error::Error for SendError<T> {}
+
+impl<T: Debug> std::error::Error for TrySendError<T> {}
+
+impl<T: Debug> std::error::Error for SendTimeoutError<T> {}
+
+impl std::error::Error for RecvError {}
+
+impl std::error::Error for TryRecvError {}
+
+impl std::error::Error for RecvTimeoutError {}
+
+#[repr(C)]
+#[derive(Debug)]
+struct Queue<T> {
+    items: VecDeque<T>,
+    closed: bool,
+    //等待中的发送者和接收者数，没有等待者时不必唤醒
+    senders_waiting: usize,
+    receivers_waiting: usize,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct Channel<T> {
+    queue: Mutex<Queue<T>>,
+    //None表示无界
+    capacity: Option<usize>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    //等待空位的发送者
+    senders_queue: WaitQueue,
+    //等待消息的接收者
+    receivers_queue: WaitQueue,
+}
+
+impl<T> Channel<T> {
+    fn new(capacity: Option<usize>) -> Self {
+        Channel {
+            queue: Mutex::new(Queue {
+                items: VecDeque::new(),
+                closed: false,
+                senders_waiting: 0,
+                receivers_waiting: 0,
+            }),
+            capacity,
+            senders: AtomicUsize::new(1),
+            receivers: AtomicUsize::new(1),
+            senders_queue: WaitQueue::new(),
+            receivers_queue: WaitQueue::new(),
+        }
+    }
+
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let notify = {
+            let mut queue = self.queue.lock().unwrap();
+            if queue.closed {
+                return Err(TrySendError::Closed(value));
+            }
+            if self.capacity.is_some_and(|c| queue.items.len() >= c) {
+                return Err(TrySendError::Full(value));
+            }
+            queue.items.push_back(value);
+            queue.receivers_waiting > 0
+        };
+        if notify {
+            _ = self.receivers_queue.notify_one();
+        }
+        Ok(())
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        let (value, notify) = {
+            let mut queue = self.queue.lock().unwrap();
+            match queue.items.pop_front() {
+                Some(value) => (value, queue.senders_waiting > 0),
+                // 关闭后先取完剩余的消息
+                None if queue.closed => return Err(TryRecvError::Closed),
+                None => return Err(TryRecvError::Empty),
+            }
+        };
+        if notify {
+            _ = self.senders_queue.notify_one();
+        }
+        Ok(value)
+    }
+
+    /// Count a waiting sender or receiver until the returned guard is dropped,
+    /// it must be counted before queued, so a message or a vacancy is never missed.
+    fn waiting(&self, sender: bool) -> Waiting<'_, T> {
+        *Waiting::count(&mut self.queue.lock().unwrap(), sender) += 1;
+        Waiting {
+            channel: self,
+            sender,
+        }
+    }
+
+    /// Poll `try_send`, the value is kept in `slot` while the channel is full.
+    fn poll_send(&self, slot: &mut Option<T>) -> Option<Result<(), SendError<T>>> {
+        match self.try_send(slot.take()?) {
+            Ok(()) => Some(Ok(())),
+            Err(TrySendError::Full(value)) => {
+                *slot = Some(value);
+                None
+            }
+            Err(TrySendError::Closed(value)) => Some(Err(SendError(value))),
+        }
+    }
+
+    fn poll_recv(&self) -> Option<Result<T, RecvError>> {
+        match self.try_recv() {
+            Ok(value) => Some(Ok(value)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Closed) => Some(Err(RecvError)),
+        }
+    }
+
+    fn close(&self) {
+        self.queue.lock().unwrap().closed = true;
+        self.senders_queue.notify_all();
+        self.receivers_queue.notify_all();
+    }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct Waiting<'c, T> {
+    channel: &'c Channel<T>,
+    sender: bool,
+}
+
+impl<T> Waiting<'_, T> {
+    fn count(queue: &mut Queue<T>, sender: bool) -> &mut usize {
+        if sender {
+            &mut queue.senders_waiting
+        } else {
+            &mut queue.receivers_waiting
+        }
+    }
+}
+
+impl<T> Drop for Waiting<'_, T> {
+    fn drop(&mut self) {
+        *Self::count(&mut self.channel.queue.lock().unwrap(), self.sender) -= 1;
+    }
+}
+
+/// Creates a channel which holds at most `capacity` messages, `send` waits
+/// while it's full.
+///
+/// # Panics
+/// if `capacity` is 0.
+///
+/// # Examples
+/// ```
+/// use open_coroutine_core::sync::channel::{bounded, TrySendError};
+///
+/// let (sender, receiver) = bounded(1);
+/// sender.send(1).unwrap();
+/// assert_eq!(Err(TrySendError::Full(2)), sender.try_send(2));
+/// assert_eq!(Ok(1), receiver.recv());
+/// ```
+#[must_use]
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "the capacity of a bounded channel can't be 0");
+    new(Some(capacity))
+}
+
+/// Creates a channel without a limit, `send` never waits.
+#[must_use]
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    new(None)
+}
+
+fn new<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Channel::new(capacity));
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver { channel },
+    )
+}
+
+/// The sending half of a channel, it can be cloned and sent to other threads.
+///
+/// Waiting in a coroutine suspends it instead of blocking the thread, and the
+/// waiters can be woken from any thread. The channel is closed when all senders
+/// or all receivers are dropped.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends a message, waits while the channel is full.
+    ///
+    /// # Errors
+    /// if the channel is closed.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut slot = Some(value);
+        if let Some(result) = self.channel.poll_send(&mut slot) {
+            return result;
+        }
+        let _waiting = self.channel.waiting(true);
+        let mut waiter = self.channel.senders_queue.waiter();
+        block_on(std::future::poll_fn(|cx| {
+            waiter.poll(cx, || self.channel.poll_send(&mut slot))
+        }))
+    }
+
+    /// Attempts to send a message without waiting.
+    ///
+    /// # Errors
+    /// if the channel is full or closed.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.channel.try_send(value)
+    }
+
+    /// Sends a message, waits until the `timeout` expires while the channel is full.
+    ///
+    /// # Errors
+    /// if timeout or the channel is closed.
+    ///
+    /// # Panics
+    /// if the channel is poisoned.
+    pub fn send_timeout(&self, value: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+        let deadline = open_coroutine_timer::get_timeout_time(timeout);
+        let mut slot = Some(value);
+        let _waiting = self.channel.waiting(true);
+        let mut waiter = self.channel.senders_queue.waiter();
+        let result = block_on_until(
+            std::future::poll_fn(|cx| waiter.poll(cx, || self.channel.poll_send(&mut slot))),
+            deadline,
+        );
+        // 超时放弃时把唤醒传给下一个发送者
+        drop(waiter);
+        match result {
+            Some(Ok(())) => Ok(()),
+            Some(Err(SendError(value))) => Err(SendTimeoutError::Closed(value)),
+            None => Err(SendTimeoutError::Timeout(
+                slot.expect("the message has been sent"),
+            )),
+        }
+    }
+
+    /// Closes the channel, the messages sent can still be received.
+    pub fn close(&self) {
+        self.channel.close();
+    }
+
+    /// Returns `true` if the channel is closed.
+    ///
+    /// # Panics
+    /// if the channel is poisoned.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.channel.queue.lock().unwrap().closed
+    }
+
+    /// Returns the number of messages in the channel.
+    ///
+    /// # Panics
+    /// if the channel is poisoned.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.channel.queue.lock().unwrap().items.len()
+    }
+
+    /// Returns `true` if there is no message in the channel.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        0 == self.len()
+    }
+
+    /// Returns the capacity of the channel, `None` if it's unbounded.
+    #[must_use]
+    pub fn capacity(&self) -> Option<usize> {
+        self.channel.capacity
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        _ = self.channel.senders.fetch_add(1, Ordering::AcqRel);
+        Sender {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.channel.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.channel.close();
+        }
+    }
+}
+
+/// The receiving half of a channel, it can be cloned and sent to other threads,
+/// each message is received by only one receiver.
+///
+/// Waiting in a coroutine suspends it instead of blocking the thread, and the
+/// waiters can be woken from any thread. The channel is closed when all senders
+/// or all receivers are dropped.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receives a message, waits while the channel is empty.
+    ///
+    /// # Errors
+    /// if the channel is closed and empty.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        if let Some(result) = self.channel.poll_recv() {
+            return result;
+        }
+        let _waiting = self.channel.waiting(false);
+        let mut waiter = self.channel.receivers_queue.waiter();
+        block_on(std::future::poll_fn(|cx| {
+            waiter.poll(cx, || self.channel.poll_recv())
+        }))
+    }
+
+    /// Attempts to receive a message without waiting.
+    ///
+    /// # Errors
+    /// if the channel is empty, or closed and empty.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.channel.try_recv()
+    }
+
+    /// Receives a message, waits until the `timeout` expires while the channel is empty.
+    ///
+    /// # Errors
+    /// if timeout or the channel is closed and empty.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = open_coroutine_timer::get_timeout_time(timeout);
+        let _waiting = self.channel.waiting(false);
+        let mut waiter = self.channel.receivers_queue.waiter();
+        let result = block_on_until(
+            std::future::poll_fn(|cx| waiter.poll(cx, || self.channel.poll_recv())),
+            deadline,
+        );
+        match result {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(RecvError)) => Err(RecvTimeoutError::Closed),
+            // 超时放弃时由waiter把唤醒传给下一个接收者
+            None => Err(RecvTimeoutError::Timeout),
+        }
+    }
+
+    /// Returns an iterator which receives the messages until the channel is closed and empty.
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+
+    /// Closes the channel, the messages sent can still be received.
+    pub fn close(&self) {
+        self.channel.close();
+    }
+
+    /// Returns `true` if the channel is closed.
+    ///
+    /// # Panics
+    /// if the channel is poisoned.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.channel.queue.lock().unwrap().closed
+    }
+
+    /// Returns the number of messages in the channel.
+    ///
+    /// # Panics
+    /// if the channel is poisoned.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.channel.queue.lock().unwrap().items.len()
+    }
+
+    /// Returns `true` if there is no message in the channel.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        0 == self.len()
+    }
+
+    /// Returns the capacity of the channel, `None` if it's unbounded.
+    #[must_use]
+    pub fn capacity(&self) -> Option<usize> {
+        self.channel.capacity
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        _ = self.channel.receivers.fetch_add(1, Ordering::AcqRel);
+        Receiver {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if self.channel.receivers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.channel.close();
+        }
+    }
+}
+
+/// An iterator over the messages of a `Receiver`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Iter<'r, T> {
+    receiver: &'r Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'r, T> IntoIterator for &'r Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'r, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coroutine::suspender::SimpleDelaySuspender;
+    use crate::scheduler::SchedulerImpl;
+    use crate::sync::tests::{schedule, submit};
+
+    #[test]
+    fn test_threads() {
+        let (sender, receiver) = bounded(2);
+        let producers: Vec<_> = (0..2)
+            .map(|i| {
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    for j in 0..100 {
+                        sender.send(i * 100 + j).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let receiver = receiver.clone();
+                std::thread::spawn(move || receiver.iter().collect::<Vec<_>>())
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut messages: Vec<_> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        messages.sort_unstable();
+        assert_eq!((0..200).collect::<Vec<_>>(), messages);
+        assert!(receiver.is_closed());
+    }
+
+    #[test]
+    fn test_suspend() -> std::io::Result<()> {
+        let (sender, receiver) = bounded(1);
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let others = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        submit(&scheduler, move |_, ()| {
+            // waits for the receiver while the channel is full
+            for i in 0..2 {
+                sender.send(i).unwrap();
+            }
+        })?;
+        let r = messages.clone();
+        submit(
+            &scheduler, // waits for the sender while the channel is empty
+            move |_, ()| r.lock().unwrap().extend(receiver.iter()),
+        )?;
+        let o = others.clone();
+        submit(&scheduler, move |_, ()| {
+            _ = o.fetch_add(1, Ordering::Release);
+        })?;
+        schedule(&mut scheduler)?;
+        assert_eq!(vec![0, 1], *messages.lock().unwrap());
+        // the thread was not blocked by the waiters
+        assert_eq!(1, others.load(Ordering::Acquire));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_thread() -> std::io::Result<()> {
+        // the sender and the receiver run in different schedulers and threads
+        let (sender, receiver) = unbounded();
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let run = |sender: Sender<usize>,
+                   receiver: Receiver<usize>,
+                   messages: Arc<Mutex<Vec<usize>>>,
+                   is_sender: bool| {
+            std::thread::spawn(move || -> std::io::Result<()> {
+                let mut scheduler = SchedulerImpl::default();
+                submit(&scheduler, move |suspender, ()| {
+                    if is_sender {
+                        drop(receiver);
+                        // the receiver is waiting in another thread
+                        suspender.delay(Duration::from_millis(50));
+                        for i in 0..3 {
+                            sender.send(i).unwrap();
+                        }
+                    } else {
+                        drop(sender);
+                        messages.lock().unwrap().extend(receiver.iter());
+                    }
+                })?;
+                schedule(&mut scheduler)?;
+                Ok(())
+            })
+        };
+        let waiter = run(sender.clone(), receiver.clone(), messages.clone(), false);
+        let notifier = run(sender, receiver, messages.clone(), true);
+        notifier.join().expect("sender panicked")?;
+        waiter.join().expect("receiver panicked")?;
+        assert_eq!(vec![0, 1, 2], *messages.lock().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_close() {
+        let (sender, receiver) = bounded(2);
+        sender.send(1).unwrap();
+        drop(sender);
+        // the remaining messages are messages after closed
+        assert_eq!(Ok(1), receiver.try_recv());
+        assert_eq!(Err(TryRecvError::Closed), receiver.try_recv());
+        assert_eq!(Err(RecvError), receiver.recv());
+
+        let (sender, receiver) = unbounded();
+        let other = receiver.clone();
+        drop(receiver);
+        sender.send(1).unwrap();
+        other.close();
+        assert!(sender.is_closed());
+        assert_eq!(Err(SendError(2)), sender.send(2));
+        assert_eq!(Some(1), other.iter().next());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (sender, receiver) = bounded(1);
+        assert_eq!(
+            Err(RecvTimeoutError::Timeout),
+            receiver.recv_timeout(Duration::from_millis(10))
+        );
+        sender.send(1).unwrap();
+        assert_eq!(
+            Err(SendTimeoutError::Timeout(2)),
+            sender.send_timeout(2, Duration::from_millis(10))
+        );
+        assert_eq!(1, sender.len());
+        assert_eq!(Ok(1), receiver.recv_timeout(Duration::from_millis(10)));
+        assert_eq!(Ok(()), sender.send_timeout(3, Duration::from_millis(10)));
+        drop(sender);
+        assert_eq!(Ok(3), receiver.recv_timeout(Duration::from_millis(10)));
+        assert_eq!(
+            Err(RecvTimeoutError::Closed),
+            receiver.recv_timeout(Duration::from_millis(10))
+        );
+        // the expired waiters are not counted
+        let queue = receiver.channel.queue.lock().unwrap();
+        assert_eq!((0, 0), (queue.senders_waiting, queue.receivers_waiting));
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_event_loops() -> std::io::Result<()> {
+        use crate::constants::Priority;
+        use crate::net::event_loop::EventLoopImpl;
+        use crate::pool::join::JoinHandle;
+        use crate::pool::Pool;
+
+        // the sender and the receiver run in the workers of different event loops
+        let producer = EventLoopImpl::default().start()?;
+        let consumer = EventLoopImpl::default().start()?;
+        let (sender, receiver) = bounded(1);
+        let total = consumer.submit_with_priority(
+            None,
+            move |_| Some(receiver.iter().sum()),
+            None,
+            Priority::High,
+        );
+        let count = producer.submit_with_priority(
+            None,
+            move |_| {
+                // waits for the receiver while the channel is full
+                for i in 0..10 {
+                    sender.send(i).unwrap();
+                }
+                Some(10)
+            },
+            None,
+            Priority::High,
+        );
+        let result = producer.wait_result(count.get_name()?, Duration::from_secs(3))?;
+        assert_eq!(Ok(Some(10)), result.expect("wait timeout").1);
+        let result = consumer.wait_result(total.get_name()?, Duration::from_secs(3))?;
+        assert_eq!(Ok(Some(45)), result.expect("wait timeout").1);
+        producer.stop(Duration::from_secs(3))?;
+        consumer.stop(Duration::from_secs(3))
+    }
+}
@@ -0,0 +1,220 @@
+use crate::future::{block_on, block_on_until};
+use crate::sync::mutex::MutexGuard;
+use crate::sync::queue::{WaitQueue, Waiter};
+use std::task::Poll;
+use std::time::Duration;
+
+/// Whether a timed wait of `Condvar` returned because of the timeout.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    /// Returns `true` if the wait was known to have timed out.
+    #[must_use]
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+/// A condition variable which works with `crate::sync::mutex::Mutex`.
+///
+/// The waiting coroutine is parked in the syscall table of its scheduler, the timed
+/// waits park it by `SyscallState::Suspend(timestamp)`, so the scheduler resumes it at
+/// the timeout like other syscalls, without an extra thread. Out of coroutines the
+/// thread is blocked instead.
+///
+/// # Examples
+/// ```
+/// use open_coroutine_core::sync::condvar::Condvar;
+/// use open_coroutine_core::sync::mutex::Mutex;
+/// use std::sync::Arc;
+///
+/// let pair = Arc::new((Mutex::new(false), Condvar::new()));
+/// let pair2 = pair.clone();
+/// std::thread::spawn(move || {
+///     let (lock, cvar) = &*pair2;
+///     *lock.lock() = true;
+///     cvar.notify_one();
+/// });
+/// let (lock, cvar) = &*pair;
+/// let started = cvar.wait_while(lock.lock(), |started| !*started);
+/// assert!(*started);
+/// ```
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+impl Condvar {
+    /// Creates a new condition variable.
+    #[must_use]
+    pub const fn new() -> Self {
+        Condvar {
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Releases the lock and waits for a notification, the lock is
+    /// re-acquired before returning. There may be spurious wakeups.
+    pub fn wait<'m, T: ?Sized>(&self, guard: MutexGuard<'m, T>) -> MutexGuard<'m, T> {
+        let mutex = guard.mutex;
+        let mut guard = Some(guard);
+        let mut waiter = self.waiters.waiter();
+        block_on(std::future::poll_fn(|cx| {
+            Self::poll_wait(&mut guard, &mut waiter, cx)
+        }));
+        mutex.lock()
+    }
+
+    /// Waits until the `condition` returns `false`.
+    pub fn wait_while<'m, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'m, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'m, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Like `wait`, but gives up after `timeout`.
+    pub fn wait_timeout<'m, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'m, T>,
+        timeout: Duration,
+    ) -> (MutexGuard<'m, T>, WaitTimeoutResult) {
+        self.wait_until(guard, open_coroutine_timer::get_timeout_time(timeout))
+    }
+
+    /// Like `wait_while`, but gives up after `timeout`.
+    pub fn wait_timeout_while<'m, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'m, T>,
+        timeout: Duration,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> (MutexGuard<'m, T>, WaitTimeoutResult) {
+        let deadline = open_coroutine_timer::get_timeout_time(timeout);
+        while condition(&mut *guard) {
+            let (g, result) = self.wait_until(guard, deadline);
+            guard = g;
+            if result.timed_out() {
+                let timed_out = condition(&mut *guard);
+                return (guard, WaitTimeoutResult(timed_out));
+            }
+        }
+        (guard, WaitTimeoutResult(false))
+    }
+
+    /// Like `wait`, but gives up at the `deadline`, which is a timestamp
+    /// in ns like `open_coroutine_timer::get_timeout_time`.
+    pub fn wait_until<'m, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'m, T>,
+        deadline: u64,
+    ) -> (MutexGuard<'m, T>, WaitTimeoutResult) {
+        let mutex = guard.mutex;
+        let mut guard = Some(guard);
+        let mut waiter = self.waiters.waiter();
+        let notified = block_on_until(
+            std::future::poll_fn(|cx| Self::poll_wait(&mut guard, &mut waiter, cx)),
+            deadline,
+        );
+        // 超时前刚好被通知的也不算超时
+        let timed_out = notified.is_none() && waiter.cancel();
+        (mutex.lock(), WaitTimeoutResult(timed_out))
+    }
+
+    fn poll_wait<T: ?Sized>(
+        guard: &mut Option<MutexGuard<'_, T>>,
+        waiter: &mut Waiter<'_>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<()> {
+        let poll = waiter.poll_notified(cx);
+        // 入队后才释放锁，保证不会错过释放锁之后的通知
+        drop(guard.take());
+        poll
+    }
+
+    /// Wakes up one waiter.
+    pub fn notify_one(&self) {
+        _ = self.waiters.notify_one();
+    }
+
+    /// Wakes up all waiters.
+    pub fn notify_all(&self) {
+        self.waiters.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::{Scheduler, SchedulerImpl};
+    use crate::sync::mutex::Mutex;
+    use crate::sync::tests::{schedule, submit};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_notify() -> std::io::Result<()> {
+        let pair = Arc::new((Mutex::new(0), Condvar::new()));
+        let woken = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        for _ in 0..2 {
+            let (pair, woken) = (pair.clone(), woken.clone());
+            submit(&scheduler, move |_, ()| {
+                let (lock, cvar) = &*pair;
+                let guard = cvar.wait_while(lock.lock(), |ready| *ready == 0);
+                assert!(*guard > 0);
+                _ = woken.fetch_add(1, Ordering::Release);
+            })?;
+        }
+        _ = scheduler.try_timed_schedule(Duration::from_millis(10))?;
+        assert_eq!(0, woken.load(Ordering::Acquire));
+        // notified from another thread
+        let notifier = pair.clone();
+        _ = std::thread::spawn(move || {
+            let (lock, cvar) = &*notifier;
+            *lock.lock() = 1;
+            cvar.notify_all();
+        });
+        schedule(&mut scheduler)?;
+        assert_eq!(2, woken.load(Ordering::Acquire));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_timeout() -> std::io::Result<()> {
+        let pair = Arc::new((Mutex::new(false), Condvar::new()));
+        let others = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        let p = pair.clone();
+        submit(&scheduler, move |_, ()| {
+            let (lock, cvar) = &*p;
+            let (guard, result) = cvar.wait_timeout(lock.lock(), Duration::from_millis(10));
+            assert!(result.timed_out());
+            let (guard, result) =
+                cvar.wait_timeout_while(guard, Duration::from_secs(3), |ready| !*ready);
+            assert!(!result.timed_out());
+            assert!(*guard);
+        })?;
+        let o = others.clone();
+        submit(&scheduler, move |_, ()| {
+            _ = o.fetch_add(1, Ordering::Release);
+        })?;
+        let notifier = pair.clone();
+        _ = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            let (lock, cvar) = &*notifier;
+            *lock.lock() = true;
+            cvar.notify_one();
+        });
+        schedule(&mut scheduler)?;
+        // the thread was not blocked by the timed wait
+        assert_eq!(1, others.load(Ordering::Acquire));
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+/// Coroutine-aware mutex.
+pub mod mutex;
+
+/// Coroutine-aware reader-writer lock.
+pub mod rwlock;
+
+/// Condition variable for coroutines.
+pub mod condvar;
+
+/// Counting semaphore for coroutines.
+pub mod semaphore;
+
+/// Channels between coroutines.
+pub mod channel;
+
+mod queue;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::constants::Priority;
+    use crate::coroutine::suspender::Suspender;
+    use crate::scheduler::{Scheduler, SchedulerImpl};
+    use std::panic::{RefUnwindSafe, UnwindSafe};
+    use std::time::Duration;
+
+    /// Submit a high priority coroutine, which can't be stolen by other tests.
+    pub(crate) fn submit<'s>(
+        scheduler: &SchedulerImpl<'s>,
+        f: impl FnOnce(&dyn Suspender<Resume = (), Yield = ()>, ()) + UnwindSafe + 's,
+    ) -> std::io::Result<()> {
+        scheduler.submit_with_priority(f, None, Priority::High)
+    }
+
+    /// Schedule until all coroutines finish, fails if they don't finish in 3s.
+    pub(crate) fn schedule(scheduler: &mut SchedulerImpl) -> std::io::Result<()> {
+        let timeout_time = open_coroutine_timer::get_timeout_time(Duration::from_secs(3));
+        while !scheduler.is_empty() {
+            assert!(
+                open_coroutine_timer::now() < timeout_time,
+                "schedule timeout"
+            );
+            _ = scheduler.try_timed_schedule(Duration::from_millis(1))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unwind_safe() {
+        fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() {}
+        assert_unwind_safe::<super::mutex::Mutex<usize>>();
+        assert_unwind_safe::<super::rwlock::RwLock<usize>>();
+        assert_unwind_safe::<super::condvar::Condvar>();
+        assert_unwind_safe::<super::semaphore::Semaphore>();
+    }
+}
@@ -0,0 +1,315 @@
+use crate::future::block_on;
+use crate::sync::queue::WaitQueue;
+use std::cell::UnsafeCell;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A mutual exclusion lock which suspends the coroutine instead of blocking the thread.
+///
+/// The contended lockers are queued, inside a coroutine the locker is suspended until
+/// the lock is released, even if the holder runs in another thread, so other coroutines
+/// can run in the thread meanwhile. Out of coroutines the thread is blocked instead.
+///
+/// Unlike `std::sync::Mutex`, the lock is not poisoned by a panic, and the guard can be
+/// held across suspensions, even if the coroutine is resumed in another thread.
+///
+/// # Examples
+/// ```
+/// use open_coroutine_core::sync::mutex::Mutex;
+/// use std::sync::Arc;
+///
+/// let mutex = Arc::new(Mutex::new(0));
+/// let m = mutex.clone();
+/// std::thread::spawn(move || *m.lock() += 1).join().unwrap();
+/// assert_eq!(1, *mutex.lock());
+/// ```
+pub struct Mutex<T: ?Sized> {
+    locked: AtomicBool,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+//UnsafeCell不是RefUnwindSafe的，只有持有数据的锁需要手动实现，其他原语由字段推导；
+//锁不会中毒，panic后数据可能处于中间状态，所以要求T本身是UnwindSafe的
+impl<T: ?Sized + UnwindSafe> RefUnwindSafe for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Creates a new mutex in an unlocked state.
+    pub const fn new(data: T) -> Self {
+        Mutex {
+            locked: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this mutex, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Acquires the mutex, the coroutine is suspended until the lock is acquired.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if let Some(guard) = self.try_lock() {
+            return guard;
+        }
+        let mut waiter = self.waiters.waiter();
+        block_on(std::future::poll_fn(|cx| {
+            waiter.poll(cx, || self.try_lock())
+        }))
+    }
+
+    /// Attempts to acquire the mutex without suspending.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Releases the lock and wakes the first waiter, which will try again.
+    fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+        _ = self.waiters.notify_one();
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for Mutex<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        match self.try_lock() {
+            Some(guard) => _ = d.field("data", &&*guard),
+            None => _ = d.field("data", &format_args!("<locked>")),
+        }
+        d.finish_non_exhaustive()
+    }
+}
+
+/// The guard of a locked `Mutex`, the lock is released when it's dropped.
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct MutexGuard<'m, T: ?Sized> {
+    pub(crate) mutex: &'m Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coroutine::suspender::{SimpleDelaySuspender, SimpleSuspender};
+    use crate::scheduler::SchedulerImpl;
+    use crate::sync::tests::{schedule, submit};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_threads() {
+        let mutex = Arc::new(Mutex::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let mutex = mutex.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        *mutex.lock() += 1;
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(4000, *mutex.lock());
+    }
+
+    #[test]
+    fn test_suspend() -> std::io::Result<()> {
+        let mutex = Arc::new(Mutex::new(Vec::new()));
+        let others = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        let m = mutex.clone();
+        submit(&scheduler, move |suspender, ()| {
+            let mut guard = m.lock();
+            guard.push("a1");
+            // hold the lock across a suspension
+            suspender.suspend();
+            guard.push("a2");
+        })?;
+        let m = mutex.clone();
+        submit(&scheduler, move |_, ()| m.lock().push("b"))?;
+        let o = others.clone();
+        submit(&scheduler, move |_, ()| {
+            _ = o.fetch_add(1, Ordering::Release);
+        })?;
+        schedule(&mut scheduler)?;
+        assert_eq!(vec!["a1", "a2", "b"], *mutex.lock());
+        // the thread was not blocked by the waiter
+        assert_eq!(1, others.load(Ordering::Acquire));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_thread() -> std::io::Result<()> {
+        // the holder and the waiter run in different schedulers and threads
+        let mutex = Arc::new(Mutex::new(0));
+        let locked = Arc::new(AtomicBool::new(false));
+        let run = |mutex: Arc<Mutex<usize>>, locked: Arc<AtomicBool>, holder: bool| {
+            std::thread::spawn(move || -> std::io::Result<()> {
+                let mut scheduler = SchedulerImpl::default();
+                submit(&scheduler, move |suspender, ()| {
+                    if holder {
+                        let mut guard = mutex.lock();
+                        locked.store(true, Ordering::Release);
+                        suspender.delay(Duration::from_millis(50));
+                        *guard += 1;
+                    } else {
+                        while !locked.load(Ordering::Acquire) {
+                            suspender.suspend();
+                        }
+                        let mut guard = mutex.lock();
+                        assert_eq!(1, *guard);
+                        *guard += 1;
+                    }
+                })?;
+                schedule(&mut scheduler)?;
+                Ok(())
+            })
+        };
+        let waiter = run(mutex.clone(), locked.clone(), false);
+        let holder = run(mutex.clone(), locked, true);
+        holder.join().expect("holder panicked")?;
+        waiter.join().expect("waiter panicked")?;
+        assert_eq!(2, *mutex.lock());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel() -> std::io::Result<()> {
+        use crate::common::Current;
+        use crate::coroutine::Coroutine;
+        use crate::scheduler::{SchedulableCoroutine, Scheduler};
+
+        let mutex = Arc::new(Mutex::new(Vec::new()));
+        let cancelled = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        let (m, c) = (mutex.clone(), cancelled.clone());
+        submit(&scheduler, move |suspender, ()| {
+            let mut guard = m.lock();
+            // let the others wait for the lock
+            suspender.suspend();
+            // the first waiter is cancelled after the unlock wakes it
+            if let Some(scheduler) = SchedulerImpl::current() {
+                scheduler.try_cancel(c.load(Ordering::Acquire));
+            }
+            guard.push("a");
+        })?;
+        let (m, c) = (mutex.clone(), cancelled.clone());
+        submit(&scheduler, move |_, ()| {
+            if let Some(coroutine) = SchedulableCoroutine::current() {
+                c.store(coroutine.id(), Ordering::Release);
+            }
+            m.lock().push("b");
+        })?;
+        let m = mutex.clone();
+        submit(&scheduler, move |_, ()| m.lock().push("c"))?;
+        schedule(&mut scheduler)?;
+        // the wakeup taken by the cancelled waiter is passed to the next one
+        assert_eq!(vec!["a", "c"], *mutex.lock());
+        Ok(())
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_event_loops() -> std::io::Result<()> {
+        use crate::constants::Priority;
+        use crate::net::event_loop::EventLoopImpl;
+        use crate::pool::join::JoinHandle;
+        use crate::pool::Pool;
+
+        // the holder and the waiter run in the workers of different event loops
+        let holder = EventLoopImpl::default().start()?;
+        let waiter = EventLoopImpl::default().start()?;
+        let mutex = Arc::new(Mutex::new(0));
+        let locked = Arc::new(AtomicBool::new(false));
+        let (m, l) = (mutex.clone(), locked.clone());
+        let holding = holder.submit_with_priority(
+            None,
+            move |_| {
+                let mut guard = m.lock();
+                l.store(true, Ordering::Release);
+                std::thread::sleep(Duration::from_millis(50));
+                *guard += 1;
+                Some(*guard)
+            },
+            None,
+            Priority::High,
+        );
+        let timeout_time = open_coroutine_timer::get_timeout_time(Duration::from_secs(3));
+        while !locked.load(Ordering::Acquire) {
+            assert!(open_coroutine_timer::now() < timeout_time, "lock timeout");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let m = mutex.clone();
+        let waiting = waiter.submit_with_priority(
+            None,
+            move |_| {
+                let mut guard = m.lock();
+                *guard += 1;
+                Some(*guard)
+            },
+            None,
+            Priority::High,
+        );
+        let result = waiter.wait_result(waiting.get_name()?, Duration::from_secs(3))?;
+        assert_eq!(Ok(Some(2)), result.expect("wait timeout").1);
+        let result = holder.wait_result(holding.get_name()?, Duration::from_secs(3))?;
+        assert_eq!(Ok(Some(1)), result.expect("wait timeout").1);
+        holder.stop(Duration::from_secs(3))?;
+        waiter.stop(Duration::from_secs(3))
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+/// The wakers waiting for a primitive in FIFO order.
+///
+/// The waiters are parked by `crate::future::block_on`, so a coroutine is suspended
+/// and resumed by its scheduler, even if it's woken from another thread, and a thread
+/// out of coroutines is parked.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct WaitQueue {
+    //(序号, Waker)，序号用于更新或删除自己的Waker
+    waiters: Mutex<VecDeque<(usize, Waker)>>,
+    seq: AtomicUsize,
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitQueue {
+    pub(crate) const fn new() -> Self {
+        WaitQueue {
+            waiters: Mutex::new(VecDeque::new()),
+            seq: AtomicUsize::new(0),
+        }
+    }
+
+    /// Create a waiter of this queue, which is queued at the first poll.
+    pub(crate) fn waiter(&self) -> Waiter<'_> {
+        Waiter {
+            queue: self,
+            key: None,
+        }
+    }
+
+    /// Queue the waker, or replace the queued one of the waiter.
+    fn register(&self, key: &mut Option<usize>, waker: &Waker) {
+        let mut waiters = self.waiters.lock().unwrap();
+        if let Some(k) = key {
+            if let Some((_, w)) = waiters.iter_mut().find(|(id, _)| id == k) {
+                w.clone_from(waker);
+                return;
+            }
+        }
+        let id = self.seq.fetch_add(1, Ordering::Relaxed);
+        *key = Some(id);
+        waiters.push_back((id, waker.clone()));
+    }
+
+    /// Remove the waiter, returns `false` if it has been woken.
+    fn remove(&self, key: &mut Option<usize>) -> bool {
+        let Some(k) = key.take() else {
+            return false;
+        };
+        let mut waiters = self.waiters.lock().unwrap();
+        if let Some(index) = waiters.iter().position(|(id, _)| *id == k) {
+            _ = waiters.remove(index);
+            return true;
+        }
+        false
+    }
+
+    /// Wake the first waiter, returns `false` if there is no waiter.
+    pub(crate) fn notify_one(&self) -> bool {
+        let waiter = self.waiters.lock().unwrap().pop_front();
+        if let Some((_, waker)) = waiter {
+            waker.wake();
+            return true;
+        }
+        false
+    }
+
+    /// Wake all waiters.
+    pub(crate) fn notify_all(&self) {
+        let waiters = std::mem::take(&mut *self.waiters.lock().unwrap());
+        for (_, waker) in waiters {
+            waker.wake();
+        }
+    }
+}
+
+/// A waiter of `WaitQueue`, if it's dropped before acquiring, such as timeout or the
+/// coroutine is cancelled, it's removed from the queue, and the notification it has
+/// consumed is passed to the next waiter, so the notification is never lost.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct Waiter<'q> {
+    queue: &'q WaitQueue,
+    //None表示未入队，或者已经获取成功
+    key: Option<usize>,
+}
+
+impl Waiter<'_> {
+    /// Try `acquire`, if failed the waker is queued and `acquire` is tried again,
+    /// so a release between the two tries can't be missed.
+    pub(crate) fn poll<R>(
+        &mut self,
+        cx: &Context<'_>,
+        mut acquire: impl FnMut() -> Option<R>,
+    ) -> Poll<R> {
+        if let Some(r) = acquire() {
+            _ = self.queue.remove(&mut self.key);
+            return Poll::Ready(r);
+        }
+        self.queue.register(&mut self.key, cx.waker());
+        if let Some(r) = acquire() {
+            _ = self.queue.remove(&mut self.key);
+            return Poll::Ready(r);
+        }
+        Poll::Pending
+    }
+
+    /// Returns `Ready` once the waiter has been woken by `notify_one` or `notify_all`,
+    /// otherwise its waker is queued or replaced.
+    pub(crate) fn poll_notified(&mut self, cx: &Context<'_>) -> Poll<()> {
+        if let Some(k) = self.key {
+            let mut waiters = self.queue.waiters.lock().unwrap();
+            let Some((_, w)) = waiters.iter_mut().find(|(id, _)| *id == k) else {
+                self.key = None;
+                return Poll::Ready(());
+            };
+            w.clone_from(cx.waker());
+            return Poll::Pending;
+        }
+        self.queue.register(&mut self.key, cx.waker());
+        Poll::Pending
+    }
+
+    /// Give up waiting and keep the notification if it has been woken,
+    /// returns `false` if it has been woken.
+    pub(crate) fn cancel(&mut self) -> bool {
+        self.queue.remove(&mut self.key)
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if self.key.is_some() && !self.queue.remove(&mut self.key) {
+            // 已被唤醒但放弃了，把唤醒传给下一个等待者
+            _ = self.queue.notify_one();
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use crate::future::{block_on, block_on_until};
+use crate::sync::queue::WaitQueue;
+use std::cell::UnsafeCell;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+/// A reader-writer lock which suspends the coroutine instead of blocking the thread.
+///
+/// The waiting readers and writers are parked in the syscall table of their schedulers
+/// like `crate::sync::mutex::Mutex`. Writers are preferred, once a writer is waiting the
+/// new readers wait until it's done, so acquiring the read lock recursively may deadlock.
+///
+/// # Examples
+/// ```
+/// use open_coroutine_core::sync::rwlock::RwLock;
+///
+/// let lock = RwLock::new(1);
+/// {
+///     let r1 = lock.read();
+///     let r2 = lock.read();
+///     assert_eq!(2, *r1 + *r2);
+///     assert!(lock.try_write().is_none());
+/// }
+/// *lock.write() += 1;
+/// assert_eq!(2, *lock.read());
+/// ```
+pub struct RwLock<T: ?Sized> {
+    //最高位表示写锁，其余位为读者数
+    state: AtomicUsize,
+    //正在等待的写者数
+    writers: AtomicUsize,
+    readers_queue: WaitQueue,
+    writers_queue: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+//UnsafeCell不是RefUnwindSafe的，只有持有数据的锁需要手动实现，其他原语由字段推导；
+//锁不会中毒，panic后数据可能处于中间状态，所以要求T本身是UnwindSafe的
+impl<T: ?Sized + UnwindSafe> RefUnwindSafe for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// Creates a new reader-writer lock in an unlocked state.
+    pub const fn new(data: T) -> Self {
+        RwLock {
+            state: AtomicUsize::new(0),
+            writers: AtomicUsize::new(0),
+            readers_queue: WaitQueue::new(),
+            writers_queue: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this lock, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Acquires a shared read lock, the coroutine is suspended until it's acquired.
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        if let Some(guard) = self.try_read() {
+            return guard;
+        }
+        let mut waiter = self.readers_queue.waiter();
+        block_on(std::future::poll_fn(|cx| {
+            waiter.poll(cx, || self.try_read())
+        }))
+    }
+
+    /// Attempts to acquire a shared read lock without suspending, it fails
+    /// if a writer holds the lock or is waiting for it.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & WRITER != 0 || self.writers.load(Ordering::Acquire) > 0 {
+                return None;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(RwLockReadGuard { lock: self }),
+                Err(s) => state = s,
+            }
+        }
+    }
+
+    /// Attempts to acquire a shared read lock until the `timeout` expires.
+    pub fn try_read_for(&self, timeout: Duration) -> Option<RwLockReadGuard<'_, T>> {
+        self.try_read_until(open_coroutine_timer::get_timeout_time(timeout))
+    }
+
+    /// Attempts to acquire a shared read lock until the `deadline`,
+    /// which is a timestamp in ns like `open_coroutine_timer::get_timeout_time`.
+    pub fn try_read_until(&self, deadline: u64) -> Option<RwLockReadGuard<'_, T>> {
+        if let Some(guard) = self.try_read() {
+            return Some(guard);
+        }
+        let mut waiter = self.readers_queue.waiter();
+        block_on_until(
+            std::future::poll_fn(|cx| waiter.poll(cx, || self.try_read())),
+            deadline,
+        )
+    }
+
+    /// Acquires an exclusive write lock, the coroutine is suspended until it's acquired.
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        if let Some(guard) = self.try_write() {
+            return guard;
+        }
+        let _waiting = WaitingWriter::new(self);
+        let mut waiter = self.writers_queue.waiter();
+        block_on(std::future::poll_fn(|cx| {
+            waiter.poll(cx, || self.try_write())
+        }))
+    }
+
+    /// Attempts to acquire an exclusive write lock without suspending.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwLockWriteGuard { lock: self })
+    }
+
+    /// Attempts to acquire an exclusive write lock until the `timeout` expires.
+    pub fn try_write_for(&self, timeout: Duration) -> Option<RwLockWriteGuard<'_, T>> {
+        self.try_write_until(open_coroutine_timer::get_timeout_time(timeout))
+    }
+
+    /// Attempts to acquire an exclusive write lock until the `deadline`,
+    /// which is a timestamp in ns like `open_coroutine_timer::get_timeout_time`.
+    pub fn try_write_until(&self, deadline: u64) -> Option<RwLockWriteGuard<'_, T>> {
+        if let Some(guard) = self.try_write() {
+            return Some(guard);
+        }
+        let _waiting = WaitingWriter::new(self);
+        let mut waiter = self.writers_queue.waiter();
+        block_on_until(
+            std::future::poll_fn(|cx| waiter.poll(cx, || self.try_write())),
+            deadline,
+        )
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn read_unlock(&self) {
+        if self.state.fetch_sub(1, Ordering::Release) == 1 {
+            _ = self.writers_queue.notify_one();
+        }
+    }
+
+    fn write_unlock(&self) {
+        self.state.store(0, Ordering::Release);
+        if !self.writers_queue.notify_one() {
+            self.readers_queue.notify_all();
+        }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for RwLock<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        match self.try_read() {
+            Some(guard) => _ = d.field("data", &&*guard),
+            None => _ = d.field("data", &format_args!("<locked>")),
+        }
+        d.finish_non_exhaustive()
+    }
+}
+
+/// Counts a waiting writer until it's dropped, even if the writer gives up or
+/// is cancelled, so the readers are not blocked by the writer preference forever.
+#[repr(C)]
+#[derive(Debug)]
+struct WaitingWriter<'l, T: ?Sized> {
+    lock: &'l RwLock<T>,
+}
+
+impl<'l, T: ?Sized> WaitingWriter<'l, T> {
+    fn new(lock: &'l RwLock<T>) -> Self {
+        _ = lock.writers.fetch_add(1, Ordering::AcqRel);
+        WaitingWriter { lock }
+    }
+}
+
+impl<T: ?Sized> Drop for WaitingWriter<'_, T> {
+    fn drop(&mut self) {
+        if self.lock.writers.fetch_sub(1, Ordering::AcqRel) == 1
+            && self.lock.state.load(Ordering::Acquire) & WRITER == 0
+        {
+            // 没有写者在等待了，被写者优先挡住的读者可以继续
+            self.lock.readers_queue.notify_all();
+        }
+    }
+}
+
+/// The guard of a shared read lock, the lock is released when it's dropped.
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockReadGuard<'l, T: ?Sized> {
+    lock: &'l RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+/// The guard of an exclusive write lock, the lock is released when it's dropped.
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockWriteGuard<'l, T: ?Sized> {
+    lock: &'l RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coroutine::suspender::{SimpleDelaySuspender, SimpleSuspender};
+    use crate::scheduler::SchedulerImpl;
+    use crate::sync::tests::{schedule, submit};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_threads() {
+        let lock = Arc::new(RwLock::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let lock = lock.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        if i % 2 == 0 {
+                            *lock.write() += 1;
+                        } else {
+                            assert!(*lock.read() <= 2000);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(2000, *lock.read());
+    }
+
+    #[test]
+    fn test_writer_preferred() -> std::io::Result<()> {
+        let lock = Arc::new(RwLock::new(()));
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let mut scheduler = SchedulerImpl::default();
+        let submit = |name: &'static str, write: bool, hold: bool| {
+            let (lock, executed) = (lock.clone(), executed.clone());
+            submit(&scheduler, move |suspender, ()| {
+                if write {
+                    let _guard = lock.write();
+                    executed.lock().unwrap().push(name);
+                } else {
+                    let _guard = lock.read();
+                    executed.lock().unwrap().push(name);
+                    if hold {
+                        suspender.delay(Duration::from_millis(20));
+                    }
+                }
+            })
+        };
+        // the writer waits for r1, then r2 waits for the writer
+        submit("r1", false, true)?;
+        submit("w", true, false)?;
+        submit("r2", false, false)?;
+        schedule(&mut scheduler)?;
+        assert_eq!(vec!["r1", "w", "r2"], *executed.lock().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout() -> std::io::Result<()> {
+        let lock = Arc::new(RwLock::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        let l = lock.clone();
+        submit(&scheduler, move |suspender, ()| {
+            let _guard = l.write();
+            suspender.delay(Duration::from_millis(50));
+        })?;
+        let l = lock.clone();
+        submit(&scheduler, move |_, ()| {
+            assert!(l.try_read_for(Duration::from_millis(10)).is_none());
+            assert!(l.try_write_for(Duration::from_millis(10)).is_none());
+            *l.try_write_for(Duration::from_secs(3))
+                .expect("write timeout") += 1;
+        })?;
+        schedule(&mut scheduler)?;
+        assert_eq!(1, *lock.read());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel() -> std::io::Result<()> {
+        use crate::common::Current;
+        use crate::coroutine::Coroutine;
+        use crate::scheduler::{SchedulableCoroutine, Scheduler};
+        use std::sync::atomic::AtomicUsize;
+
+        let lock = Arc::new(RwLock::new(()));
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let writer = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        let (l, e, w) = (lock.clone(), executed.clone(), writer.clone());
+        submit(&scheduler, move |suspender, ()| {
+            let _guard = l.read();
+            // let the writer and the other reader wait
+            suspender.suspend();
+            // the writer is cancelled after the unlock wakes it
+            if let Some(scheduler) = SchedulerImpl::current() {
+                scheduler.try_cancel(w.load(Ordering::Acquire));
+            }
+            e.lock().unwrap().push("r1");
+        })?;
+        let (l, e, w) = (lock.clone(), executed.clone(), writer.clone());
+        submit(&scheduler, move |_, ()| {
+            if let Some(coroutine) = SchedulableCoroutine::current() {
+                w.store(coroutine.id(), Ordering::Release);
+            }
+            let _guard = l.write();
+            e.lock().unwrap().push("w");
+        })?;
+        let (l, e) = (lock.clone(), executed.clone());
+        submit(&scheduler, move |_, ()| {
+            let _guard = l.read();
+            e.lock().unwrap().push("r2");
+        })?;
+        schedule(&mut scheduler)?;
+        // the cancelled writer doesn't block the reader
+        assert_eq!(vec!["r1", "r2"], *executed.lock().unwrap());
+        assert_eq!(0, lock.writers.load(Ordering::Acquire));
+        Ok(())
+    }
+}
@@ -0,0 +1,430 @@
+use crate::future::{block_on, block_on_until};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+#[repr(C)]
+#[derive(Debug)]
+struct Waiter {
+    id: usize,
+    //需要的许可数
+    need: usize,
+    //许可已移交给该等待者
+    granted: bool,
+    waker: Waker,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct State {
+    permits: usize,
+    //许可总数，包括已被持有的
+    total: usize,
+    seq: usize,
+    waiters: VecDeque<Waiter>,
+}
+
+impl State {
+    /// Hand the permits to the waiters in FIFO order, returns the wakers to wake.
+    fn dispatch(&mut self) -> Vec<Waker> {
+        let mut wakers = Vec::new();
+        for waiter in self.waiters.iter_mut().filter(|w| !w.granted) {
+            // 队首的等待者满足不了时，后面的也不能插队
+            if self.permits < waiter.need {
+                break;
+            }
+            self.permits -= waiter.need;
+            waiter.granted = true;
+            wakers.push(waiter.waker.clone());
+        }
+        wakers
+    }
+}
+
+/// A counting semaphore which suspends the coroutine instead of blocking the thread.
+///
+/// The permits are handed to the waiters in FIFO order, a waiter asking for many
+/// permits is not overtaken by later ones, and the waiters can be woken from any thread.
+///
+/// # Examples
+/// ```
+/// use open_coroutine_core::sync::semaphore::Semaphore;
+///
+/// let semaphore = Semaphore::new(2);
+/// let permit = semaphore.acquire();
+/// assert_eq!(1, semaphore.available_permits());
+/// assert!(semaphore.try_acquire_many(2).is_none());
+/// drop(permit);
+/// assert!(semaphore.try_acquire_many(2).is_some());
+/// ```
+#[repr(C)]
+#[derive(Debug)]
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the given number of permits.
+    #[must_use]
+    pub const fn new(permits: usize) -> Self {
+        Semaphore {
+            state: Mutex::new(State {
+                permits,
+                total: permits,
+                seq: 0,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Returns the number of permits available now.
+    ///
+    /// # Panics
+    /// if the semaphore is poisoned.
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().permits
+    }
+
+    /// Adds `n` permits to the semaphore.
+    ///
+    /// # Panics
+    /// if the semaphore is poisoned.
+    pub fn add_permits(&self, n: usize) {
+        self.state.lock().unwrap().total += n;
+        self.release(n);
+    }
+
+    /// Give back `n` permits held, the total number of permits is unchanged.
+    fn release(&self, n: usize) {
+        let wakers = {
+            let mut state = self.state.lock().unwrap();
+            state.permits += n;
+            state.dispatch()
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Acquires a permit, the coroutine is suspended until it's available.
+    ///
+    /// # Panics
+    /// if the semaphore has no permit at all.
+    pub fn acquire(&self) -> Permit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Acquires `n` permits at once, the coroutine is suspended until they're available.
+    ///
+    /// # Panics
+    /// if `n` is greater than the total number of permits.
+    pub fn acquire_many(&self, n: usize) -> Permit<'_> {
+        let mut acquiring = Acquiring::new(self, n);
+        block_on(std::future::poll_fn(|cx| acquiring.poll(cx)))
+    }
+
+    /// Attempts to acquire a permit without suspending.
+    pub fn try_acquire(&self) -> Option<Permit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Attempts to acquire `n` permits without suspending, it fails if there
+    /// are waiters, even if the permits are enough.
+    ///
+    /// # Panics
+    /// if the semaphore is poisoned.
+    pub fn try_acquire_many(&self, n: usize) -> Option<Permit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if state.waiters.is_empty() && state.permits >= n {
+            state.permits -= n;
+            return Some(Permit {
+                semaphore: self,
+                permits: n,
+            });
+        }
+        None
+    }
+
+    /// Attempts to acquire a permit until the `timeout` expires.
+    ///
+    /// # Panics
+    /// if the semaphore has no permit at all.
+    pub fn acquire_timeout(&self, timeout: Duration) -> Option<Permit<'_>> {
+        self.acquire_many_timeout(1, timeout)
+    }
+
+    /// Attempts to acquire `n` permits until the `timeout` expires.
+    ///
+    /// # Panics
+    /// if `n` is greater than the total number of permits.
+    pub fn acquire_many_timeout(&self, n: usize, timeout: Duration) -> Option<Permit<'_>> {
+        let deadline = open_coroutine_timer::get_timeout_time(timeout);
+        let mut acquiring = Acquiring::new(self, n);
+        block_on_until(std::future::poll_fn(|cx| acquiring.poll(cx)), deadline)
+            // 超时前可能刚好拿到了许可
+            .or_else(|| acquiring.cancel())
+    }
+}
+
+/// A waiter of `Semaphore`, if it's dropped before acquiring, such as timeout or the
+/// coroutine is cancelled, it's removed from the waiters, the permits granted to it are
+/// released, and the waiters behind it are dispatched again.
+#[repr(C)]
+#[derive(Debug)]
+struct Acquiring<'s> {
+    semaphore: &'s Semaphore,
+    need: usize,
+    //None表示未入队，或者已经获取成功
+    key: Option<usize>,
+}
+
+impl<'s> Acquiring<'s> {
+    fn new(semaphore: &'s Semaphore, need: usize) -> Self {
+        Acquiring {
+            semaphore,
+            need,
+            key: None,
+        }
+    }
+
+    fn permit(&self) -> Permit<'s> {
+        Permit {
+            semaphore: self.semaphore,
+            permits: self.need,
+        }
+    }
+
+    fn poll(&mut self, cx: &Context<'_>) -> Poll<Permit<'s>> {
+        let mut state = self.semaphore.state.lock().unwrap();
+        if self.need > state.total {
+            //不能带着锁panic，否则会毒化锁
+            let total = state.total;
+            drop(state);
+            panic!("acquire {} permits but the total is {total}", self.need);
+        }
+        if let Some(k) = self.key {
+            if let Some(index) = state.waiters.iter().position(|w| w.id == k) {
+                if state.waiters[index].granted {
+                    _ = state.waiters.remove(index);
+                    self.key = None;
+                    return Poll::Ready(self.permit());
+                }
+                state.waiters[index].waker.clone_from(cx.waker());
+                return Poll::Pending;
+            }
+        }
+        if state.waiters.is_empty() && state.permits >= self.need {
+            state.permits -= self.need;
+            return Poll::Ready(self.permit());
+        }
+        state.seq += 1;
+        let id = state.seq;
+        self.key = Some(id);
+        state.waiters.push_back(Waiter {
+            id,
+            need: self.need,
+            granted: false,
+            waker: cx.waker().clone(),
+        });
+        Poll::Pending
+    }
+
+    /// Give up waiting, returns the permit if it has been granted.
+    fn cancel(&mut self) -> Option<Permit<'s>> {
+        let k = self.key.take()?;
+        let wakers = {
+            let mut state = self.semaphore.state.lock().unwrap();
+            let index = state.waiters.iter().position(|w| w.id == k)?;
+            if state.waiters.remove(index)?.granted {
+                return Some(self.permit());
+            }
+            // 放弃的等待者可能挡住了后面的
+            state.dispatch()
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+        None
+    }
+}
+
+impl Drop for Acquiring<'_> {
+    fn drop(&mut self) {
+        // 已移交的许可随Permit一起释放
+        drop(self.cancel());
+    }
+}
+
+/// The permits acquired from a `Semaphore`, they're released when it's dropped.
+#[must_use = "if unused the permits will immediately be released"]
+#[repr(C)]
+#[derive(Debug)]
+pub struct Permit<'s> {
+    semaphore: &'s Semaphore,
+    permits: usize,
+}
+
+impl Permit<'_> {
+    /// Returns the number of permits held.
+    #[must_use]
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Drops the permit without releasing the permits, which reduces the
+    /// total number of permits of the semaphore.
+    ///
+    /// # Panics
+    /// if the semaphore is poisoned.
+    pub fn forget(mut self) {
+        let n = std::mem::take(&mut self.permits);
+        let wakers: Vec<Waker> = {
+            let mut state = self.semaphore.state.lock().unwrap();
+            state.total -= n;
+            // 需要的比总数还多的等待者永远拿不到，唤醒它们以便panic
+            let total = state.total;
+            state
+                .waiters
+                .iter()
+                .filter(|w| w.need > total)
+                .map(|w| w.waker.clone())
+                .collect()
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.release(self.permits);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coroutine::suspender::SimpleSuspender;
+    use crate::scheduler::SchedulerImpl;
+    use crate::sync::tests::{schedule, submit};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_threads() {
+        let semaphore = Arc::new(Semaphore::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let semaphore = semaphore.clone();
+                let running = running.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        let _permit = semaphore.acquire();
+                        assert!(running.fetch_add(1, Ordering::AcqRel) < 2);
+                        std::thread::yield_now();
+                        _ = running.fetch_sub(1, Ordering::AcqRel);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(2, semaphore.available_permits());
+    }
+
+    #[test]
+    fn test_fifo() -> std::io::Result<()> {
+        let semaphore = Arc::new(Semaphore::new(3));
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let mut scheduler = SchedulerImpl::default();
+        for (name, n) in [("a", 2), ("b", 2), ("c", 1)] {
+            let semaphore = semaphore.clone();
+            let executed = executed.clone();
+            submit(&scheduler, move |suspender, ()| {
+                let permit = semaphore.acquire_many(n);
+                assert_eq!(n, permit.permits());
+                // "c" can't overtake "b" with the remaining permit
+                suspender.suspend();
+                executed.lock().unwrap().push(name);
+            })?;
+        }
+        schedule(&mut scheduler)?;
+        assert_eq!(vec!["a", "b", "c"], *executed.lock().unwrap());
+        assert_eq!(3, semaphore.available_permits());
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout() {
+        let semaphore = Semaphore::new(2);
+        let permit = semaphore.acquire();
+        assert!(semaphore
+            .acquire_many_timeout(2, Duration::from_millis(10))
+            .is_none());
+        // the expired waiter doesn't block the others
+        let other = semaphore.acquire_timeout(Duration::from_millis(10));
+        assert!(other.is_some());
+        drop(other);
+        drop(permit);
+        let permit = semaphore.try_acquire_many(2).expect("acquire failed");
+        permit.forget();
+        assert_eq!(0, semaphore.available_permits());
+        semaphore.add_permits(1);
+        assert!(semaphore.try_acquire().is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "acquire 3 permits but the total is 2")]
+    fn test_acquire_too_many() {
+        let semaphore = Semaphore::new(3);
+        semaphore.try_acquire().expect("acquire failed").forget();
+        drop(semaphore.acquire_many(3));
+    }
+
+    #[test]
+    fn test_cancel() -> std::io::Result<()> {
+        use crate::common::Current;
+        use crate::coroutine::Coroutine;
+        use crate::scheduler::{SchedulableCoroutine, Scheduler};
+
+        let semaphore = Arc::new(Semaphore::new(1));
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let cancelled = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = SchedulerImpl::default();
+        let (s, e, c) = (semaphore.clone(), executed.clone(), cancelled.clone());
+        submit(&scheduler, move |suspender, ()| {
+            let _permit = s.acquire();
+            // let the others wait for the permit
+            suspender.suspend();
+            // the first waiter is cancelled after the permit is granted to it
+            if let Some(scheduler) = SchedulerImpl::current() {
+                scheduler.try_cancel(c.load(Ordering::Acquire));
+            }
+            e.lock().unwrap().push("a");
+        })?;
+        let (s, e, c) = (semaphore.clone(), executed.clone(), cancelled.clone());
+        submit(&scheduler, move |_, ()| {
+            if let Some(coroutine) = SchedulableCoroutine::current() {
+                c.store(coroutine.id(), Ordering::Release);
+            }
+            let _permit = s.acquire();
+            e.lock().unwrap().push("b");
+        })?;
+        let (s, e) = (semaphore.clone(), executed.clone());
+        submit(&scheduler, move |_, ()| {
+            let _permit = s.acquire();
+            e.lock().unwrap().push("c");
+        })?;
+        schedule(&mut scheduler)?;
+        // the permit granted to the cancelled waiter is passed to the next one
+        assert_eq!(vec!["a", "c"], *executed.lock().unwrap());
+        assert_eq!(1, semaphore.available_permits());
+        Ok(())
+    }
+}
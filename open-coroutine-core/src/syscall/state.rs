@@ -26,6 +26,7 @@ macro_rules! change_state {
             coroutine
                 .syscall((), Syscall::$syscall, SyscallState::Computing)
                 .expect("change to syscall state failed !");
+            crate::trace::syscall(coroutine, Syscall::$syscall, SyscallState::Computing);
         }
         let r = $self.inner.$syscall($($arg, )*);
         if let Some(coroutine) = SchedulableCoroutine::current() {
@@ -33,6 +34,7 @@ macro_rules! change_state {
             coroutine
                 .syscall((), Syscall::$syscall, SyscallState::Finished)
                 .expect("change to syscall Finished state failed !");
+            crate::trace::syscall(coroutine, Syscall::$syscall, SyscallState::Finished);
             coroutine
                 .syscall_resume()
                 .expect("change to running state failed !");
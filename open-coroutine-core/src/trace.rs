@@ -0,0 +1,476 @@
+use crate::common::Named;
+use crate::constants::{Syscall, SyscallState};
+use crate::coroutine::error::CoroutineError;
+use crate::coroutine::Coroutine;
+use crate::scheduler::listener::Listener;
+use crate::scheduler::SchedulableCoroutine;
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+static ENABLE: AtomicBool = AtomicBool::new(false);
+
+//环形缓冲区的容量，满了之后丢弃最早的事件
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CAPACITY);
+
+/// The default capacity of the recorder.
+pub const DEFAULT_CAPACITY: usize = 1 << 20;
+
+static NEXT_TID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static TID: u64 = {
+        let tid = NEXT_TID.fetch_add(1, Ordering::Relaxed);
+        let thread = std::thread::current();
+        let name = thread.name().map_or_else(|| format!("thread-{tid}"), String::from);
+        _ = threads().insert(tid, name);
+        tid
+    };
+}
+
+fn threads() -> &'static DashMap<u64, String> {
+    static THREADS: OnceLock<DashMap<u64, String>> = OnceLock::new();
+    THREADS.get_or_init(DashMap::new)
+}
+
+fn events() -> &'static Mutex<VecDeque<Event>> {
+    static EVENTS: Mutex<VecDeque<Event>> = Mutex::new(VecDeque::new());
+    &EVENTS
+}
+
+/// The id of current thread in the trace.
+pub(crate) fn thread_id() -> u64 {
+    TID.with(|tid| *tid)
+}
+
+/// Whether the recorder is running.
+#[must_use]
+pub fn is_recording() -> bool {
+    RECORDING.load(Ordering::Relaxed)
+}
+
+/// Whether add a `TraceListener` to the new pools.
+#[must_use]
+pub fn get_enable() -> bool {
+    ENABLE.load(Ordering::Relaxed)
+}
+
+/// Enable or disable adding a `TraceListener` to the pools created after,
+/// it's disabled by default, because the listener is called on every switch.
+pub fn set_enable(enable: bool) {
+    ENABLE.store(enable, Ordering::Relaxed);
+}
+
+/// The max number of the events kept by the recorder.
+#[must_use]
+pub fn get_capacity() -> usize {
+    CAPACITY.load(Ordering::Relaxed)
+}
+
+/// Set the max number of the events kept by the recorder, the earliest
+/// events are discarded when it's full.
+///
+/// # Panics
+/// if the recorder is poisoned.
+pub fn set_capacity(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+    let mut events = events().lock().unwrap();
+    while events.len() > capacity {
+        _ = events.pop_front();
+    }
+}
+
+/// Start recording, the events recorded before are discarded.
+///
+/// The schedulers with a `TraceListener` are recorded, the pools and the event
+/// loops have one after `set_enable(true)`.
+///
+/// # Panics
+/// if the recorder is poisoned.
+pub fn start() {
+    events().lock().unwrap().clear();
+    RECORDING.store(true, Ordering::Release);
+}
+
+/// Stop recording and take the recorded events.
+///
+/// # Panics
+/// if the recorder is poisoned.
+#[must_use]
+pub fn stop() -> Trace {
+    RECORDING.store(false, Ordering::Release);
+    let mut events = Vec::from(std::mem::take(&mut *events().lock().unwrap()));
+    events.sort_by_key(|e| e.ts);
+    Trace { events }
+}
+
+/// A trace event, the timestamps are in ns.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Event {
+    /// The name of the event.
+    pub name: String,
+    /// The category of the event, one of `coroutine`, `syscall`, `preempt` and `task`.
+    pub cat: &'static str,
+    /// The phase of the event, `X` for the slices, `i` for the instant events,
+    /// `b` and `e` for the beginning and the end of the syscall waits.
+    pub ph: char,
+    /// The timestamp in ns.
+    pub ts: u64,
+    /// The duration in ns of the `X` events.
+    pub dur: Option<u64>,
+    /// The thread of the event.
+    pub tid: u64,
+    /// The id of the async events, which is the id of the coroutine.
+    pub id: Option<usize>,
+    /// The arguments of the event.
+    pub args: Vec<(&'static str, String)>,
+}
+
+fn record(event: impl FnOnce() -> Event) {
+    //未开始记录时不构造事件
+    if is_recording() {
+        let event = event();
+        let capacity = get_capacity();
+        let mut events = events().lock().unwrap();
+        if events.len() >= capacity {
+            if capacity == 0 {
+                return;
+            }
+            _ = events.pop_front();
+        }
+        events.push_back(event);
+    }
+}
+
+/// Record a slice on current thread, which begins at `begin`.
+pub(crate) fn slice(cat: &'static str, name: &str, begin: u64, args: Vec<(&'static str, String)>) {
+    record(|| Event {
+        name: String::from(name),
+        cat,
+        ph: 'X',
+        ts: begin,
+        dur: Some(open_coroutine_timer::now().saturating_sub(begin)),
+        tid: thread_id(),
+        id: None,
+        args,
+    });
+}
+
+/// Record an instant event on current thread.
+pub(crate) fn instant(cat: &'static str, name: &str, args: Vec<(&'static str, String)>) {
+    record(|| Event {
+        name: String::from(name),
+        cat,
+        ph: 'i',
+        ts: open_coroutine_timer::now(),
+        dur: None,
+        tid: thread_id(),
+        id: None,
+        args,
+    });
+}
+
+/// Record a preemption signal sent to the coroutine running in thread `tid`.
+#[cfg(all(unix, feature = "preemptive-schedule"))]
+pub(crate) fn preempt(tid: u64, coroutine: &SchedulableCoroutine) {
+    record(|| Event {
+        name: String::from("preempt"),
+        cat: "preempt",
+        ph: 'i',
+        ts: open_coroutine_timer::now(),
+        dur: None,
+        tid,
+        id: None,
+        args: vec![("coroutine", String::from(coroutine.get_name()))],
+    });
+}
+
+/// Record the syscall state transitions of current coroutine, the syscall
+/// waits are async events, because the coroutine may be resumed in another thread.
+#[cfg(all(unix, feature = "net"))]
+pub(crate) fn syscall(coroutine: &SchedulableCoroutine, syscall: Syscall, state: SyscallState) {
+    let ph = match state {
+        SyscallState::Computing => 'b',
+        SyscallState::Finished => 'e',
+        SyscallState::Suspend(_) | SyscallState::Calling(_) | SyscallState::Timeout => return,
+    };
+    record(|| Event {
+        name: syscall.to_string(),
+        cat: "syscall",
+        ph,
+        ts: open_coroutine_timer::now(),
+        dur: None,
+        tid: thread_id(),
+        id: Some(coroutine.id()),
+        args: vec![("coroutine", String::from(coroutine.get_name()))],
+    });
+}
+
+/// The recorded events.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Trace {
+    /// The events sorted by the timestamp.
+    pub events: Vec<Event>,
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => _ = write!(escaped, "\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn micros(ns: u64) -> String {
+    format!("{}.{:03}", ns / 1000, ns % 1000)
+}
+
+impl Trace {
+    /// Render the events in the Chrome Trace Event Format, which can be
+    /// loaded by Perfetto or `chrome://tracing`.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let pid = std::process::id();
+        let mut lines: Vec<String> = threads()
+            .iter()
+            .map(|r| {
+                format!(
+                    "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":{pid},\"tid\":{},\"args\":{{\"name\":\"{}\"}}}}",
+                    r.key(),
+                    escape(r.value())
+                )
+            })
+            .collect();
+        lines.sort();
+        for event in &self.events {
+            let mut line = format!(
+                "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"{}\",\"ts\":{},\"pid\":{pid},\"tid\":{}",
+                escape(&event.name),
+                event.cat,
+                event.ph,
+                micros(event.ts),
+                event.tid
+            );
+            if let Some(dur) = event.dur {
+                _ = write!(line, ",\"dur\":{}", micros(dur));
+            }
+            if let Some(id) = event.id {
+                _ = write!(line, ",\"id\":{id}");
+            }
+            if 'i' == event.ph {
+                line.push_str(",\"s\":\"t\"");
+            }
+            let args: Vec<String> = event
+                .args
+                .iter()
+                .map(|(k, v)| format!("\"{k}\":\"{}\"", escape(v)))
+                .collect();
+            _ = write!(line, ",\"args\":{{{}}}}}", args.join(","));
+            lines.push(line);
+        }
+        format!(
+            "{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ns\"}}\n",
+            lines.join(",\n")
+        )
+    }
+
+    /// Write the events in the Chrome Trace Event Format.
+    ///
+    /// # Errors
+    /// if write failed.
+    pub fn write_to(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writer.write_all(self.to_json().as_bytes())
+    }
+}
+
+/// A `Listener` which records the run slices, suspensions and the ends
+/// of the coroutines, it does nothing unless the recorder is running.
+/// The pools add it to their workers when `set_enable(true)`.
+///
+/// # Examples
+/// ```
+/// use open_coroutine_core::scheduler::{Scheduler, SchedulerImpl};
+/// use open_coroutine_core::trace::TraceListener;
+///
+/// let mut scheduler = SchedulerImpl::default();
+/// scheduler.add_listener(TraceListener::default());
+/// open_coroutine_core::trace::start();
+/// _ = scheduler.submit(|_, ()| {}, None);
+/// scheduler.try_schedule().unwrap();
+/// let json = open_coroutine_core::trace::stop().to_json();
+/// assert!(json.starts_with("{\"traceEvents\":["));
+/// ```
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct TraceListener {
+    //正在运行的协程及其开始运行的时间
+    running: DashMap<usize, u64>,
+}
+
+impl TraceListener {
+    fn end_slice(&self, coroutine: &SchedulableCoroutine) {
+        if !is_recording() {
+            return;
+        }
+        if let Some((_, begin)) = self.running.remove(&coroutine.id()) {
+            slice(
+                "coroutine",
+                coroutine.get_name(),
+                begin,
+                vec![("co_id", coroutine.id().to_string())],
+            );
+        }
+    }
+
+    fn end(&self, coroutine: &SchedulableCoroutine, name: &str) {
+        self.end_slice(coroutine);
+        instant(
+            "coroutine",
+            name,
+            vec![("coroutine", String::from(coroutine.get_name()))],
+        );
+    }
+}
+
+impl Listener for TraceListener {
+    fn on_resume(&self, _: u64, coroutine: &SchedulableCoroutine) {
+        if is_recording() {
+            _ = self
+                .running
+                .insert(coroutine.id(), open_coroutine_timer::now());
+        }
+    }
+
+    fn on_suspend(&self, _: u64, coroutine: &SchedulableCoroutine) {
+        self.end(coroutine, "suspend");
+    }
+
+    fn on_syscall(&self, _: u64, coroutine: &SchedulableCoroutine, _: Syscall, _: SyscallState) {
+        self.end_slice(coroutine);
+    }
+
+    fn on_complete(&self, _: u64, coroutine: &SchedulableCoroutine, _: Option<usize>) {
+        self.end(coroutine, "complete");
+    }
+
+    fn on_error(&self, _: u64, coroutine: &SchedulableCoroutine, _: &CoroutineError) {
+        self.end(coroutine, "error");
+    }
+
+    fn on_cancel(&self, _: u64, coroutine: &SchedulableCoroutine) {
+        self.end(coroutine, "cancel");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(all(unix, feature = "net"))]
+    use crate::common::Current;
+    use crate::constants::Priority;
+    use crate::coroutine::suspender::SimpleSuspender;
+    use crate::scheduler::{Scheduler, SchedulerImpl};
+
+    //记录器是全局的，测试之间不能并发
+    static RECORDER_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_trace() -> std::io::Result<()> {
+        let _recorder = RECORDER_LOCK.lock().unwrap();
+        let mut scheduler = SchedulerImpl::default();
+        scheduler.add_listener(TraceListener::default());
+        start();
+        // high priority coroutines can't be stolen by other tests
+        scheduler.submit_with_priority(
+            |suspender, ()| {
+                #[cfg(all(unix, feature = "net"))]
+                if let Some(coroutine) = SchedulableCoroutine::current() {
+                    syscall(coroutine, Syscall::nanosleep, SyscallState::Computing);
+                    syscall(coroutine, Syscall::nanosleep, SyscallState::Finished);
+                }
+                suspender.suspend();
+            },
+            None,
+            Priority::High,
+        )?;
+        scheduler.try_schedule()?;
+        let trace = stop();
+        let tid = thread_id();
+        let events: Vec<(&str, char)> = trace
+            .events
+            .iter()
+            .filter(|e| e.tid == tid && e.cat != "task")
+            .map(|e| (e.cat, e.ph))
+            .collect();
+        let mut expected = vec![("coroutine", 'X')];
+        #[cfg(all(unix, feature = "net"))]
+        expected.extend([("syscall", 'b'), ("syscall", 'e')]);
+        expected.extend([("coroutine", 'i'), ("coroutine", 'X'), ("coroutine", 'i')]);
+        assert_eq!(expected, events);
+        let json = trace.to_json();
+        #[cfg(all(unix, feature = "net"))]
+        assert!(json.contains("\"name\":\"nanosleep\",\"cat\":\"syscall\",\"ph\":\"b\""));
+        assert!(json.contains("\"name\":\"thread_name\",\"ph\":\"M\""));
+        assert!(!is_recording());
+        Ok(())
+    }
+
+    #[test]
+    fn test_capacity() {
+        let _recorder = RECORDER_LOCK.lock().unwrap();
+        set_capacity(2);
+        start();
+        for name in ["a", "b", "c"] {
+            instant("test", name, Vec::new());
+        }
+        let trace = stop();
+        set_capacity(DEFAULT_CAPACITY);
+        let names: Vec<&str> = trace
+            .events
+            .iter()
+            .filter(|e| e.cat == "test")
+            .map(|e| e.name.as_str())
+            .collect();
+        // the earliest event is discarded
+        assert_eq!(vec!["b", "c"], names);
+    }
+
+    #[test]
+    fn test_not_recording() -> std::io::Result<()> {
+        let _recorder = RECORDER_LOCK.lock().unwrap();
+        let listener = TraceListener::default();
+        let coroutine = SchedulableCoroutine::new(
+            String::from("test_not_recording"),
+            |_, ()| {},
+            crate::constants::DEFAULT_STACK_SIZE,
+        )?;
+        listener.on_resume(0, &coroutine);
+        assert!(listener.running.is_empty());
+        start();
+        listener.on_resume(0, &coroutine);
+        _ = stop();
+        // the slice is left unfinished after the recorder stopped
+        listener.on_suspend(0, &coroutine);
+        assert_eq!(1, listener.running.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!("a\\\"b\\\\c\\n\\u0001", escape("a\"b\\c\n\u{1}"));
+        assert_eq!("1.002", micros(1002));
+    }
+}
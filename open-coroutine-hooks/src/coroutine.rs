@@ -1,5 +1,7 @@
+use open_coroutine_core::coroutine::error::CoroutineError;
 use open_coroutine_core::net::core::EventLoops;
 use open_coroutine_core::net::event_loop::JoinHandleImpl;
+pub use open_coroutine_core::net::event_loop::{JoinError, COROUTINE_FAILED, JOIN_FAILED};
 use open_coroutine_core::pool::join::JoinHandle;
 use std::ffi::{c_long, c_void};
 use std::time::Duration;
@@ -22,29 +24,63 @@ pub extern "C" fn coroutine_crate(
 ///等待协程完成
 #[no_mangle]
 pub extern "C" fn coroutine_join(handle: JoinHandleImpl<'static>) -> c_long {
-    match handle.join() {
-        Ok(ptr) => match ptr {
-            Ok(ptr) => match ptr {
-                Some(ptr) => ptr as *mut c_void as c_long,
-                None => 0,
-            },
-            Err(_) => -1,
-        },
-        Err(_) => -1,
-    }
+    to_c_long(handle.join(), std::ptr::null_mut())
 }
 
 ///等待协程完成
 #[no_mangle]
 pub extern "C" fn coroutine_timeout_join(handle: &JoinHandleImpl<'static>, ns_time: u64) -> c_long {
-    match handle.timeout_join(Duration::from_nanos(ns_time)) {
-        Ok(ptr) => match ptr {
-            Ok(ptr) => match ptr {
-                Some(ptr) => ptr as *mut c_void as c_long,
-                None => 0,
-            },
-            Err(_) => -1,
-        },
-        Err(_) => -1,
+    to_c_long(
+        handle.timeout_join(Duration::from_nanos(ns_time)),
+        std::ptr::null_mut(),
+    )
+}
+
+///等待协程完成，失败时填充错误
+#[no_mangle]
+pub extern "C" fn coroutine_join_err(
+    handle: JoinHandleImpl<'static>,
+    error: *mut JoinError,
+) -> c_long {
+    to_c_long(handle.join(), error)
+}
+
+///等待协程完成，失败时填充错误
+#[no_mangle]
+pub extern "C" fn coroutine_timeout_join_err(
+    handle: &JoinHandleImpl<'static>,
+    ns_time: u64,
+    error: *mut JoinError,
+) -> c_long {
+    to_c_long(handle.timeout_join(Duration::from_nanos(ns_time)), error)
+}
+
+///释放协程的错误
+#[no_mangle]
+pub extern "C" fn coroutine_error_free(error: *mut c_void) {
+    if !error.is_null() {
+        drop(unsafe { Box::from_raw(error.cast::<CoroutineError>()) });
+    }
+}
+
+fn to_c_long(
+    result: std::io::Result<Result<Option<usize>, CoroutineError>>,
+    error: *mut JoinError,
+) -> c_long {
+    let (code, e) = match result {
+        Ok(Ok(Some(ptr))) => return ptr as *mut c_void as c_long,
+        Ok(Ok(None)) => return 0,
+        Ok(Err(e)) => (
+            COROUTINE_FAILED,
+            Box::into_raw(Box::new(e)).cast::<c_void>(),
+        ),
+        Err(_) => (JOIN_FAILED, std::ptr::null_mut()),
+    };
+    if let Some(error) = unsafe { error.as_mut() } {
+        error.code = code;
+        error.error = e;
+    } else {
+        coroutine_error_free(e);
     }
+    -1
 }
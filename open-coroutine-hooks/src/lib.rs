@@ -53,6 +53,14 @@ pub extern "C" fn init_config(config: Config) {
     _ = Config::get_instance()
         .set_event_loop_size(config.get_event_loop_size())
         .set_stack_size(config.get_stack_size())
+        .set_stack_cache_size(config.get_stack_cache_size())
+        .set_stack_cache_memory(config.get_stack_cache_memory())
+        .set_stack_measure(config.get_stack_measure())
+        .set_dump_record(config.get_dump_record())
+        .set_dump_backtrace(config.get_dump_backtrace())
+        .set_dump_on_sigquit(config.get_dump_on_sigquit())
+        .set_metrics(config.get_metrics())
+        .set_trace(config.get_trace())
         .set_min_size(config.get_min_size())
         .set_max_size(config.get_max_size())
         .set_keep_alive_time(config.get_keep_alive_time());
@@ -1,14 +1,17 @@
-use open_coroutine_core::net::event_loop::EventLoopImpl;
+use open_coroutine_core::net::event_loop::{EventLoopImpl, JoinError};
 use std::cmp::Ordering;
 use std::ffi::c_char;
-use std::io::{Error, ErrorKind};
 use std::time::Duration;
 
 #[allow(improper_ctypes)]
 extern "C" {
-    fn coroutine_join(handle: JoinHandle) -> libc::c_long;
+    fn coroutine_join_err(handle: JoinHandle, error: *mut JoinError) -> libc::c_long;
 
-    fn coroutine_timeout_join(handle: &JoinHandle, ns_time: u64) -> libc::c_long;
+    fn coroutine_timeout_join_err(
+        handle: &JoinHandle,
+        ns_time: u64,
+        error: *mut JoinError,
+    ) -> libc::c_long;
 }
 
 #[repr(C)]
@@ -17,13 +20,14 @@ pub struct JoinHandle(*const EventLoopImpl<'static>, *const c_char);
 
 impl JoinHandle {
     /// # Errors
-    /// if join failed.
+    /// if join failed, if the coroutine failed, the inner error is a `CoroutineError`.
     #[allow(clippy::cast_possible_truncation)]
     pub fn timeout_join<R>(&self, dur: Duration) -> std::io::Result<Option<R>> {
         unsafe {
-            let ptr = coroutine_timeout_join(self, dur.as_nanos() as u64);
+            let mut error = JoinError::default();
+            let ptr = coroutine_timeout_join_err(self, dur.as_nanos() as u64, &mut error);
             match ptr.cmp(&0) {
-                Ordering::Less => Err(Error::new(ErrorKind::Other, "timeout join failed")),
+                Ordering::Less => Err(error.into_error("timeout join failed")),
                 Ordering::Equal => Ok(None),
                 Ordering::Greater => Ok(Some(std::ptr::read_unaligned(ptr as *mut R))),
             }
@@ -31,12 +35,13 @@ impl JoinHandle {
     }
 
     /// # Errors
-    /// if join failed.
+    /// if join failed, if the coroutine failed, the inner error is a `CoroutineError`.
     pub fn join<R>(self) -> std::io::Result<Option<R>> {
         unsafe {
-            let ptr = coroutine_join(self);
+            let mut error = JoinError::default();
+            let ptr = coroutine_join_err(self, &mut error);
             match ptr.cmp(&0) {
-                Ordering::Less => Err(Error::new(ErrorKind::Other, "join failed")),
+                Ordering::Less => Err(error.into_error("join failed")),
                 Ordering::Equal => Ok(None),
                 Ordering::Greater => Ok(Some(std::ptr::read_unaligned(ptr as *mut R))),
             }